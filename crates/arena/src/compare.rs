@@ -0,0 +1,212 @@
+//! Compares run_tests result files (test_cases/optimizer_results/vNN_payloads.jsonl) against each other.
+//!
+//! The first file is the baseline, every other file is compared against it on the (test case, metric type, trial)s they both have.
+//! Higher metric is better, anything that's worse on average than the baseline by more than SIMULATED_ANNEALING_DIFF_TOL is flagged.
+use hf_core::constants::SIMULATED_ANNEALING_DIFF_TOL;
+use hf_core::helpers::my_pct_diff;
+use serde_json::Value;
+use statrs::distribution::{ContinuousCDF, StudentsT};
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::{BufRead, BufReader};
+use std::path::Path;
+
+#[derive(Debug, Clone, Copy)]
+struct Trial {
+    best: f64,
+    wall_time: f64,
+}
+
+type Key = (String, String); // (test case, metric type)
+
+struct ResultFile {
+    version: String,
+    runs: BTreeMap<Key, BTreeMap<i64, Vec<Trial>>>,
+}
+
+fn load(path: &Path) -> ResultFile {
+    let file = File::open(path).unwrap_or_else(|e| panic!("Failed to open {:?}: {}", path, e));
+    let mut out = ResultFile {
+        version: path.file_stem().unwrap().to_string_lossy().to_string(),
+        runs: BTreeMap::new(),
+    };
+    for line in BufReader::new(file).lines() {
+        let line = line.unwrap();
+        if line.trim().is_empty() {
+            continue;
+        }
+        let value: Value = serde_json::from_str(&line)
+            .unwrap_or_else(|e| panic!("Failed to parse a line of {:?}: {}", path, e));
+        if let Some(version) = value.get("version").and_then(|x| x.as_str()) {
+            out.version = version.to_string(); // header
+            continue;
+        }
+        let (Some(test_case), Some(metric_type), Some(trial_num), Some(best), Some(wall_time)) = (
+            value["test_case"].as_str(),
+            value["metric_type"].as_str(),
+            value["trial_num"].as_i64(),
+            value["best"].as_f64(),
+            value["wall_time"].as_f64(),
+        ) else {
+            continue; // older files are missing some of these, nothing to compare then
+        };
+        if metric_type.starts_with("MC_") {
+            continue; // monte carlo verification rows, not optimizer results
+        }
+        out.runs
+            .entry((test_case.to_string(), metric_type.to_string()))
+            .or_default()
+            .entry(trial_num)
+            .or_default()
+            .push(Trial { best, wall_time });
+    }
+    out
+}
+
+fn mean_var(x: &[f64]) -> (f64, f64) {
+    let n = x.len() as f64;
+    let mean = x.iter().sum::<f64>() / n;
+    let var = if x.len() > 1 {
+        x.iter().map(|v| (v - mean).powi(2)).sum::<f64>() / (n - 1.0)
+    } else {
+        f64::NAN
+    };
+    (mean, var)
+}
+
+/// Two sided p value of Welch's t-test, NAN if either side has fewer than 2 samples
+fn welch_p_value(a: &[f64], b: &[f64]) -> f64 {
+    if a.len() < 2 || b.len() < 2 {
+        return f64::NAN;
+    }
+    let (mean_a, var_a) = mean_var(a);
+    let (mean_b, var_b) = mean_var(b);
+    let (se_a, se_b) = (var_a / a.len() as f64, var_b / b.len() as f64);
+    if se_a + se_b == 0.0 {
+        return if mean_a == mean_b { 1.0 } else { 0.0 };
+    }
+    let t = (mean_a - mean_b) / (se_a + se_b).sqrt();
+    let df = (se_a + se_b).powi(2)
+        / (se_a.powi(2) / (a.len() - 1) as f64 + se_b.powi(2) / (b.len() - 1) as f64);
+    let dist = StudentsT::new(0.0, 1.0, df).unwrap();
+    2.0 * (1.0 - dist.cdf(t.abs()))
+}
+
+#[derive(Debug)]
+struct Comparison {
+    test_case: String,
+    metric_type: String,
+    trials: usize,
+    mean_diff: f64, // candidate - baseline, positive is better
+    best_diff: f64,
+    rel_mean_diff: f64,
+    wall_time_ratio: f64, // candidate / baseline
+    p_value: f64,
+    regression: bool,
+}
+
+fn compare_one(
+    key: &Key,
+    base: &BTreeMap<i64, Vec<Trial>>,
+    cand: &BTreeMap<i64, Vec<Trial>>,
+) -> Option<Comparison> {
+    let shared: Vec<i64> = base
+        .keys()
+        .filter(|x| cand.contains_key(x))
+        .copied()
+        .collect();
+    if shared.is_empty() {
+        return None;
+    }
+    let collect = |runs: &BTreeMap<i64, Vec<Trial>>| -> Vec<Trial> {
+        shared
+            .iter()
+            .flat_map(|t| runs[t].iter().copied())
+            .collect()
+    };
+    let (base_trials, cand_trials) = (collect(base), collect(cand));
+    let base_best: Vec<f64> = base_trials.iter().map(|x| x.best).collect();
+    let cand_best: Vec<f64> = cand_trials.iter().map(|x| x.best).collect();
+    let (base_mean, _) = mean_var(&base_best);
+    let (cand_mean, _) = mean_var(&cand_best);
+    let max = |x: &[f64]| x.iter().copied().fold(f64::NEG_INFINITY, f64::max);
+    let wall_time = |x: &[Trial]| mean_var(&x.iter().map(|t| t.wall_time).collect::<Vec<f64>>()).0;
+
+    let rel_mean_diff = my_pct_diff(cand_mean, base_mean) * (cand_mean - base_mean).signum();
+    Some(Comparison {
+        test_case: key.0.clone(),
+        metric_type: key.1.clone(),
+        trials: shared.len(),
+        mean_diff: cand_mean - base_mean,
+        best_diff: max(&cand_best) - max(&base_best),
+        rel_mean_diff,
+        wall_time_ratio: wall_time(&cand_trials) / wall_time(&base_trials),
+        p_value: welch_p_value(&cand_best, &base_best),
+        regression: rel_mean_diff < -SIMULATED_ANNEALING_DIFF_TOL,
+    })
+}
+
+/// Prints the comparison of every file against the first, returns whether any regression was found
+pub fn compare_files(paths: &[String]) -> bool {
+    assert!(
+        paths.len() >= 2,
+        "Need at least two result files to compare"
+    );
+    let files: Vec<ResultFile> = paths.iter().map(|x| load(Path::new(x))).collect();
+    let base = &files[0];
+    let mut any_regression = false;
+
+    for cand in files.iter().skip(1) {
+        println!("{} vs {} (baseline)", cand.version, base.version);
+        println!(
+            "{:<24} {:<6} {:>6} {:>14} {:>14} {:>9} {:>9} {:>8}",
+            "test case", "metric", "trials", "mean diff", "best diff", "rel %", "time x", "p"
+        );
+        let mut count = 0;
+        for (key, base_runs) in base.runs.iter() {
+            let Some(cand_runs) = cand.runs.get(key) else {
+                continue;
+            };
+            let Some(c) = compare_one(key, base_runs, cand_runs) else {
+                continue;
+            };
+            count += 1;
+            any_regression |= c.regression;
+            println!(
+                "{:<24} {:<6} {:>6} {:>14.2} {:>14.2} {:>9.4} {:>9.3} {:>8.4}{}",
+                c.test_case,
+                c.metric_type,
+                c.trials,
+                c.mean_diff,
+                c.best_diff,
+                c.rel_mean_diff * 100.0,
+                c.wall_time_ratio,
+                c.p_value,
+                if c.regression { "  REGRESSION" } else { "" }
+            );
+        }
+        if count == 0 {
+            println!("No test cases/trials in common");
+        }
+        println!();
+    }
+    any_regression
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn welch_agrees_with_known_values() {
+        // identical samples can't be told apart, clearly separated ones can
+        assert!((welch_p_value(&[1.0, 2.0, 3.0], &[1.0, 2.0, 3.0]) - 1.0).abs() < 1e-12);
+        assert!(welch_p_value(&[1.0, 1.1, 0.9, 1.05], &[5.0, 5.1, 4.9, 5.05]) < 1e-4);
+        // t = -2.2514, df = 5.52 worked out by hand
+        assert!(
+            (welch_p_value(&[1.0, 2.0, 3.0, 4.0], &[2.0, 4.0, 6.0, 8.0, 10.0]) - 0.0691).abs()
+                < 1e-3
+        );
+        assert!(welch_p_value(&[1.0], &[2.0, 3.0]).is_nan());
+    }
+}
@@ -1,13 +1,60 @@
+mod compare;
+
+use compare::compare_files;
 use hf_core::verification::run_tests::run_tests;
+use hf_core::verification::sweep::run_sweep;
+use hf_core::verification::tune::tune;
 use std::env;
+use std::path::Path;
+
+fn usage(program: &str) -> ! {
+    eprintln!(
+        "Usage: {} <path_to_payloads> | compare <baseline_results.jsonl> <other_results.jsonl>... | tune <path_to_payloads> [num_configs] [seed] | sweep <payload.json> <grid.json> [out_prefix]",
+        program
+    );
+    std::process::exit(1);
+}
+
 fn main() {
     let args: Vec<String> = env::args().collect();
-    let payload_path_string = if args.len() > 1 {
-        args[1].clone()
-    } else {
-        eprintln!("Usage: {} <path_to_payloads>", args[0]);
-        std::process::exit(1);
-    };
-
-    run_tests(payload_path_string, false);
+    match args.get(1).map(|x| x.as_str()) {
+        Some("compare") => {
+            if args.len() < 4 {
+                usage(&args[0]);
+            }
+            if compare_files(&args[2..]) {
+                std::process::exit(1); // so scripts can tell
+            }
+        }
+        Some("tune") => {
+            if args.len() < 3 {
+                usage(&args[0]);
+            }
+            let num_configs: usize = args
+                .get(3)
+                .map_or(Ok(27), |x| x.parse())
+                .unwrap_or_else(|_| usage(&args[0]));
+            let seed: u64 = args
+                .get(4)
+                .map_or(Ok(0), |x| x.parse())
+                .unwrap_or_else(|_| usage(&args[0]));
+            let (params, score) = tune(Path::new(&args[2]), num_configs, seed);
+            println!(
+                "Best: {:+.5}% +-{:.5}% vs default (95%, n = {})",
+                score.mean * 100.0,
+                score.half_width * 100.0,
+                score.samples
+            );
+            println!("{}", serde_json::to_string_pretty(&params).unwrap());
+        }
+        Some("sweep") => {
+            if args.len() < 4 {
+                usage(&args[0]);
+            }
+            let out_prefix: &str = args.get(4).map_or("sweep", |x| x.as_str());
+            run_sweep(Path::new(&args[2]), Path::new(&args[3]), out_prefix);
+        }
+        Some(payload_path) => run_tests(payload_path.to_string(), false),
+        None => usage(&args[0]),
+    }
 }
@@ -0,0 +1,45 @@
+//! Full evaluations of every bundled payload from scratch (no caches), the same work the website does per histogram/metric.
+//!
+//! Baselines work the same as the kernels bench: `-- --save-baseline <name>` then `-- --baseline <name>`.
+use criterion::{BatchSize, Criterion, criterion_group, criterion_main};
+use hf_core::helpers::test_cases_dir;
+use hf_core::payload::parse_to_payloads;
+use hf_core::performance::Performance;
+use hf_core::state_bundle::StateBundle;
+
+fn end_to_end(c: &mut Criterion) {
+    let mut group = c.benchmark_group("average_gold_metric");
+    group.sample_size(10);
+    let state_bundles: Vec<(String, StateBundle)> =
+        parse_to_payloads(&test_cases_dir().join("payloads"))
+            .into_iter()
+            .map(|(name, payload)| (name, StateBundle::init_from_payload(payload).unwrap()))
+            .collect();
+
+    for (name, state_bundle) in state_bundles.iter() {
+        group.bench_function(name, |b| {
+            b.iter_batched(
+                || state_bundle.clone(),
+                |mut x| x.optimizer_average_gold_metric(&mut Performance::new()),
+                BatchSize::LargeInput,
+            )
+        });
+    }
+    group.finish();
+
+    let mut group = c.benchmark_group("leftover_probs");
+    group.sample_size(10);
+    for (name, state_bundle) in state_bundles.iter() {
+        group.bench_function(name, |b| {
+            b.iter_batched(
+                || state_bundle.clone(),
+                |mut x| x.compute_leftover_probs(),
+                BatchSize::LargeInput,
+            )
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, end_to_end);
+criterion_main!(benches);
@@ -0,0 +1,118 @@
+//! Microbenchmarks of the evaluation hot path, on one material row of the bundled payloads.
+//!
+//! `cargo bench -p hf-core --bench kernels -- --save-baseline before`, change things, then
+//! `cargo bench -p hf-core --bench kernels -- --baseline before` to see the difference.
+use criterion::{BatchSize, Criterion, criterion_group, criterion_main};
+use hf_core::advanced_honing::compute::compute_adv_dist_wrapper;
+use hf_core::constants::FLOAT_TOL;
+use hf_core::helpers::test_cases_dir;
+use hf_core::payload::parse_to_payloads;
+use hf_core::performance::Performance;
+use hf_core::state_bundle::StateBundle;
+use std::hint::black_box;
+
+const NORMAL_CASE: &str = "three_+25";
+const SPECIAL_CASE: &str = "serca_1215";
+const ADV_CASE: &str = "1920_adv3040";
+const ROW: i64 = 0; // red, every upgrade uses it
+
+fn prepared(name: &str) -> StateBundle {
+    let (_, payload) = parse_to_payloads(&test_cases_dir().join("payloads"))
+        .into_iter()
+        .find(|(x, _)| x == name)
+        .unwrap_or_else(|| panic!("{} isn't in test_cases/payloads", name));
+    let mut state_bundle = StateBundle::init_from_payload(payload).unwrap();
+    state_bundle.update_prob_dist();
+    state_bundle.update_cost_dist();
+    state_bundle.compute_special_probs(false);
+    state_bundle
+}
+
+/// Same as what saddlepoint_approximation_wrapper works out before calling householder
+fn min_delta(state_bundle: &StateBundle) -> f64 {
+    state_bundle
+        .extract_all_support_with_meta(ROW)
+        .map(|x| x.gap_size)
+        .filter(|x| *x > FLOAT_TOL)
+        .fold(f64::INFINITY, f64::min)
+}
+
+fn kernels(c: &mut Criterion) {
+    let state_bundle = prepared(NORMAL_CASE);
+    let mut performance = Performance::new();
+    let mean: f64 = state_bundle.simple_avg(ROW, 0);
+    let (min_value, max_value) = state_bundle.find_min_max(ROW, 0);
+    let ks_zero = state_bundle.ks(0.0, false, f64::NAN, ROW, 0, &mut performance);
+    let (_, guess, _) = state_bundle.min_guess_max_triplet(
+        mean,
+        min_value,
+        max_value,
+        (ks_zero.1, ks_zero.2, ks_zero.3),
+    );
+    let min_delta = min_delta(&state_bundle);
+
+    c.bench_function("ks", |b| {
+        b.iter(|| state_bundle.ks(black_box(guess), false, f64::NAN, ROW, 0, &mut performance))
+    });
+    c.bench_function("ks_biased", |b| {
+        b.iter(|| state_bundle.ks(black_box(guess), true, mean.ln(), ROW, 0, &mut performance))
+    });
+    c.bench_function("householder", |b| {
+        b.iter(|| {
+            state_bundle.householder(
+                false,
+                f64::NAN,
+                ROW,
+                0,
+                black_box(mean),
+                guess,
+                min_value,
+                max_value,
+                min_delta,
+                &mut performance,
+            )
+        })
+    });
+    c.bench_function("saddlepoint_approximation", |b| {
+        b.iter(|| {
+            state_bundle.saddlepoint_approximation_wrapper(
+                ROW,
+                0,
+                black_box(mean),
+                false,
+                f64::NAN,
+                &mut performance,
+            )
+        })
+    });
+    c.bench_function("brute_success_prob", |b| {
+        b.iter(|| state_bundle.brute_success_prob(ROW, 0, black_box(mean), mean, false))
+    });
+
+    let special_bundle = prepared(SPECIAL_CASE);
+    c.bench_function("compute_special_probs", |b| {
+        b.iter_batched(
+            || {
+                let mut x = special_bundle.clone();
+                x.special_cache.clear();
+                x
+            },
+            |mut x| x.compute_special_probs(false),
+            BatchSize::SmallInput,
+        )
+    });
+
+    let adv_bundle = prepared(ADV_CASE);
+    let adv_config = adv_bundle
+        .upgrade_arr
+        .iter()
+        .find(|x| !x.is_normal_honing)
+        .unwrap()
+        .adv_config;
+    c.bench_function("compute_adv_dist_wrapper", |b| {
+        b.iter(|| compute_adv_dist_wrapper(black_box(&adv_config)))
+    });
+}
+
+criterion_group!(benches, kernels);
+criterion_main!(benches);
@@ -0,0 +1,465 @@
+//! Adaptive juice/book policy, decides every tap based on how much of that juice has already been used up.
+//!
+//! The optimizer's plan (Upgrade::state) is fixed per tap index, so an unlucky player keeps juicing at market price long after
+//! their own juice is gone. Here we do dynamic programming over (upgrade, tap index, taps that used this juice, amount of this juice used so far),
+//! one juice row at a time with everything else held at the static plan:
+//! - other materials are priced at their marginal price around the static plan's average usage
+//! - artisan is exact, it only depends on how many of the taps used this juice
+//!
+//! Special leaps and advanced honing are left out, the comparison is done on the normal honing upgrades with no special budget.
+//! Both plans are then evaluated by simulating with the same random numbers, because the adaptive policy has no closed form.
+use crate::constants::ARTISAN_MULTIPLIER;
+use crate::market_depth;
+use crate::performance::Performance;
+use crate::simulation::realized_gold;
+use crate::state_bundle::StateBundle;
+use crate::upgrade::Upgrade;
+use num::Integer;
+use rand::Rng;
+use serde::Serialize;
+
+#[cfg(feature = "v35")]
+use crate::optimizer::solve;
+
+pub const MAX_GRID: usize = 128; // the juice used so far is rounded to at most this many points
+pub const MAX_TAPS: usize = 1000; // only matters if there's no artisan at all
+
+#[derive(Debug, Clone, Serialize)]
+pub struct RowPolicy {
+    pub row: usize, // material row, 7 + id for weapons and 7 + num_juice_avail + id for armor
+    pub id: usize,
+    pub unit: f64, // grid step of the amount used so far
+    pub grid_len: usize,
+    pub use_juice: Vec<(usize, Vec<Vec<bool>>)>, // (index into upgrade_arr, [tap index][taps that used this juice so far * grid_len + amount used / unit])
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct AdaptivePolicy {
+    pub rows: Vec<RowPolicy>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct AdaptiveComparison {
+    pub static_metric: f64, // the usual average gold metric of the static plan (no special, no adv)
+    pub static_simulated: f64,
+    pub adaptive_simulated: f64,
+    pub std_err: f64, // of the difference between the two simulated averages
+    pub num_samples: usize,
+}
+
+/// Price of the next unit after using x
+fn marginal_price(x: f64, thresh_price_pairs: &[(f64, f64)], depth: &[(f64, f64)]) -> f64 {
+    let index: usize = thresh_price_pairs
+        .iter()
+        .skip(1)
+        .take_while(|(thresh, _)| *thresh <= x)
+        .count();
+    let (thresh, price) = thresh_price_pairs[index];
+    if index + 1 == thresh_price_pairs.len() {
+        price * market_depth::multiplier(x - thresh, depth)
+    } else {
+        price
+    }
+}
+
+/// (juice amount, chance) of this id on this upgrade, (0, 0) if it can't be used
+fn juice_effect(state_bundle: &StateBundle, upgrade: &Upgrade, id: usize) -> (f64, f64) {
+    let juice_info = &state_bundle.prep_output.juice_info;
+    if upgrade.upgrade_index < 3
+        || !juice_info.normal_uindex_to_id[upgrade.upgrade_index].contains(&id)
+    {
+        return (0.0, 0.0);
+    }
+    let this = juice_info.access(id, upgrade.upgrade_index);
+    (this.normal_amt_used as f64, this.normal_chance)
+}
+
+/// Normal honing upgrades with no special budget, in honing order
+fn comparison_bundle(state_bundle: &StateBundle) -> StateBundle {
+    let normal: Vec<usize> = state_bundle
+        .special_state
+        .iter()
+        .copied()
+        .filter(|&u_index| state_bundle.upgrade_arr[u_index].is_normal_honing)
+        .collect();
+    let mut out = state_bundle.subset(&normal);
+    out.prep_output.special_budget = 0;
+    out.special_cache.clear();
+    out.update_prob_dist();
+    out.update_cost_dist();
+    out.compute_special_probs(false);
+    out
+}
+
+impl AdaptivePolicy {
+    pub fn build(input: &StateBundle) -> (AdaptivePolicy, StateBundle) {
+        let state_bundle = comparison_bundle(input);
+        let juice_info = &state_bundle.prep_output.juice_info;
+        let material_info = &state_bundle.prep_output.optimizer_material_info;
+        let marginal: Vec<f64> = material_info
+            .iter()
+            .enumerate()
+            .map(|(row, pairs)| {
+                marginal_price(
+                    state_bundle.simple_avg(row as i64, 0),
+                    pairs,
+                    &state_bundle.prep_output.market_depth[row],
+                )
+            })
+            .collect();
+
+        let mut rows: Vec<RowPolicy> = Vec::new();
+        for id in 0..juice_info.num_juice_avail {
+            for is_weapon in [true, false] {
+                let row = 7
+                    + id
+                    + if is_weapon {
+                        0
+                    } else {
+                        juice_info.num_juice_avail
+                    };
+                let users: Vec<usize> = state_bundle
+                    .special_state
+                    .iter()
+                    .copied()
+                    .filter(|&u_index| {
+                        let upgrade = &state_bundle.upgrade_arr[u_index];
+                        upgrade.is_weapon == is_weapon
+                            && juice_effect(&state_bundle, upgrade, id).0 > 0.0
+                    })
+                    .collect();
+                if users.is_empty() {
+                    continue;
+                }
+                rows.push(Self::build_row(&state_bundle, row, id, &users, &marginal));
+            }
+        }
+        (AdaptivePolicy { rows }, state_bundle)
+    }
+
+    fn build_row(
+        state_bundle: &StateBundle,
+        row: usize,
+        id: usize,
+        users: &[usize],
+        marginal: &[f64],
+    ) -> RowPolicy {
+        let pairs = &state_bundle.prep_output.optimizer_material_info[row];
+        let depth = &state_bundle.prep_output.market_depth[row];
+        let last_thresh: f64 = pairs.last().unwrap().0.max(0.0);
+        let amounts: Vec<i64> = users
+            .iter()
+            .map(|&u| juice_effect(state_bundle, &state_bundle.upgrade_arr[u], id).0 as i64)
+            .collect();
+        let gcd: i64 = amounts.iter().fold(0, |acc, x| acc.gcd(x)).max(1);
+        let unit: f64 = (gcd as f64).max((last_thresh / (MAX_GRID - 1) as f64).ceil());
+        let grid_len: usize = (last_thresh / unit).floor() as usize + 1;
+
+        let mut next_value: Vec<f64> = vec![0.0; grid_len]; // value of starting the next upgrade with this much used
+        let mut use_juice: Vec<(usize, Vec<Vec<bool>>)> = Vec::with_capacity(users.len());
+        for &u_index in users.iter().rev() {
+            let upgrade = &state_bundle.upgrade_arr[u_index];
+            let (amt, juice_chance) = juice_effect(state_bundle, upgrade, id);
+            let amt_units: usize = (amt / unit).round() as usize;
+            let (chances, other_costs) =
+                Self::taps_without_row(state_bundle, upgrade, id, marginal);
+            let num_taps: usize = chances.len();
+
+            // artisan only depends on how many taps used this juice, the other juices follow the static plan
+            let artisan_per_chance: f64 = ARTISAN_MULTIPLIER * upgrade.artisan_rate;
+            let mut chance_so_far: Vec<f64> = vec![0.0; num_taps + 1];
+            for t in 0..num_taps {
+                chance_so_far[t + 1] = chance_so_far[t] + chances[t];
+            }
+            let pity = |t: usize, n: usize| {
+                upgrade.starting_artisan
+                    + artisan_per_chance * (chance_so_far[t] + n as f64 * juice_chance)
+                    >= 1.0
+            };
+
+            let mut decisions: Vec<Vec<bool>> = vec![Vec::new(); num_taps];
+            let mut after: Vec<f64> = Vec::new(); // value of failing tap t, indexed [juiced so far * grid_len + used / unit]
+            for t in (0..num_taps).rev() {
+                let mut this_value: Vec<f64> = vec![0.0; (t + 1) * grid_len];
+                decisions[t] = vec![false; (t + 1) * grid_len];
+                for n in 0..=t {
+                    let certain: bool = t == num_taps - 1 || chances[t] >= 1.0 || pity(t, n);
+                    for c in 0..grid_len {
+                        if certain {
+                            this_value[n * grid_len + c] = -other_costs[t] + next_value[c];
+                            continue;
+                        }
+                        let mut best: (f64, bool) = (f64::NEG_INFINITY, false);
+                        for use_this in [false, true] {
+                            let chance: f64 =
+                                (chances[t] + use_this as i64 as f64 * juice_chance).min(1.0);
+                            let (next_c, juice_cost) = if use_this {
+                                let used: f64 = c as f64 * unit;
+                                (
+                                    (c + amt_units).min(grid_len - 1),
+                                    realized_gold(used, pairs, depth)
+                                        - realized_gold(used + amt, pairs, depth),
+                                )
+                            } else {
+                                (c, 0.0)
+                            };
+                            let fail_value: f64 = if chance >= 1.0 {
+                                0.0
+                            } else {
+                                after[(n + use_this as usize) * grid_len + next_c]
+                            };
+                            let this: f64 = -other_costs[t] - juice_cost
+                                + chance * next_value[next_c]
+                                + (1.0 - chance) * fail_value;
+                            if this > best.0 + 1e-12 {
+                                best = (this, use_this);
+                            }
+                        }
+                        this_value[n * grid_len + c] = best.0;
+                        decisions[t][n * grid_len + c] = best.1;
+                    }
+                }
+                after = this_value;
+            }
+            next_value = after;
+            use_juice.push((u_index, decisions));
+        }
+        use_juice.reverse();
+        RowPolicy {
+            row,
+            id,
+            unit,
+            grid_len,
+            use_juice,
+        }
+    }
+
+    /// (chance, cost of everything else) of every tap if this juice is never used, up until artisan is guaranteed to be full
+    fn taps_without_row(
+        state_bundle: &StateBundle,
+        upgrade: &Upgrade,
+        id: usize,
+        marginal: &[f64],
+    ) -> (Vec<f64>, Vec<f64>) {
+        let num_juice_avail = state_bundle.prep_output.juice_info.num_juice_avail;
+        let offset = 7 + if upgrade.is_weapon {
+            0
+        } else {
+            num_juice_avail
+        };
+        let base_cost: f64 = (0..7).map(|m| upgrade.costs[m] * marginal[m]).sum();
+
+        let mut chances: Vec<f64> = Vec::new();
+        let mut other_costs: Vec<f64> = Vec::new();
+        let mut artisan: f64 = upgrade.starting_artisan;
+        while chances.len() < MAX_TAPS {
+            let t = chances.len();
+            let mut extra: f64 = 0.0;
+            let mut cost: f64 = base_cost;
+            let (juice, book) = *upgrade.state.get(t).unwrap_or(&(false, 0));
+            for (other_id, other_used) in [(0, juice), (book, book > 0)] {
+                if other_id != id && other_used {
+                    let (other_amt, other_chance) = juice_effect(state_bundle, upgrade, other_id);
+                    extra += other_chance;
+                    cost += other_amt * marginal[offset + other_id];
+                }
+            }
+            let mut chance: f64 = upgrade.tap_chance(t, extra);
+            if artisan >= 1.0 {
+                chance = 1.0;
+                cost = base_cost;
+            }
+            chances.push(chance);
+            other_costs.push(cost);
+            if chance >= 1.0 {
+                break;
+            }
+            artisan += ARTISAN_MULTIPLIER * chance * upgrade.artisan_rate;
+        }
+        (chances, other_costs)
+    }
+
+    /// Only one book fits in a tap, if several want to be used we take the one with the highest chance
+    fn decide(
+        &self,
+        state_bundle: &StateBundle,
+        u_index: usize,
+        tap: usize,
+        used: &[f64],
+        juiced: &[usize],
+    ) -> (bool, usize) {
+        let upgrade = &state_bundle.upgrade_arr[u_index];
+        let mut juice: bool = false;
+        let mut book: (usize, f64) = (0, 0.0);
+        for row_policy in self.rows.iter() {
+            let Some((_, decisions)) = row_policy.use_juice.iter().find(|x| x.0 == u_index) else {
+                continue;
+            };
+            let c = ((used[row_policy.row] / row_policy.unit).round() as usize)
+                .min(row_policy.grid_len - 1);
+            if !decisions
+                .get(tap)
+                .and_then(|x| x.get(juiced[row_policy.row] * row_policy.grid_len + c))
+                .is_some_and(|x| *x)
+            {
+                continue;
+            }
+            let juice_chance = juice_effect(state_bundle, upgrade, row_policy.id).1;
+            if row_policy.id == 0 {
+                juice = true;
+            } else if juice_chance > book.1 {
+                book = (row_policy.id, juice_chance);
+            }
+        }
+        (juice, book.0)
+    }
+}
+
+/// Gold left of one simulated run, decide(u_index, tap, used so far, taps of this upgrade that used each row)
+/// returns the (juice, book) of that tap
+fn simulate_once<R: Rng, F: Fn(usize, usize, &[f64], &[usize]) -> (bool, usize)>(
+    state_bundle: &StateBundle,
+    rng: &mut R,
+    decide: &F,
+) -> f64 {
+    let juice_info = &state_bundle.prep_output.juice_info;
+    let mut used: Vec<f64> = vec![0.0; juice_info.total_num_avail];
+    for &u_index in state_bundle.special_state.iter() {
+        let upgrade = &state_bundle.upgrade_arr[u_index];
+        if !upgrade.unlocked {
+            for (u, c) in used.iter_mut().zip(upgrade.unlock_costs.iter()).take(7) {
+                *u += c;
+            }
+        }
+        let offset = 7 + if upgrade.is_weapon {
+            0
+        } else {
+            juice_info.num_juice_avail
+        };
+        let mut artisan: f64 = upgrade.starting_artisan;
+        let mut juiced: Vec<usize> = vec![0; used.len()];
+        let mut tap: usize = 0;
+        loop {
+            for (u, c) in used.iter_mut().zip(upgrade.costs.iter()).take(7) {
+                *u += c;
+            }
+            let chance: f64 = if artisan >= 1.0 {
+                1.0
+            } else {
+                let mut extra: f64 = 0.0;
+                let (juice, book) = decide(u_index, tap, &used, &juiced);
+                for (id, this_used) in [(0, juice), (book, book > 0)] {
+                    let (amt, juice_chance) = juice_effect(state_bundle, upgrade, id);
+                    if this_used && amt > 0.0 {
+                        used[offset + id] += amt;
+                        juiced[offset + id] += 1;
+                        extra += juice_chance;
+                    }
+                }
+                upgrade.tap_chance(tap, extra)
+            };
+            if rng.random::<f64>() < chance {
+                break;
+            }
+            artisan += ARTISAN_MULTIPLIER * chance * upgrade.artisan_rate;
+            tap += 1;
+        }
+    }
+    used.iter()
+        .zip(state_bundle.prep_output.optimizer_material_info.iter())
+        .zip(state_bundle.prep_output.market_depth.iter())
+        .map(|((x, pairs), depth)| realized_gold(*x, pairs, depth))
+        .sum()
+}
+
+impl AdaptivePolicy {
+    /// Simulates both plans with the same random numbers, comparison_bundle is the second thing returned by build
+    pub fn compare<R: Rng + Clone>(
+        &self,
+        comparison_bundle: &mut StateBundle,
+        num_samples: usize,
+        rng: &mut R,
+        performance: &mut Performance,
+    ) -> AdaptiveComparison {
+        let static_metric = comparison_bundle.optimizer_average_gold_metric(performance);
+        let bundle: &StateBundle = comparison_bundle;
+
+        let static_decide = |u_index: usize, tap: usize, _: &[f64], _: &[usize]| {
+            *bundle.upgrade_arr[u_index]
+                .state
+                .get(tap)
+                .unwrap_or(&(false, 0))
+        };
+        let adaptive_decide = |u_index: usize, tap: usize, used: &[f64], juiced: &[usize]| {
+            self.decide(bundle, u_index, tap, used, juiced)
+        };
+
+        let mut static_sum: f64 = 0.0;
+        let mut adaptive_sum: f64 = 0.0;
+        let mut diff_sq_sum: f64 = 0.0;
+        for _ in 0..num_samples {
+            let mut rng_copy = rng.clone();
+            let static_gold = simulate_once(bundle, &mut rng_copy, &static_decide);
+            let adaptive_gold = simulate_once(bundle, rng, &adaptive_decide);
+            static_sum += static_gold;
+            adaptive_sum += adaptive_gold;
+            diff_sq_sum += (adaptive_gold - static_gold).powi(2);
+        }
+        let n = num_samples as f64;
+        let mean_diff = (adaptive_sum - static_sum) / n;
+        AdaptiveComparison {
+            static_metric,
+            static_simulated: static_sum / n,
+            adaptive_simulated: adaptive_sum / n,
+            std_err: ((diff_sq_sum / n - mean_diff * mean_diff).max(0.0) / n).sqrt(),
+            num_samples,
+        }
+    }
+}
+
+/// solve, then build the adaptive policy on top of the static plan it found
+#[cfg(feature = "v35")]
+pub fn solve_adaptive<R: Rng + Clone>(
+    rng: &mut R,
+    state_bundle: StateBundle,
+    num_samples: usize,
+    performance: &mut Performance,
+) -> (StateBundle, AdaptivePolicy, AdaptiveComparison) {
+    let solved = solve(rng, state_bundle, performance);
+    let (policy, mut comparison_bundle) = AdaptivePolicy::build(&solved);
+    let comparison = policy.compare(&mut comparison_bundle, num_samples, rng, performance);
+    (solved, policy, comparison)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::helpers::test_cases_dir;
+    use crate::payload::parse_to_payloads;
+    use rand::SeedableRng;
+    use rand::rngs::StdRng;
+
+    #[test]
+    fn adaptive_not_worse_than_static() {
+        let payloads = parse_to_payloads(&test_cases_dir().join("payloads"));
+        let (_, payload) = payloads.iter().find(|(name, _)| name == "2122").unwrap();
+        let mut state_bundle = StateBundle::init_from_payload(payload.clone()).unwrap();
+        state_bundle.update_prob_dist();
+        state_bundle.update_cost_dist();
+
+        let (policy, mut comparison_bundle) = AdaptivePolicy::build(&state_bundle);
+        assert!(!policy.rows.is_empty());
+        let mut rng = StdRng::seed_from_u64(0);
+        let mut performance = Performance::new();
+        let comparison = policy.compare(&mut comparison_bundle, 20000, &mut rng, &mut performance);
+
+        // the simulation agrees with the closed form for the static plan
+        assert!(
+            (comparison.static_simulated - comparison.static_metric).abs()
+                < 0.02 * comparison.static_metric.abs()
+        );
+        assert!(
+            comparison.adaptive_simulated > comparison.static_simulated - 3.0 * comparison.std_err
+        );
+    }
+}
@@ -1,2 +1,3 @@
 pub mod compute;
+pub mod one_sim;
 pub mod utils;
@@ -1,3 +1,5 @@
+//! One advanced honing attempt played out ball by ball, returns (taps paid for, juice used, scrolls used)
+
 use crate::advanced_honing::utils::AdvConfig;
 use rand::Rng;
 
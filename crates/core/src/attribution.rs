@@ -0,0 +1,149 @@
+//! Splits expected material use and gold between the upgrades, so the UI can say things like "60% of the cost is weapon +25".
+//!
+//! Material use is additive so that part is exact, it's just each upgrade's mean given how likely free taps are to cover it.
+//! Gold isn't (distribute_budgets prices the total, so the first few mats are free and the rest are at market price),
+//! so each upgrade gets its leave-one-out difference (gold left without it minus gold left with everything),
+//! then those get scaled so they add up to the whole cost (gold left with nothing to hone minus gold left with everything).
+use crate::constants::{SPECIAL_TOL, TreatmentsType};
+use crate::instructions::upgrade_label;
+use crate::performance::Performance;
+use crate::state_bundle::StateBundle;
+use serde::Serialize;
+
+#[derive(Debug, Clone, Serialize, PartialEq)]
+pub struct UpgradeAttribution {
+    pub upgrade: usize, // index into upgrade_arr
+    pub label: String,
+    pub expected_use: Vec<f64>, // [material type], adds up to average_breakdown
+    pub marginal_gold: Vec<f64>, // [treatment plan] gold saved by not doing this one
+    pub gold_cost: Vec<f64>,    // [treatment plan] marginal_gold scaled to add up to the total cost
+    pub share: Vec<f64>,        // [treatment plan] gold_cost / total cost
+}
+
+impl StateBundle {
+    /// Expected amount of each material this upgrade uses, free taps included
+    fn expected_use(&self, u_index: usize) -> Vec<f64> {
+        let position = self
+            .special_state
+            .iter()
+            .position(|x| *x == u_index)
+            .unwrap();
+        let upgrade = &self.upgrade_arr[u_index];
+        let mut out: Vec<f64> = vec![0.0; upgrade.cost_dist.len()];
+        for (skip_count, &special_prob) in self.special_probs().iter().enumerate() {
+            if special_prob < SPECIAL_TOL {
+                continue;
+            }
+            for (support_index, support) in upgrade.cost_dist.iter().enumerate() {
+                out[support_index] += special_prob
+                    * support
+                        .access_collapsed(skip_count > position)
+                        .iter()
+                        .map(|(s, p)| s * p)
+                        .sum::<f64>();
+            }
+        }
+        out
+    }
+
+    /// Same treatments as ui_average_gold_metric (the optimizer's plan if None), n + 2 metric evaluations
+    pub fn upgrade_attribution(
+        &mut self,
+        inp_treatment_arr: Option<&[TreatmentsType]>,
+        performance: &mut Performance,
+    ) -> Vec<UpgradeAttribution> {
+        let (full, _, _) = self.ui_average_gold_metric(inp_treatment_arr, performance);
+        let expected_use: Vec<Vec<f64>> = (0..self.upgrade_arr.len())
+            .map(|u_index| self.expected_use(u_index))
+            .collect();
+        let (nothing, _, _) = self
+            .subset(&[])
+            .ui_average_gold_metric(inp_treatment_arr, performance);
+
+        let marginal_gold: Vec<Vec<f64>> = (0..self.upgrade_arr.len())
+            .map(|left_out| {
+                let rest: Vec<usize> = (0..self.upgrade_arr.len())
+                    .filter(|x| *x != left_out)
+                    .collect();
+                let (without, _, _) = self
+                    .subset(&rest)
+                    .ui_average_gold_metric(inp_treatment_arr, performance);
+                without
+                    .iter()
+                    .zip(full.iter())
+                    .map(|(w, f)| w - f)
+                    .collect()
+            })
+            .collect();
+
+        // leave-one-out can come out slightly negative from the approximations, those upgrades just don't get any of the cost
+        let marginal_sum: Vec<f64> = (0..full.len())
+            .map(|t| marginal_gold.iter().map(|x| x[t].max(0.0)).sum())
+            .collect();
+        marginal_gold
+            .into_iter()
+            .zip(expected_use)
+            .enumerate()
+            .map(|(u_index, (marginal_gold, expected_use))| {
+                let share: Vec<f64> = marginal_gold
+                    .iter()
+                    .zip(marginal_sum.iter())
+                    .map(|(m, sum)| if *sum > 0.0 { m.max(0.0) / sum } else { 0.0 })
+                    .collect();
+                UpgradeAttribution {
+                    upgrade: u_index,
+                    label: upgrade_label(&self.upgrade_arr[u_index]),
+                    expected_use,
+                    gold_cost: share
+                        .iter()
+                        .zip(nothing.iter().zip(full.iter()))
+                        .map(|(s, (n, f))| s * (n - f))
+                        .collect(),
+                    marginal_gold,
+                    share,
+                }
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::helpers::test_cases_dir;
+    use crate::payload::parse_to_payloads;
+
+    #[test]
+    fn attribution_adds_up() {
+        let (_, payload) = parse_to_payloads(&test_cases_dir().join("payloads"))
+            .into_iter()
+            .find(|(x, _)| x == "reaper")
+            .unwrap();
+        let mut state_bundle = StateBundle::init_from_payload(payload).unwrap();
+        let mut performance = Performance::new();
+        let treatment_arr = state_bundle.prep_output.treatment_arr();
+        let attribution = state_bundle.upgrade_attribution(Some(&treatment_arr), &mut performance);
+        let (metrics_arr, average_breakdown, _) =
+            state_bundle.ui_average_gold_metric(Some(&treatment_arr), &mut performance);
+        let (nothing, _, _) = state_bundle
+            .subset(&[])
+            .ui_average_gold_metric(Some(&treatment_arr), &mut performance);
+
+        assert_eq!(attribution.len(), state_bundle.upgrade_arr.len());
+        for (support_index, avg) in average_breakdown.iter().enumerate() {
+            let total: f64 = attribution
+                .iter()
+                .map(|x| x.expected_use[support_index])
+                .sum();
+            assert!((total - avg).abs() <= 1e-6 * avg.abs().max(1.0));
+        }
+        for t in 0..treatment_arr.len() {
+            let share: f64 = attribution.iter().map(|x| x.share[t]).sum();
+            assert!((share - 1.0).abs() < 1e-9, "{}", share);
+            let cost: f64 = attribution.iter().map(|x| x.gold_cost[t]).sum();
+            let total = nothing[t] - metrics_arr[t];
+            assert!(total > 0.0);
+            assert!((cost - total).abs() <= 1e-6 * total);
+        }
+    }
+}
@@ -1,53 +1,44 @@
-use super::constants::DATA;
+use super::events::EventEffects;
+use super::registry::data;
 
-pub fn get_event_extra_chance(express_event: bool, tier: usize) -> Vec<f64> {
-    if !express_event {
-        return vec![0.0; 25];
-    }
-    DATA[tier].EVENT_NORMAL_EXTRA_CHANCE.clone()
+pub fn get_event_extra_chance(events: &EventEffects) -> Vec<f64> {
+    events.normal_extra_chance.clone()
 }
-pub fn get_artisan(express_event: bool, tier: usize) -> Vec<f64> {
-    if !express_event {
-        return vec![1.0; 25];
-    }
-
-    DATA[tier].EVENT_ARTISAN_MULTIPLIER.clone()
+pub fn get_artisan(events: &EventEffects) -> Vec<f64> {
+    events.artisan_multiplier.clone()
 }
 
 pub fn get_special_leap_cost(tier: usize) -> Vec<Vec<i64>> {
-    DATA[tier].SPECIAL_LEAPS_COST.clone()
+    data(tier).data.SPECIAL_LEAPS_COST.clone()
 }
 
 pub fn get_normal_hone_chances(tier: usize) -> Vec<f64> {
-    DATA[tier].NORMAL_CHANCES.clone()
+    data(tier).data.NORMAL_CHANCES.clone()
 }
 pub fn get_data(
-    express_event: bool,
+    events: &EventEffects,
     tier: usize,
     is_adv: bool,
     is_weapon: bool,
     is_unlock: bool,
 ) -> Vec<Vec<f64>> {
+    let entry = data(tier);
     let base = match (is_adv, is_weapon, is_unlock) {
-        (false, false, false) => &DATA[tier].NORMAL_ARMOR_COST,
-        (false, false, true) => &DATA[tier].NORMAL_ARMOR_UNLOCK,
-        (false, true, false) => &DATA[tier].NORMAL_WEAPON_COST,
-        (false, true, true) => &DATA[tier].NORMAL_WEAPON_UNLOCK,
-        (true, false, false) => &DATA[tier].ADV_ARMOR_COST,
-        (true, false, true) => &DATA[tier].ADV_ARMOR_UNLOCK,
-        (true, true, false) => &DATA[tier].ADV_WEAPON_COST,
-        (true, true, true) => &DATA[tier].ADV_WEAPON_UNLOCK,
+        (false, false, false) => &entry.data.NORMAL_ARMOR_COST,
+        (false, false, true) => &entry.data.NORMAL_ARMOR_UNLOCK,
+        (false, true, false) => &entry.data.NORMAL_WEAPON_COST,
+        (false, true, true) => &entry.data.NORMAL_WEAPON_UNLOCK,
+        (true, false, false) => &entry.data.ADV_ARMOR_COST,
+        (true, false, true) => &entry.data.ADV_ARMOR_UNLOCK,
+        (true, true, false) => &entry.data.ADV_WEAPON_COST,
+        (true, true, true) => &entry.data.ADV_WEAPON_UNLOCK,
     };
 
-    let multiplier_arr = match (is_adv, express_event, is_unlock) {
-        (false, false, false) => &vec![vec![1.0_f64; 25]; 7],
-        (false, false, true) => &vec![vec![1.0_f64; 25]; 7],
-        (false, true, false) => &DATA[tier].EVENT_NORMAL_COST_MULTIPLIER,
-        (false, true, true) => &DATA[tier].EVENT_NORMAL_UNLOCK_MULTIPLIER,
-        (true, false, false) => &vec![vec![1.0_f64; 25]; 7],
-        (true, false, true) => &vec![vec![1.0_f64; 25]; 7],
-        (true, true, false) => &DATA[tier].EVENT_ADV_COST_MULTIPLIER,
-        (true, true, true) => &DATA[tier].EVENT_ADV_UNLOCK_MULTIPLIER,
+    let multiplier_arr = match (is_adv, is_unlock) {
+        (false, false) => &events.normal_cost_multiplier,
+        (false, true) => &events.normal_unlock_multiplier,
+        (true, false) => &events.adv_cost_multiplier,
+        (true, true) => &events.adv_unlock_multiplier,
     };
 
     let mut result = vec![vec![0.0_f64; if is_adv { 4 } else { 25 }]; 7];
@@ -1,3 +1,4 @@
+use super::events::{EXPRESS_EVENT_NAME, EventDefinition};
 use serde::Deserialize;
 
 #[allow(non_snake_case)]
@@ -26,6 +27,172 @@ pub struct RawData {
     pub ADV_ARMOR_COST: [[f64; 4]; 7],
     pub ADV_WEAPON_UNLOCK: [[f64; 4]; 7],
     pub ADV_ARMOR_UNLOCK: [[f64; 4]; 7],
+
+    #[serde(default)]
+    pub EVENTS: Vec<EventDefinition>, // named events other than express, which is made from the EVENT_ tables above
+    #[serde(default)]
+    pub VERSION: Option<String>, // shown to the user so they know which patch the numbers are from, defaults to the name
+}
+
+type Invalid = (String, String); // (field, reason)
+
+fn check_all(
+    field: &str,
+    values: &[f64],
+    ok: impl Fn(f64) -> bool,
+    reason: &str,
+) -> Result<(), Invalid> {
+    match values.iter().position(|x| !ok(*x)) {
+        Some(index) => Err((
+            format!("{}[{}]", field, index),
+            format!("{} {}", values[index], reason),
+        )),
+        None => Ok(()),
+    }
+}
+
+fn check_table<const N: usize>(
+    field: &str,
+    table: &[[f64; N]],
+    ok: impl Fn(f64) -> bool + Copy,
+    reason: &str,
+) -> Result<(), Invalid> {
+    for (row_index, row) in table.iter().enumerate() {
+        check_all(&format!("{}[{}]", field, row_index), row, ok, reason)?;
+    }
+    Ok(())
+}
+
+fn is_chance(x: f64) -> bool {
+    (0.0..=1.0).contains(&x)
+}
+fn is_non_negative(x: f64) -> bool {
+    x.is_finite() && x >= 0.0
+}
+fn is_positive(x: f64) -> bool {
+    x.is_finite() && x > 0.0
+}
+
+impl RawData {
+    /// serde only checks the shape, this checks the things that would otherwise panic (or silently be nonsense) deep inside the optimizer
+    pub fn validate(&self) -> Result<(), Invalid> {
+        check_all(
+            "NORMAL_CHANCES",
+            &self.NORMAL_CHANCES,
+            is_chance,
+            "is not a chance",
+        )?;
+        check_all(
+            "EVENT_NORMAL_EXTRA_CHANCE",
+            &self.EVENT_NORMAL_EXTRA_CHANCE,
+            is_chance,
+            "is not a chance",
+        )?;
+        check_all(
+            "EVENT_ARTISAN_MULTIPLIER",
+            &self.EVENT_ARTISAN_MULTIPLIER,
+            is_positive,
+            "is not a valid multiplier",
+        )?;
+        for (field, table) in [
+            ("NORMAL_WEAPON_COST", &self.NORMAL_WEAPON_COST),
+            ("NORMAL_ARMOR_COST", &self.NORMAL_ARMOR_COST),
+            ("NORMAL_WEAPON_UNLOCK", &self.NORMAL_WEAPON_UNLOCK),
+            ("NORMAL_ARMOR_UNLOCK", &self.NORMAL_ARMOR_UNLOCK),
+        ] {
+            check_table(field, table, is_non_negative, "is not a valid cost")?;
+        }
+        for (field, table) in [
+            ("ADV_WEAPON_COST", &self.ADV_WEAPON_COST),
+            ("ADV_ARMOR_COST", &self.ADV_ARMOR_COST),
+            ("ADV_WEAPON_UNLOCK", &self.ADV_WEAPON_UNLOCK),
+            ("ADV_ARMOR_UNLOCK", &self.ADV_ARMOR_UNLOCK),
+        ] {
+            check_table(field, table, is_non_negative, "is not a valid cost")?;
+        }
+        for (field, table) in [
+            (
+                "EVENT_NORMAL_COST_MULTIPLIER",
+                &self.EVENT_NORMAL_COST_MULTIPLIER,
+            ),
+            (
+                "EVENT_NORMAL_UNLOCK_MULTIPLIER",
+                &self.EVENT_NORMAL_UNLOCK_MULTIPLIER,
+            ),
+        ] {
+            check_table(field, table, is_non_negative, "is not a valid multiplier")?;
+        }
+        for (field, table) in [
+            ("EVENT_ADV_COST_MULTIPLIER", &self.EVENT_ADV_COST_MULTIPLIER),
+            (
+                "EVENT_ADV_UNLOCK_MULTIPLIER",
+                &self.EVENT_ADV_UNLOCK_MULTIPLIER,
+            ),
+        ] {
+            check_table(field, table, is_non_negative, "is not a valid multiplier")?;
+        }
+        for (row_index, row) in self.SPECIAL_LEAPS_COST.iter().enumerate() {
+            if let Some(index) = row.iter().position(|x| *x < 0) {
+                return Err((
+                    format!("SPECIAL_LEAPS_COST[{}][{}]", row_index, index),
+                    format!("{} is not a valid cost", row[index]),
+                ));
+            }
+        }
+
+        // JuiceInfo::new expects ids to show up in order starting from 0
+        let mut num_ids = 0;
+        for (index, &(id, is_adv, upgrade_plus, normal_chance, amt_used, gs_chance, gsx2_chance)) in
+            self.JUICE_BOOKS_AVAIL.iter().enumerate()
+        {
+            let field = format!("JUICE_BOOKS_AVAIL[{}]", index);
+            let max_plus = match is_adv {
+                0 => 25,
+                1 => 4,
+                _ => return Err((field, format!("is_adv is {}, should be 0 or 1", is_adv))),
+            };
+            if id > num_ids {
+                return Err((field, format!("id {} skips id {}", id, num_ids)));
+            }
+            num_ids = num_ids.max(id + 1);
+            if !(1..=max_plus).contains(&upgrade_plus) {
+                return Err((
+                    field,
+                    format!("upgrade_plus {} is out of range", upgrade_plus),
+                ));
+            }
+            if amt_used < 0 {
+                return Err((field, format!("amount used {} is negative", amt_used)));
+            }
+            if !is_chance(normal_chance)
+                || !is_chance(gs_chance)
+                || !is_chance(gsx2_chance)
+                || gs_chance + gsx2_chance > 1.0
+            {
+                return Err((field, "has chances outside of [0, 1]".to_owned()));
+            }
+        }
+        for (index, &(id, is_adv, upgrade_plus, mult)) in
+            self.EVENT_ADV_JUICE_MULTIPLIER.iter().enumerate()
+        {
+            let field = format!("EVENT_ADV_JUICE_MULTIPLIER[{}]", index);
+            if id >= num_ids || is_adv > 1 || !(1..=25).contains(&upgrade_plus) {
+                return Err((
+                    field,
+                    "does not refer to a juice in JUICE_BOOKS_AVAIL".to_owned(),
+                ));
+            }
+            if !is_non_negative(mult) {
+                return Err((field, format!("{} is not a valid multiplier", mult)));
+            }
+        }
+        for event in self.EVENTS.iter() {
+            event
+                .validate()
+                .map_err(|e| (format!("EVENTS[{}]", event.name), e.to_string()))?;
+        }
+        Ok(())
+    }
 }
 #[allow(non_snake_case)]
 pub struct Data {
@@ -47,6 +214,7 @@ pub struct Data {
     pub ADV_ARMOR_COST: Vec<Vec<f64>>,
     pub ADV_WEAPON_UNLOCK: Vec<Vec<f64>>,
     pub ADV_ARMOR_UNLOCK: Vec<Vec<f64>>,
+    pub EVENTS: Vec<EventDefinition>,
 }
 
 impl From<RawData> for Data {
@@ -76,6 +244,23 @@ impl From<RawData> for Data {
             ADV_ARMOR_COST: to_vec2d_4(&r.ADV_ARMOR_COST),
             ADV_WEAPON_UNLOCK: to_vec2d_4(&r.ADV_WEAPON_UNLOCK),
             ADV_ARMOR_UNLOCK: to_vec2d_4(&r.ADV_ARMOR_UNLOCK),
+            EVENTS: r.EVENTS,
+        }
+    }
+}
+
+impl Data {
+    pub fn express_event(&self) -> EventDefinition {
+        EventDefinition {
+            name: EXPRESS_EVENT_NAME.to_owned(),
+            normal_extra_chance: Some(self.EVENT_NORMAL_EXTRA_CHANCE.clone()),
+            artisan_multiplier: Some(self.EVENT_ARTISAN_MULTIPLIER.clone()),
+            normal_cost_multiplier: Some(self.EVENT_NORMAL_COST_MULTIPLIER.clone()),
+            normal_unlock_multiplier: Some(self.EVENT_NORMAL_UNLOCK_MULTIPLIER.clone()),
+            adv_cost_multiplier: Some(self.EVENT_ADV_COST_MULTIPLIER.clone()),
+            adv_unlock_multiplier: Some(self.EVENT_ADV_UNLOCK_MULTIPLIER.clone()),
+            juice_multiplier: self.EVENT_ADV_JUICE_MULTIPLIER.clone(),
+            double_balls: true,
         }
     }
 }
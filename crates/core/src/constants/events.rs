@@ -0,0 +1,385 @@
+//! Event effects, used to be a single express_event bool that switched every EVENT_* table at once
+//!
+//! An event is a partial set of multipliers (anything left out is untouched), events are combined by multiplying
+//! multipliers and adding extra chances. The express event is built from the EVENT_* tables of the data file,
+//! anything else comes either from the EVENTS list of the data file or is defined directly in the payload.
+use super::registry::data;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+pub const EXPRESS_EVENT_NAME: &str = "express";
+
+#[derive(Debug, Clone, Serialize, Deserialize, Default, PartialEq)]
+pub struct EventDefinition {
+    pub name: String,
+    #[serde(default)]
+    pub normal_extra_chance: Option<Vec<f64>>, // [upgrade_index], added to base chance
+    #[serde(default)]
+    pub artisan_multiplier: Option<Vec<f64>>, // [upgrade_index]
+    #[serde(default)]
+    pub normal_cost_multiplier: Option<Vec<Vec<f64>>>, // [cost type][upgrade_index]
+    #[serde(default)]
+    pub normal_unlock_multiplier: Option<Vec<Vec<f64>>>,
+    #[serde(default)]
+    pub adv_cost_multiplier: Option<Vec<Vec<f64>>>, // [cost type][adv upgrade_index]
+    #[serde(default)]
+    pub adv_unlock_multiplier: Option<Vec<Vec<f64>>>,
+    #[serde(default)]
+    pub juice_multiplier: Vec<(usize, usize, usize, f64)>, // (id, is_adv, upgrade_plus, multiplier), same as EVENT_ADV_JUICE_MULTIPLIER
+    #[serde(default)]
+    pub double_balls: bool, // adv honing 10/20 gains 2 balls per tap
+}
+
+/// What the payload lists, either the name of an event in the data or the whole definition
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(untagged)]
+pub enum EventInput {
+    Named(String),
+    Defined(EventDefinition),
+}
+
+/// All active events combined into full-size tables, this is what the accessors read from
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EventEffects {
+    pub names: Vec<String>,
+    pub normal_extra_chance: Vec<f64>,
+    pub artisan_multiplier: Vec<f64>,
+    pub normal_cost_multiplier: Vec<Vec<f64>>,
+    pub normal_unlock_multiplier: Vec<Vec<f64>>,
+    pub adv_cost_multiplier: Vec<Vec<f64>>,
+    pub adv_unlock_multiplier: Vec<Vec<f64>>,
+    pub juice_multiplier: Vec<(usize, usize, usize, f64)>,
+    pub double_balls: bool,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum EventError {
+    Unknown { name: String, tier: usize },
+    WrongShape(String), // name of the event
+    BadValue(String),
+    Reserved(String),
+}
+
+impl fmt::Display for EventError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            EventError::Unknown { name, tier } => {
+                write!(f, "Unknown event {} for tier {}", name, tier)
+            }
+            EventError::WrongShape(name) => {
+                write!(f, "event {} has a table of the wrong shape", name)
+            }
+            EventError::BadValue(name) => write!(
+                f,
+                "event {} has a multiplier that isn't a finite non-negative number, an artisan multiplier that isn't positive or a negative extra chance",
+                name
+            ),
+            EventError::Reserved(name) => {
+                write!(f, "{} is reserved for the EVENT_ tables", name)
+            }
+        }
+    }
+}
+
+impl std::error::Error for EventError {}
+
+impl Default for EventEffects {
+    fn default() -> Self {
+        Self::none()
+    }
+}
+
+/// Shape is checked by add() beforehand
+fn multiply_into(target: &mut [Vec<f64>], source: &Option<Vec<Vec<f64>>>) {
+    if let Some(source) = source {
+        for (t_row, s_row) in target.iter_mut().zip(source.iter()) {
+            for (t, s) in t_row.iter_mut().zip(s_row.iter()) {
+                *t *= s;
+            }
+        }
+    }
+}
+
+impl EventDefinition {
+    /// probability_distribution never reaches a sure tap if artisan doesn't grow or the chance goes below base
+    fn chances_ok(&self) -> bool {
+        self.artisan_multiplier
+            .as_ref()
+            .is_none_or(|r| r.iter().all(|x| x.is_finite() && *x > 0.0))
+            && self
+                .normal_extra_chance
+                .as_ref()
+                .is_none_or(|r| r.iter().all(|x| x.is_finite() && *x >= 0.0))
+    }
+
+    /// Stricter than add() (no negative multipliers, no taking the express name), for definitions that come from a data file
+    pub fn validate(&self) -> Result<(), EventError> {
+        let shape_ok = |table: &Option<Vec<Vec<f64>>>, width: usize| {
+            table
+                .as_ref()
+                .is_none_or(|t| t.len() == 7 && t.iter().all(|row| row.len() == width))
+        };
+        let values_ok = |table: &Option<Vec<Vec<f64>>>| {
+            table.as_ref().is_none_or(|t| {
+                t.iter()
+                    .all(|row| row.iter().all(|x| x.is_finite() && *x >= 0.0))
+            })
+        };
+        let row_ok = |row: &Option<Vec<f64>>| row.as_ref().is_none_or(|r| r.len() == 25);
+        let tables = [
+            (&self.normal_cost_multiplier, 25),
+            (&self.normal_unlock_multiplier, 25),
+            (&self.adv_cost_multiplier, 4),
+            (&self.adv_unlock_multiplier, 4),
+        ];
+        if !row_ok(&self.normal_extra_chance)
+            || !row_ok(&self.artisan_multiplier)
+            || !tables.iter().all(|(t, width)| shape_ok(t, *width))
+        {
+            return Err(EventError::WrongShape(self.name.clone()));
+        }
+        if !self.chances_ok() || !tables.iter().all(|(t, _)| values_ok(t)) {
+            return Err(EventError::BadValue(self.name.clone()));
+        }
+        if self.name == EXPRESS_EVENT_NAME {
+            return Err(EventError::Reserved(self.name.clone()));
+        }
+        Ok(())
+    }
+}
+
+impl EventEffects {
+    pub fn none() -> Self {
+        Self {
+            names: Vec::new(),
+            normal_extra_chance: vec![0.0; 25],
+            artisan_multiplier: vec![1.0; 25],
+            normal_cost_multiplier: vec![vec![1.0; 25]; 7],
+            normal_unlock_multiplier: vec![vec![1.0; 25]; 7],
+            adv_cost_multiplier: vec![vec![1.0; 4]; 7],
+            adv_unlock_multiplier: vec![vec![1.0; 4]; 7],
+            juice_multiplier: Vec::new(),
+            double_balls: false,
+        }
+    }
+
+    /// Nothing is changed if the event doesn't fit
+    pub fn add(&mut self, event: &EventDefinition) -> Result<(), EventError> {
+        let row_ok = |row: &Option<Vec<f64>>| row.as_ref().is_none_or(|x| x.len() == 25);
+        let table_ok = |table: &Option<Vec<Vec<f64>>>, target: &[Vec<f64>]| {
+            table.as_ref().is_none_or(|x| {
+                x.len() == target.len() && x.iter().all(|row| row.len() == target[0].len())
+            })
+        };
+        if !row_ok(&event.normal_extra_chance)
+            || !row_ok(&event.artisan_multiplier)
+            || !table_ok(&event.normal_cost_multiplier, &self.normal_cost_multiplier)
+            || !table_ok(
+                &event.normal_unlock_multiplier,
+                &self.normal_unlock_multiplier,
+            )
+            || !table_ok(&event.adv_cost_multiplier, &self.adv_cost_multiplier)
+            || !table_ok(&event.adv_unlock_multiplier, &self.adv_unlock_multiplier)
+        {
+            return Err(EventError::WrongShape(event.name.clone()));
+        }
+        if !event.chances_ok() {
+            return Err(EventError::BadValue(event.name.clone()));
+        }
+        if let Some(extra) = &event.normal_extra_chance {
+            for (t, s) in self.normal_extra_chance.iter_mut().zip(extra.iter()) {
+                *t += s;
+            }
+        }
+        if let Some(artisan) = &event.artisan_multiplier {
+            for (t, s) in self.artisan_multiplier.iter_mut().zip(artisan.iter()) {
+                *t *= s;
+            }
+        }
+        multiply_into(
+            &mut self.normal_cost_multiplier,
+            &event.normal_cost_multiplier,
+        );
+        multiply_into(
+            &mut self.normal_unlock_multiplier,
+            &event.normal_unlock_multiplier,
+        );
+        multiply_into(&mut self.adv_cost_multiplier, &event.adv_cost_multiplier);
+        multiply_into(
+            &mut self.adv_unlock_multiplier,
+            &event.adv_unlock_multiplier,
+        );
+        for &(id, is_adv, upgrade_plus, mult) in event.juice_multiplier.iter() {
+            match self
+                .juice_multiplier
+                .iter_mut()
+                .find(|x| x.0 == id && x.1 == is_adv && x.2 == upgrade_plus)
+            {
+                Some(existing) => existing.3 *= mult,
+                None => self.juice_multiplier.push((id, is_adv, upgrade_plus, mult)),
+            }
+        }
+        self.double_balls |= event.double_balls;
+        self.names.push(event.name.clone());
+        Ok(())
+    }
+
+    pub fn resolve(inputs: &[EventInput], tier: usize) -> Result<Self, EventError> {
+        let mut out = Self::none();
+        for input in inputs {
+            match input {
+                EventInput::Named(name) => out.add(&builtin_event(tier, name).ok_or_else(
+                    || EventError::Unknown {
+                        name: name.clone(),
+                        tier,
+                    },
+                )?)?,
+                EventInput::Defined(event) => out.add(event)?,
+            }
+        }
+        Ok(out)
+    }
+
+    pub fn juice_multiplier(&self, id: usize, is_adv: bool, upgrade_index: usize) -> f64 {
+        self.juice_multiplier
+            .iter()
+            .find(|x| x.0 == id && (x.1 == 1) == is_adv && x.2 == upgrade_index + 1)
+            .map(|x| x.3)
+            .unwrap_or(1.0)
+    }
+}
+
+/// Events that ship with the data of this tier
+pub fn builtin_event(tier: usize, name: &str) -> Option<EventDefinition> {
+    if name == EXPRESS_EVENT_NAME {
+        return Some(data(tier).data.express_event());
+    }
+    data(tier)
+        .data
+        .EVENTS
+        .iter()
+        .find(|x| x.name == name)
+        .cloned()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::helpers::test_cases_dir;
+    use crate::model::PayloadError;
+    use crate::payload::parse_to_payloads;
+    use crate::performance::Performance;
+    use crate::state_bundle::StateBundle;
+
+    #[test]
+    fn events_combine() {
+        let express = builtin_event(0, EXPRESS_EVENT_NAME).unwrap();
+        let once =
+            EventEffects::resolve(&[EventInput::Named(EXPRESS_EVENT_NAME.to_owned())], 0).unwrap();
+        assert_eq!(
+            once.artisan_multiplier,
+            data(0).data.EVENT_ARTISAN_MULTIPLIER
+        );
+        assert_eq!(
+            once.normal_cost_multiplier,
+            data(0).data.EVENT_NORMAL_COST_MULTIPLIER
+        );
+        assert!(once.double_balls);
+
+        let halved_juice = EventDefinition {
+            name: "half juice".to_owned(),
+            juice_multiplier: vec![(0, 1, 1, 0.5)],
+            ..Default::default()
+        };
+        let both = EventEffects::resolve(
+            &[
+                EventInput::Defined(express.clone()),
+                EventInput::Defined(halved_juice),
+            ],
+            0,
+        )
+        .unwrap();
+        let express_mult = once.juice_multiplier(0, true, 0);
+        assert!((both.juice_multiplier(0, true, 0) - 0.5 * express_mult).abs() < 1e-12);
+        assert_eq!(both.names, vec![EXPRESS_EVENT_NAME, "half juice"]);
+
+        let none = EventEffects::resolve(&[], 0).unwrap();
+        assert_eq!(none.juice_multiplier(0, true, 0), 1.0);
+        assert!(!none.double_balls);
+
+        assert_eq!(
+            EventEffects::resolve(&[EventInput::Named("nope".to_owned())], 0).unwrap_err(),
+            EventError::Unknown {
+                name: "nope".to_owned(),
+                tier: 0
+            }
+        );
+        let lopsided = EventDefinition {
+            name: "lopsided".to_owned(),
+            artisan_multiplier: Some(vec![1.0; 3]),
+            ..Default::default()
+        };
+        assert_eq!(
+            EventEffects::resolve(&[EventInput::Defined(lopsided)], 0).unwrap_err(),
+            EventError::WrongShape("lopsided".to_owned())
+        );
+    }
+
+    #[test]
+    fn stalling_events_are_errors() {
+        let (_, mut payload) = parse_to_payloads(&test_cases_dir().join("payloads"))
+            .into_iter()
+            .find(|(x, _)| x == "single_+25")
+            .unwrap();
+        payload.events = vec![EventInput::Defined(EventDefinition {
+            name: "no artisan".to_owned(),
+            artisan_multiplier: Some(vec![0.0; 25]),
+            ..Default::default()
+        })];
+        assert!(matches!(
+            StateBundle::init_from_payload(payload).unwrap_err(),
+            PayloadError::Event(EventError::BadValue(name)) if name == "no artisan"
+        ));
+
+        let worse = EventDefinition {
+            name: "worse".to_owned(),
+            normal_extra_chance: Some(vec![-0.01; 25]),
+            ..Default::default()
+        };
+        assert_eq!(
+            EventEffects::resolve(&[EventInput::Defined(worse.clone())], 0).unwrap_err(),
+            EventError::BadValue("worse".to_owned())
+        );
+        assert_eq!(
+            worse.validate(),
+            Err(EventError::BadValue("worse".to_owned()))
+        );
+    }
+
+    #[test]
+    fn express_matches_old_bool() {
+        // printed by the tree from before events existed, where express_event was a bool that switched every EVENT_* table:
+        // init_from_payload with express_event = true, then optimizer_average_gold_metric on the payload's own plan
+        let expected = [
+            ("1920_adv3040", -8141307.48401079),
+            ("2122", -5246609.288092092),
+            ("serca_1215", -5101202.933257903),
+        ];
+        let payloads = parse_to_payloads(&test_cases_dir().join("payloads"));
+        for (name, old) in expected {
+            let (_, payload) = payloads.iter().find(|(x, _)| x == name).unwrap();
+            let mut payload = payload.clone();
+            payload.express_event = true;
+            let metric = StateBundle::init_from_payload(payload)
+                .unwrap()
+                .optimizer_average_gold_metric(&mut Performance::new());
+            assert!(
+                (metric - old).abs() < 1e-9 * old.abs(),
+                "{} {} {}",
+                name,
+                metric,
+                old
+            );
+        }
+    }
+}
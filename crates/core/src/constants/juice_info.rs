@@ -5,6 +5,7 @@ use std::{
     ops::Deref,
 };
 
+use crate::constants::events::EventEffects;
 use crate::parser::MaterialInput;
 
 // use crate::my_dbg;
@@ -13,14 +14,12 @@ use crate::parser::MaterialInput;
 pub struct OneUindexJuice {
     pub normal_amt_used: i64,
     normal_base_amt_used: i64,
-    normal_event_amt_used: i64,
     pub normal_chance: f64,
     // pub can_adv: bool, this information is deduced from being in the uindex_to_id vector or not
     pub adv_chances: (f64, f64),
 
     pub adv_amt_used: i64,
     adv_base_amt_used: i64,
-    adv_event_amt_used: i64,
 }
 #[serde_as]
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -59,10 +58,7 @@ impl JuiceInfo {
     pub fn access(&self, id: usize, upgrade_index: usize) -> OneUindexJuice {
         self.all_juices[id][&upgrade_index]
     }
-    pub fn new(
-        juice_books_avail: &[(usize, usize, usize, f64, i64, f64, f64)],
-        event_multiplier: &[(usize, usize, usize, f64)],
-    ) -> JuiceInfo {
+    pub fn new(juice_books_avail: &[(usize, usize, usize, f64, i64, f64, f64)]) -> JuiceInfo {
         let mut normal_uindex_to_id: Vec<Vec<usize>> = vec![vec![]; 25];
         let mut adv_uindex_to_id: Vec<Vec<usize>> = vec![vec![]; 4];
 
@@ -70,10 +66,6 @@ impl JuiceInfo {
         let mut all_data: Vec<HashMap<usize, OneUindexJuice>> = Vec::new();
         let mut seen_ids: HashSet<usize> = HashSet::new();
 
-        let mut event_multipliers: HashMap<(usize, usize, usize), f64> = HashMap::new();
-        for (id, is_adv, upgrade_plus, mult) in event_multiplier {
-            event_multipliers.insert((*id, *is_adv, *upgrade_plus - 1), *mult);
-        }
         for &(id, is_adv, upgrade_plus, normal_chance, amt_used, gs_chance, gsx2_chance) in
             juice_books_avail.iter()
         {
@@ -91,12 +83,6 @@ impl JuiceInfo {
                 &mut adv_uindex_to_id
             };
             relevant[upgrade_index].push(id);
-            let this_event_mult = event_multipliers.get(&(id, is_adv, upgrade_index));
-            let this_event_amt = if this_event_mult.is_none() {
-                amt_used
-            } else {
-                (amt_used as f64 * this_event_mult.unwrap()).ceil() as i64
-            };
             if !all_data[id].contains_key(&upgrade_index) {
                 all_data[id].insert(upgrade_index, OneUindexJuice::default());
             }
@@ -104,12 +90,10 @@ impl JuiceInfo {
             if is_adv == 1 {
                 this.adv_amt_used = amt_used;
                 this.adv_base_amt_used = amt_used;
-                this.adv_event_amt_used = this_event_amt;
                 this.adv_chances = (gs_chance, gsx2_chance);
             } else {
                 this.normal_amt_used = amt_used;
                 this.normal_base_amt_used = amt_used;
-                this.normal_event_amt_used = this_event_amt;
                 this.normal_chance = normal_chance;
             }
         }
@@ -130,7 +114,7 @@ impl JuiceInfo {
 pub fn get_priced_juice_info(
     base: &JuiceInfo,
     material_info: &MaterialInput,
-    event: bool,
+    events: &EventEffects,
 ) -> JuiceInfo {
     // my_dbg!(base.total_num_avail, &market_price);
     assert!(base.total_num_avail == material_info.len());
@@ -138,26 +122,21 @@ pub fn get_priced_juice_info(
     let mut out: JuiceInfo = base.clone();
     for (id, juice_type) in out.all_juices.iter_mut().enumerate() {
         assert!(juice_type.prices.len() == 0);
-        for price_pair in material_info[7 + id].iter().map(|x| x.1).zip(
+        for price_pair in material_info[7 + id].iter().map(|x| x.buy).zip(
             material_info[7 + base.num_juice_avail + id]
                 .iter()
-                .map(|x| x.1),
+                .map(|x| x.buy),
         ) {
             juice_type.prices.push(price_pair)
         }
 
-        for (_, this) in juice_type.data.iter_mut() {
-            this.normal_amt_used = if event {
-                this.normal_event_amt_used
-            } else {
-                this.normal_base_amt_used
-            };
-
-            this.adv_amt_used = if event {
-                this.adv_event_amt_used
-            } else {
-                this.adv_base_amt_used
-            };
+        for (&upgrade_index, this) in juice_type.data.iter_mut() {
+            this.normal_amt_used = (this.normal_base_amt_used as f64
+                * events.juice_multiplier(id, false, upgrade_index))
+            .ceil() as i64;
+            this.adv_amt_used = (this.adv_base_amt_used as f64
+                * events.juice_multiplier(id, true, upgrade_index))
+            .ceil() as i64;
         }
     }
     out
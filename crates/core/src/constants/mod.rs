@@ -1,5 +1,7 @@
 pub mod accessor;
 mod constants;
 pub use constants::*;
-mod data;
+pub mod data;
+pub mod events;
 pub mod juice_info;
+pub mod registry;
@@ -0,0 +1,265 @@
+//! Registry of game data tables, the two bundled files are always there and more can be loaded at runtime
+//! (from a directory natively, or from bytes that the frontend fetched in wasm) so that patch day doesn't need a rebuild.
+//!
+//! `tier` in the payload is still an index into this registry (0 and 1 are the bundled files), but payloads can refer to data by name instead.
+//! Re-registering a name with different contents replaces it in place, so indices never shift.
+use super::data::{Data, RawData};
+use super::juice_info::JuiceInfo;
+use once_cell::sync::Lazy;
+use serde::Serialize;
+use std::fmt;
+use std::fs;
+use std::path::Path;
+use std::sync::{Arc, RwLock};
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum DataError {
+    Io(String),
+    Parse {
+        name: String,
+        reason: String,
+    },
+    Invalid {
+        name: String,
+        field: String,
+        reason: String,
+    },
+    UnknownName(String),
+    UnknownTier(usize),
+}
+
+impl fmt::Display for DataError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DataError::Io(reason) => write!(f, "Failed to read data: {}", reason),
+            DataError::Parse { name, reason } => {
+                write!(f, "Data {} does not have the right shape: {}", name, reason)
+            }
+            DataError::Invalid {
+                name,
+                field,
+                reason,
+            } => write!(f, "Data {} has an invalid {}: {}", name, field, reason),
+            DataError::UnknownName(name) => write!(f, "No data named {}", name),
+            DataError::UnknownTier(tier) => write!(f, "No data at tier {}", tier),
+        }
+    }
+}
+
+impl std::error::Error for DataError {}
+
+pub struct DataEntry {
+    pub name: String,
+    pub version: String,
+    pub checksum: String,
+    pub data: Data,
+    pub base_juice_info: JuiceInfo,
+}
+
+/// What the frontend gets to see about each entry
+#[derive(Debug, Clone, Serialize)]
+pub struct DataEntryInfo {
+    pub tier: usize,
+    pub name: String,
+    pub version: String,
+    pub checksum: String,
+}
+
+/// FNV-1a, we want something that is stable across builds and platforms which DefaultHasher doesn't promise
+fn checksum(bytes: &[u8]) -> String {
+    let mut hash: u64 = 0xcbf29ce484222325;
+    for byte in bytes {
+        hash ^= *byte as u64;
+        hash = hash.wrapping_mul(0x100000001b3);
+    }
+    format!("{:016x}", hash)
+}
+
+pub fn parse_data(name: &str, bytes: &[u8]) -> Result<DataEntry, DataError> {
+    let raw: RawData = serde_json::from_slice(bytes).map_err(|e| DataError::Parse {
+        name: name.to_owned(),
+        reason: e.to_string(),
+    })?;
+    raw.validate()
+        .map_err(|(field, reason)| DataError::Invalid {
+            name: name.to_owned(),
+            field,
+            reason,
+        })?;
+    let version = raw.VERSION.clone().unwrap_or_else(|| name.to_owned());
+    let data = Data::from(raw);
+    let base_juice_info = JuiceInfo::new(&data.JUICE_BOOKS_AVAIL);
+    Ok(DataEntry {
+        name: name.to_owned(),
+        version,
+        checksum: checksum(bytes),
+        data,
+        base_juice_info,
+    })
+}
+
+/// The entries themselves, there's one global one (REGISTRY) but tests can make their own
+pub struct Registry {
+    entries: Vec<Arc<DataEntry>>,
+}
+
+impl Registry {
+    /// Just the bundled files
+    pub fn bundled() -> Self {
+        let bundled: [(&str, &str); 2] = [
+            ("T4 June 2026", include_str!("./T4 June 2026.json")),
+            ("Serca March 2026", include_str!("./Serca March 2026.json")),
+        ];
+        Self {
+            entries: bundled
+                .iter()
+                .map(|(name, contents)| Arc::new(parse_data(name, contents.as_bytes()).unwrap())) // bundled data failing is a build problem
+                .collect(),
+        }
+    }
+
+    /// Adds (or replaces, if the name is taken) an entry and returns its tier
+    pub fn register(&mut self, entry: DataEntry) -> usize {
+        if let Some(tier) = self.entries.iter().position(|x| x.name == entry.name) {
+            self.entries[tier] = Arc::new(entry);
+            tier
+        } else {
+            self.entries.push(Arc::new(entry));
+            self.entries.len() - 1
+        }
+    }
+
+    pub fn register_bytes(&mut self, name: &str, bytes: &[u8]) -> Result<usize, DataError> {
+        Ok(self.register(parse_data(name, bytes)?))
+    }
+
+    pub fn entry(&self, tier: usize) -> Result<Arc<DataEntry>, DataError> {
+        self.entries
+            .get(tier)
+            .cloned()
+            .ok_or(DataError::UnknownTier(tier))
+    }
+
+    pub fn tier_by_name(&self, name: &str) -> Result<usize, DataError> {
+        self.entries
+            .iter()
+            .position(|x| x.name == name)
+            .ok_or_else(|| DataError::UnknownName(name.to_owned()))
+    }
+
+    pub fn list(&self) -> Vec<DataEntryInfo> {
+        self.entries
+            .iter()
+            .enumerate()
+            .map(|(tier, x)| DataEntryInfo {
+                tier,
+                name: x.name.clone(),
+                version: x.version.clone(),
+                checksum: x.checksum.clone(),
+            })
+            .collect()
+    }
+}
+
+static REGISTRY: Lazy<RwLock<Registry>> = Lazy::new(|| RwLock::new(Registry::bundled()));
+
+pub fn register_data(entry: DataEntry) -> usize {
+    REGISTRY.write().unwrap().register(entry)
+}
+
+pub fn register_data_bytes(name: &str, bytes: &[u8]) -> Result<usize, DataError> {
+    Ok(register_data(parse_data(name, bytes)?))
+}
+
+/// Registers every json in the folder (named after the file stem), nothing is registered if any of them fail
+pub fn register_data_dir(path: &Path) -> Result<Vec<usize>, DataError> {
+    let mut entries: Vec<_> = fs::read_dir(path)
+        .map_err(|e| DataError::Io(e.to_string()))?
+        .filter_map(|entry| entry.ok())
+        .map(|entry| entry.path())
+        .filter(|file_path| {
+            file_path.is_file()
+                && file_path
+                    .extension()
+                    .and_then(|ext| ext.to_str())
+                    .map(|ext| ext.eq_ignore_ascii_case("json"))
+                    == Some(true)
+        })
+        .collect();
+    entries.sort();
+
+    let mut parsed: Vec<DataEntry> = Vec::with_capacity(entries.len());
+    for file_path in entries {
+        let name = file_path
+            .file_stem()
+            .map(|stem| stem.to_string_lossy().to_string())
+            .unwrap_or_else(|| "N/A".to_string());
+        let bytes = fs::read(&file_path).map_err(|e| DataError::Io(e.to_string()))?;
+        parsed.push(parse_data(&name, &bytes)?);
+    }
+    Ok(parsed.into_iter().map(register_data).collect())
+}
+
+pub fn data_entry(tier: usize) -> Result<Arc<DataEntry>, DataError> {
+    REGISTRY.read().unwrap().entry(tier)
+}
+
+pub fn tier_by_name(name: &str) -> Result<usize, DataError> {
+    REGISTRY.read().unwrap().tier_by_name(name)
+}
+
+pub fn list_data() -> Vec<DataEntryInfo> {
+    REGISTRY.read().unwrap().list()
+}
+
+/// Accessors go through this, an unknown tier at this point is a bug (payloads are checked in resolve_tier)
+pub fn data(tier: usize) -> Arc<DataEntry> {
+    data_entry(tier).unwrap_or_else(|e| panic!("{}", e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn runtime_data_round_trip() {
+        // a local registry so that other tests reading the global one can't see this
+        let mut registry = Registry::bundled();
+        let bundled = include_str!("./T4 June 2026.json");
+        let tier = registry
+            .register_bytes("test patch", bundled.as_bytes())
+            .unwrap();
+        assert!(tier >= 2);
+        assert_eq!(registry.tier_by_name("test patch"), Ok(tier));
+        assert_eq!(
+            registry.entry(tier).unwrap().checksum,
+            registry.entry(0).unwrap().checksum
+        );
+        // same name again replaces rather than appends
+        assert_eq!(
+            registry.register_bytes("test patch", bundled.as_bytes()),
+            Ok(tier)
+        );
+        assert_eq!(registry.list().len(), tier + 1);
+        assert_eq!(
+            tier_by_name("test patch"),
+            Err(DataError::UnknownName("test patch".to_owned()))
+        );
+
+        assert!(matches!(
+            registry.register_bytes("broken", b"{\"NORMAL_CHANCES\": [1.0]}"),
+            Err(DataError::Parse { .. })
+        ));
+
+        let mut value: serde_json::Value = serde_json::from_str(bundled).unwrap();
+        value["NORMAL_CHANCES"][3] = serde_json::json!(1.5);
+        assert!(matches!(
+            registry.register_bytes("bad chance", value.to_string().as_bytes()),
+            Err(DataError::Invalid { .. })
+        ));
+        assert_eq!(
+            registry.tier_by_name("bad chance"),
+            Err(DataError::UnknownName("bad chance".to_owned()))
+        );
+    }
+}
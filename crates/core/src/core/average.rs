@@ -1,5 +1,7 @@
 use crate::constants::{SPECIAL_TOL, TreatmentsType};
 use crate::helpers::distribute_budgets;
+use crate::market_depth;
+use crate::parser::ThreshPrices;
 use crate::performance::Performance;
 use crate::state_bundle::StateBundle;
 
@@ -22,37 +24,64 @@ impl StateBundle {
         mean
     }
 
-    /// Special case of ui_average_gold_metric, just separating them to keep things clean
-    ///
-    /// See Saddlepoint Approximation.pdf for more info on the math.
-    pub fn optimizer_average_gold_metric(&mut self, performance: &mut Performance) -> f64 {
-        self.update_prob_dist();
-        self.update_cost_dist();
-        self.compute_special_probs(false);
-        performance.states_evaluated += 1;
-
-        let mut total_gold: f64 = 0.0;
+    /// Average gold left of a single material type, summed over skip counts.
+    /// Assumes update_prob_dist, update_cost_dist and compute_special_probs have been called
+    pub fn one_material_average_gold(
+        &self,
+        support_index: usize,
+        thresh_price_pairs: &[(f64, f64)],
+        performance: &mut Performance,
+    ) -> f64 {
+        let mut out: f64 = 0.0;
         for (skip_count, &special_prob) in
             self.special_cache[&self.special_state].iter().enumerate()
         {
             if special_prob < SPECIAL_TOL {
                 continue;
             }
-            for (support_index, thresh_price_pairs) in
-                self.prep_output.optimizer_material_info.iter().enumerate()
-            {
-                let this_avg: f64 = self.one_dimension_average_gold(
+            out += special_prob
+                * self.one_dimension_average_gold(
                     support_index as i64,
                     skip_count,
                     thresh_price_pairs,
                     performance,
                 );
-
-                total_gold += special_prob * this_avg;
-            }
         }
+        out
+    }
+
+    /// one_material_average_gold of every material type, valued by thresh_prices (anything shaped like distribute_budgets' output)
+    pub fn material_average_golds(
+        &self,
+        thresh_prices: &ThreshPrices,
+        performance: &mut Performance,
+    ) -> Vec<f64> {
+        thresh_prices
+            .iter()
+            .enumerate()
+            .map(|(support_index, thresh_price_pairs)| {
+                self.one_material_average_gold(support_index, thresh_price_pairs, performance)
+            })
+            .collect()
+    }
 
-        total_gold
+    /// Sum of material_average_golds, same assumptions
+    pub fn average_gold(&self, thresh_prices: &ThreshPrices, performance: &mut Performance) -> f64 {
+        self.material_average_golds(thresh_prices, performance)
+            .iter()
+            .sum()
+    }
+
+    /// Special case of ui_average_gold_metric, just separating them to keep things clean
+    ///
+    /// See Saddlepoint Approximation.pdf for more info on the math.
+    pub fn optimizer_average_gold_metric(&mut self, performance: &mut Performance) -> f64 {
+        self.update_prob_dist();
+        self.update_cost_dist();
+        self.compute_special_probs(false);
+        performance.states_evaluated += 1;
+
+        self.average_gold(&self.prep_output.optimizer_material_info, performance)
     }
 
     /// See Saddlepoint Approximation.pdf for more info on the math.
@@ -67,7 +96,7 @@ impl StateBundle {
         performance.states_evaluated += 1;
 
         let treatment_arr: &Vec<TreatmentsType> = if inp_treatment_arr.is_none() {
-            &vec![self.prep_output.optimizer_plan.clone().try_into().unwrap()]
+            &vec![self.prep_output.optimizer_plan.clone()]
         } else {
             &inp_treatment_arr.unwrap().to_vec()
         };
@@ -77,32 +106,25 @@ impl StateBundle {
             vec![0.0; self.prep_output.juice_info.total_num_avail];
         let mut metrics_arr: Vec<f64> = vec![0.0; treatment_arr.len()];
         for (treat_index, treatment) in treatment_arr.iter().enumerate() {
-            for (skip_count, &special_prob) in
-                self.special_cache[&self.special_state].iter().enumerate()
+            let golds: Vec<f64> = self.material_average_golds(
+                &distribute_budgets(&self.prep_output.raw_material_info, treatment),
+                performance,
+            );
+            metrics_arr[treat_index] = golds.iter().sum();
+            gold_breakdown[treat_index][..golds.len()].copy_from_slice(&golds);
+        }
+        for (skip_count, &special_prob) in
+            self.special_cache[&self.special_state].iter().enumerate()
+        {
+            if special_prob < SPECIAL_TOL {
+                continue;
+            }
+            for (support_index, average) in average_breakdown
+                .iter_mut()
+                .take(self.prep_output.raw_material_info.len())
+                .enumerate()
             {
-                if special_prob < SPECIAL_TOL {
-                    continue;
-                }
-                for (support_index, thresh_price_pairs) in
-                    distribute_budgets(&self.prep_output.raw_material_info, treatment)
-                        .iter()
-                        .enumerate()
-                {
-                    let this_avg: f64 = self.one_dimension_average_gold(
-                        support_index as i64,
-                        skip_count,
-                        thresh_price_pairs,
-                        performance,
-                    );
-                    let this = special_prob * this_avg;
-
-                    gold_breakdown[treat_index][support_index] += this;
-                    metrics_arr[treat_index] += this;
-                    if treat_index == 0 {
-                        average_breakdown[support_index] +=
-                            special_prob * self.simple_avg(support_index as i64, skip_count)
-                    }
-                }
+                *average += special_prob * self.simple_avg(support_index as i64, skip_count);
             }
         }
         // for y in gold_breakdown.iter_mut() {
@@ -127,8 +149,14 @@ impl StateBundle {
         let num_thresholds: usize = thresh_price_pairs.len();
 
         let simple_mean: f64 = self.simple_avg(support_index, skip_count);
-
-        if num_thresholds == 1 {
+        let depth: &[(f64, f64)] = usize::try_from(support_index)
+            .ok()
+            .and_then(|x| self.prep_output.market_depth.get(x))
+            .map_or(&[], |x| x.as_slice());
+
+        // distribute_budgets always starts at 0, so this is nothing owned and everything bought.
+        // Leftovers are never valued at the buy price, they get their own segment priced at what they'd sell for
+        if num_thresholds == 1 && depth.is_empty() {
             return thresh_price_pairs[0].1 * (thresh_price_pairs[0].0 - simple_mean);
         }
 
@@ -162,6 +190,28 @@ impl StateBundle {
 
             out += (prev_price - price) * (thresh * prob - biased_prob * simple_mean);
         }
+
+        // market depth steps are past everything owned, E[(X - t)^+] = mean - t + E[(t - X)^+]
+        for (amount, extra) in market_depth::steps(last_price, depth) {
+            let thresh: f64 = last_thresh + amount;
+            let biased_prob: f64 = self.saddlepoint_approximation_wrapper(
+                support_index,
+                skip_count,
+                thresh,
+                true,
+                simple_mean_log,
+                performance,
+            );
+            let prob: f64 = self.saddlepoint_approximation_wrapper(
+                support_index,
+                skip_count,
+                thresh,
+                false,
+                f64::NAN,
+                performance,
+            );
+            out -= extra * (simple_mean - thresh + thresh * prob - biased_prob * simple_mean);
+        }
         return out;
     }
 }
@@ -2,6 +2,7 @@ pub mod average;
 mod bound;
 mod brute;
 mod cumulants;
+mod robust;
 mod root_finder;
 mod saddlepoint_approximation;
 mod special;
@@ -0,0 +1,90 @@
+//! Evaluates the average gold metric over several price scenarios, so that the plan isn't tuned to one day's market snapshot
+//!
+//! Each scenario gets its own distribute_budgets (done in PreparationOutput), the distributions themselves don't change between scenarios
+//! so this is just optimizer_average_gold_metric repeated with different thresh_price_pairs.
+use crate::performance::Performance;
+use crate::state_bundle::StateBundle;
+
+impl StateBundle {
+    /// Average gold of this state under every price scenario, in the same order as prep_output.scenario_material_info
+    pub fn scenario_average_golds(&mut self, performance: &mut Performance) -> Vec<f64> {
+        self.update_prob_dist();
+        self.update_cost_dist();
+        self.compute_special_probs(false);
+        performance.states_evaluated += 1;
+
+        self.prep_output
+            .scenario_material_info
+            .iter()
+            .map(|(_, material_info)| self.average_gold(material_info, performance))
+            .collect()
+    }
+
+    /// Weighted average across scenarios (weights are normalized in PreparationOutput)
+    pub fn expected_scenario_gold_metric(&mut self, performance: &mut Performance) -> f64 {
+        let golds = self.scenario_average_golds(performance);
+        self.prep_output
+            .scenario_material_info
+            .iter()
+            .zip(golds)
+            .map(|((weight, _), gold)| weight * gold)
+            .sum()
+    }
+
+    /// The metric is gold left over (higher is better), so the worst case is the minimum
+    pub fn worst_scenario_gold_metric(&mut self, performance: &mut Performance) -> f64 {
+        self.scenario_average_golds(performance)
+            .into_iter()
+            .fold(f64::INFINITY, f64::min)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::helpers::test_cases_dir;
+    use crate::model::PayloadError;
+    use crate::parser::PriceScenario;
+    use crate::payload::parse_to_payloads;
+    use crate::performance::Performance;
+    use crate::state_bundle::StateBundle;
+
+    #[test]
+    fn scenarios_bracket_point_estimate() {
+        let (_, mut payload) = parse_to_payloads(&test_cases_dir().join("payloads"))
+            .into_iter()
+            .find(|(name, _)| name == "single_+25")
+            .unwrap();
+        let mut performance = Performance::new();
+        let point = StateBundle::init_from_payload(payload.clone())
+            .unwrap()
+            .optimizer_average_gold_metric(&mut performance);
+
+        let num_mats = payload.material_info.len();
+        assert!(PriceScenario::from_ranges(&vec![(0.8, 1.2); num_mats], 0).is_err());
+        payload.price_scenarios =
+            Some(PriceScenario::from_ranges(&vec![(0.8, 1.2); num_mats], 3).unwrap());
+        let mut state_bundle = StateBundle::init_from_payload(payload.clone()).unwrap();
+        let golds = state_bundle.scenario_average_golds(&mut performance);
+        let expected = state_bundle.expected_scenario_gold_metric(&mut performance);
+        let worst = state_bundle.worst_scenario_gold_metric(&mut performance);
+
+        // the metric is linear in prices when thresholds don't move, so the middle scenario is the point estimate
+        assert!((golds[1] - point).abs() < 1e-6 * point.abs().max(1.0));
+        assert!((expected - point).abs() < 1e-6 * point.abs().max(1.0));
+        assert!(worst <= expected);
+
+        let mut short = payload.clone();
+        short.price_scenarios.as_mut().unwrap()[0].multipliers.pop();
+        assert!(matches!(
+            StateBundle::init_from_payload(short),
+            Err(PayloadError::BadPriceScenario(_))
+        ));
+        for scenario in payload.price_scenarios.as_mut().unwrap() {
+            scenario.weight = 0.0;
+        }
+        assert!(matches!(
+            StateBundle::init_from_payload(payload),
+            Err(PayloadError::BadPriceScenario(_))
+        ));
+    }
+}
@@ -70,10 +70,8 @@ impl StateBundle {
             .take(self.special_invalid_index.unwrap())
             .count();
         if m == 0 {
-            let mut out = vec![0.0; self.special_state.len()];
-            if !out.is_empty() {
-                out[0] = 1.0;
-            }
+            let mut out = vec![0.0; self.special_state.len().max(1)]; // nothing to hone still has the 0 skipped case
+            out[0] = 1.0;
 
             if preserve_tail {
                 return Some(out);
@@ -267,3 +265,23 @@ fn eliminate_tail(range: Range<usize>, actual_out: &mut Vec<f64>) {
         actual_out[index] = 0.0;
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::helpers::test_cases_dir;
+    use crate::payload::parse_to_payloads;
+    use crate::state_bundle::StateBundle;
+
+    #[test]
+    fn nothing_to_hone_skips_nothing() {
+        let (_, payload) = parse_to_payloads(&test_cases_dir().join("payloads"))
+            .into_iter()
+            .find(|(x, _)| x == "2122")
+            .unwrap();
+        // used to be an empty vec, which left skip count 0 with no probability at all
+        let mut empty = StateBundle::init_from_payload(payload).unwrap().subset(&[]);
+        assert_eq!(empty.compute_special_probs(true), Some(vec![1.0]));
+        empty.compute_special_probs(false);
+        assert_eq!(empty.special_probs(), &vec![1.0]);
+    }
+}
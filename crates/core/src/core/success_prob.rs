@@ -55,7 +55,7 @@ impl StateBundle {
             for treatment_plan in 0..effective_budget.len() {
                 prob_leftover[treatment_plan].push(self.one_dimension_prob(
                     support_index as i64,
-                    effective_budget[treatment_plan].0,
+                    effective_budget[treatment_plan].owned,
                     &mut dummy_performance,
                 ));
             }
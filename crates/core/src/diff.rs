@@ -0,0 +1,369 @@
+//! Explains why two bundles (two payloads, or two plans for the same payload) end up with different costs.
+//!
+//! Upgrades are matched by (piece, normal or adv, upgrade_index), not by position in upgrade_arr.
+//! The per-material delta is split by evaluating a hybrid: a's inputs with b's plan (juice, special order, treatment).
+//! a -> hybrid is the part caused by the plan and hybrid -> b is the part caused by the inputs, so the two add up to the total.
+//! The hybrid only exists if both bundles have the same upgrades with the same number of taps, otherwise there's only the total.
+//! The per-material split is in average gold, which only adds up to the metric for metric_type 1, so with the scenario
+//! metrics (2 and 3) there's no material breakdown at all, just the two metrics.
+use crate::helpers::encode_one_positions;
+use crate::instructions::upgrade_label;
+use crate::model::MaterialRow;
+use crate::performance::Performance;
+use crate::state_bundle::StateBundle;
+use crate::upgrade::Upgrade;
+use serde::Serialize;
+
+#[derive(Debug, Clone, Serialize, PartialEq)]
+pub struct InputChange {
+    pub what: String,
+    pub a: String,
+    pub b: String,
+}
+
+#[derive(Debug, Clone, Serialize, PartialEq)]
+pub struct UpgradeDiff {
+    pub label: String,
+    pub a: Option<String>, // encode_one_positions of the state, None if a doesn't have this upgrade
+    pub b: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, PartialEq)]
+pub struct MaterialDelta {
+    pub row: usize,
+    pub label: String,
+    pub total: f64, // b - a, average gold left
+    pub from_plan: Option<f64>,
+    pub from_inputs: Option<f64>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct BundleDiff {
+    pub inputs: Vec<InputChange>,
+    pub upgrades: Vec<UpgradeDiff>, // only the ones that differ
+    pub optimizer_plan: Option<(Vec<usize>, Vec<usize>)>, // if the treatment plan differs
+    pub special_order: Option<(Vec<String>, Vec<String>)>, // if the order of special leaps differs
+    pub materials: Vec<MaterialDelta>, // average gold per material row, empty unless both metric_types are 1
+    pub metric_a: f64,
+    pub metric_b: f64,
+}
+
+fn upgrade_key(upgrade: &Upgrade) -> (usize, bool, usize) {
+    (
+        upgrade.piece_type,
+        upgrade.is_normal_honing,
+        upgrade.upgrade_index,
+    )
+}
+
+fn row_label(row: usize, num_juice_avail: usize) -> String {
+    match MaterialRow::from_index(row, num_juice_avail) {
+        Some(MaterialRow::Base(material)) => format!("{:?}", material),
+        Some(MaterialRow::Juice { id, weapon }) => {
+            format!("{} juice {}", if weapon { "weapon" } else { "armor" }, id.0)
+        }
+        None => format!("row {}", row),
+    }
+}
+
+impl StateBundle {
+    /// Average gold left per material row, these add up to optimizer_average_gold_metric
+    pub fn material_golds(&mut self, performance: &mut Performance) -> Vec<f64> {
+        self.update_prob_dist();
+        self.update_cost_dist();
+        self.compute_special_probs(false);
+        performance.states_evaluated += 1;
+
+        if self.upgrade_arr.is_empty() {
+            return vec![0.0; self.prep_output.optimizer_material_info.len()];
+        }
+        self.material_average_golds(&self.prep_output.optimizer_material_info, performance)
+    }
+
+    /// Index in other.upgrade_arr of the same upgrade, for every upgrade in self
+    fn match_upgrades(&self, other: &StateBundle) -> Vec<Option<usize>> {
+        self.upgrade_arr
+            .iter()
+            .map(|upgrade| {
+                other
+                    .upgrade_arr
+                    .iter()
+                    .position(|x| upgrade_key(x) == upgrade_key(upgrade))
+            })
+            .collect()
+    }
+
+    /// self's inputs with other's plan, None if the plan doesn't fit
+    fn with_plan_of(&self, other: &StateBundle) -> Option<StateBundle> {
+        if self.upgrade_arr.len() != other.upgrade_arr.len()
+            || self.prep_output.raw_num_breakpoints != other.prep_output.raw_num_breakpoints
+        {
+            return None;
+        }
+        let matched: Vec<usize> = self
+            .match_upgrades(other)
+            .into_iter()
+            .collect::<Option<_>>()?;
+        let mut out: StateBundle = self.clone();
+        for (upgrade, &o_index) in out.upgrade_arr.iter_mut().zip(matched.iter()) {
+            let state = &other.upgrade_arr[o_index].state;
+            if state.len() != upgrade.state.len() {
+                return None;
+            }
+            upgrade.state.update_payload(state.payload.clone());
+        }
+        out.special_state = other
+            .special_state
+            .iter()
+            .map(|&o_index| matched.iter().position(|x| *x == o_index).unwrap())
+            .collect();
+        out.prep_output
+            .set_optimizer_plan(other.prep_output.optimizer_plan.clone());
+        out.special_cache.clear();
+        out.latest_special_probs = None;
+        Some(out)
+    }
+}
+
+fn input_changes(a: &StateBundle, b: &StateBundle) -> Vec<InputChange> {
+    let mut out: Vec<InputChange> = Vec::new();
+    let mut push = |what: String, x: String, y: String| {
+        if x != y {
+            out.push(InputChange { what, a: x, b: y });
+        }
+    };
+    let (pa, pb) = (&a.prep_output, &b.prep_output);
+    push(
+        "special_budget".to_owned(),
+        pa.special_budget.to_string(),
+        pb.special_budget.to_string(),
+    );
+    push(
+        "metric_type".to_owned(),
+        a.metric_type.to_string(),
+        b.metric_type.to_string(),
+    );
+    push(
+        "material rows x sources".to_owned(),
+        format!("{}x{}", pa.raw_material_info.len(), pa.raw_num_breakpoints),
+        format!("{}x{}", pb.raw_material_info.len(), pb.raw_num_breakpoints),
+    );
+    let num_juice_avail = pa.juice_info.num_juice_avail;
+    for (row, (ra, rb)) in pa
+        .raw_material_info
+        .iter()
+        .zip(pb.raw_material_info.iter())
+        .enumerate()
+    {
+        let label = row_label(row, num_juice_avail);
+        for (column, (ta, tb)) in ra.iter().zip(rb.iter()).enumerate() {
+            push(
+                format!("{} [{}] owned", label, column),
+                ta.owned.to_string(),
+                tb.owned.to_string(),
+            );
+            push(
+                format!("{} [{}] buy", label, column),
+                ta.buy.to_string(),
+                tb.buy.to_string(),
+            );
+            push(
+                format!("{} [{}] leftover value", label, column),
+                ta.leftover_value().to_string(),
+                tb.leftover_value().to_string(),
+            );
+        }
+        push(
+            format!("{} market depth", label),
+            format!("{:?}", pa.market_depth.get(row)),
+            format!("{:?}", pb.market_depth.get(row)),
+        );
+    }
+    push(
+        "price scenarios".to_owned(),
+        format!("{:?}", pa.price_scenarios),
+        format!("{:?}", pb.price_scenarios),
+    );
+
+    // tier and events show up as different chances and costs
+    for (upgrade, b_index) in a.upgrade_arr.iter().zip(a.match_upgrades(b)) {
+        let Some(b_index) = b_index else {
+            continue;
+        };
+        let other = &b.upgrade_arr[b_index];
+        let label = upgrade_label(upgrade);
+        push(
+            format!("{} artisan", label),
+            upgrade.starting_artisan.to_string(),
+            other.starting_artisan.to_string(),
+        );
+        push(
+            format!("{} taps so far", label),
+            upgrade.starting_num_taps.to_string(),
+            other.starting_num_taps.to_string(),
+        );
+        push(
+            format!("{} unlocked", label),
+            upgrade.unlocked.to_string(),
+            other.unlocked.to_string(),
+        );
+        push(
+            format!("{} base chance", label),
+            upgrade.base_chance.to_string(),
+            other.base_chance.to_string(),
+        );
+        push(
+            format!("{} costs", label),
+            format!("{:?}", upgrade.costs),
+            format!("{:?}", other.costs),
+        );
+        push(
+            format!("{} special cost", label),
+            upgrade.special_cost.to_string(),
+            other.special_cost.to_string(),
+        );
+    }
+    out
+}
+
+fn upgrade_diffs(a: &StateBundle, b: &StateBundle) -> Vec<UpgradeDiff> {
+    let mut out: Vec<UpgradeDiff> = Vec::new();
+    for (upgrade, b_index) in a.upgrade_arr.iter().zip(a.match_upgrades(b)) {
+        let code_a = encode_one_positions(&upgrade.state);
+        let code_b = b_index.map(|x| encode_one_positions(&b.upgrade_arr[x].state));
+        if code_b.as_ref() != Some(&code_a) {
+            out.push(UpgradeDiff {
+                label: upgrade_label(upgrade),
+                a: Some(code_a),
+                b: code_b,
+            });
+        }
+    }
+    for (upgrade, a_index) in b.upgrade_arr.iter().zip(b.match_upgrades(a)) {
+        if a_index.is_none() {
+            out.push(UpgradeDiff {
+                label: upgrade_label(upgrade),
+                a: None,
+                b: Some(encode_one_positions(&upgrade.state)),
+            });
+        }
+    }
+    out
+}
+
+/// Average gold per material row of a, b and the hybrid (a's inputs with b's plan), see the top of the file
+fn material_deltas(
+    a: &StateBundle,
+    b: &StateBundle,
+    performance: &mut Performance,
+) -> Vec<MaterialDelta> {
+    let golds_a = a.clone().material_golds(performance);
+    let golds_b = b.clone().material_golds(performance);
+    let golds_hybrid: Option<Vec<f64>> = (golds_a.len() == golds_b.len())
+        .then(|| a.with_plan_of(b))
+        .flatten()
+        .map(|mut x| x.material_golds(performance));
+
+    let num_juice_avail = a.prep_output.juice_info.num_juice_avail;
+    (0..golds_a.len().max(golds_b.len()))
+        .map(|row| {
+            let ga = golds_a.get(row).copied().unwrap_or(0.0);
+            let gb = golds_b.get(row).copied().unwrap_or(0.0);
+            let gh = golds_hybrid.as_ref().map(|x| x[row]);
+            MaterialDelta {
+                row,
+                label: row_label(row, num_juice_avail),
+                total: gb - ga,
+                from_plan: gh.map(|x| x - ga),
+                from_inputs: gh.map(|x| gb - x),
+            }
+        })
+        .collect()
+}
+
+/// What changed from a to b, the metrics are recomputed so neither needs to be evaluated beforehand
+pub fn diff(a: &StateBundle, b: &StateBundle, performance: &mut Performance) -> BundleDiff {
+    let special_labels = |x: &StateBundle| -> Vec<String> {
+        x.special_state
+            .iter()
+            .map(|&u_index| upgrade_label(&x.upgrade_arr[u_index]))
+            .collect()
+    };
+    let (order_a, order_b) = (special_labels(a), special_labels(b));
+
+    BundleDiff {
+        inputs: input_changes(a, b),
+        upgrades: upgrade_diffs(a, b),
+        optimizer_plan: (a.prep_output.optimizer_plan != b.prep_output.optimizer_plan).then(|| {
+            (
+                a.prep_output.optimizer_plan.clone(),
+                b.prep_output.optimizer_plan.clone(),
+            )
+        }),
+        special_order: (order_a != order_b).then_some((order_a, order_b)),
+        materials: if a.metric_type == 1 && b.metric_type == 1 {
+            material_deltas(a, b, performance)
+        } else {
+            Vec::new()
+        },
+        metric_a: a.clone().metric_router(performance),
+        metric_b: b.clone().metric_router(performance),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::helpers::test_cases_dir;
+    use crate::payload::parse_to_payloads;
+
+    #[test]
+    fn plan_and_input_parts_add_up() {
+        let (_, payload) = parse_to_payloads(&test_cases_dir().join("payloads"))
+            .into_iter()
+            .find(|(name, _)| name == "2122")
+            .unwrap();
+        let mut performance = Performance::new();
+        let a = StateBundle::init_from_payload(payload.clone()).unwrap();
+
+        // same inputs, different plan
+        let mut b = a.clone();
+        b.special_state.reverse();
+        let first = &mut b.upgrade_arr[0].state;
+        let flipped: Vec<(bool, usize)> = first.iter().map(|&(juice, id)| (!juice, id)).collect();
+        first.update_payload(flipped);
+        let plan_only = diff(&a, &b, &mut performance);
+        assert!(plan_only.inputs.is_empty());
+        assert_eq!(plan_only.upgrades.len(), 1);
+        assert!(plan_only.special_order.is_some());
+        for material in plan_only.materials.iter() {
+            assert!((material.from_plan.unwrap() - material.total).abs() < 1e-6);
+            assert!(material.from_inputs.unwrap().abs() < 1e-6);
+        }
+
+        // different inputs, plan carried over
+        let mut richer = payload;
+        richer.special_budget += 1000;
+        richer.special_state = Some(b.special_state.clone());
+        for (input, upgrade) in richer.upgrade_info.iter_mut().zip(b.upgrade_arr.iter()) {
+            input.state = Some(upgrade.state.payload.clone());
+        }
+        let c = StateBundle::init_from_payload(richer).unwrap();
+        let both = diff(&a, &c, &mut performance);
+        assert_eq!(both.inputs.len(), 1);
+        assert_eq!(both.inputs[0].what, "special_budget");
+        let total: f64 = both.materials.iter().map(|x| x.total).sum();
+        assert!((total - (both.metric_b - both.metric_a)).abs() < 1e-6 * both.metric_a.abs());
+        for material in both.materials.iter() {
+            let split = material.from_plan.unwrap() + material.from_inputs.unwrap();
+            assert!((split - material.total).abs() < 1e-6 * (1.0 + material.total.abs()));
+        }
+
+        // average gold per material doesn't add up to a scenario metric, so there's no breakdown
+        let mut scenario = c.clone();
+        scenario.metric_type = 2;
+        let scenario_diff = diff(&a, &scenario, &mut performance);
+        assert!(scenario_diff.materials.is_empty());
+        assert!((scenario_diff.metric_b - both.metric_b).abs() < 1e-6 * both.metric_b.abs());
+    }
+}
@@ -0,0 +1,297 @@
+//! Brute force over the whole (streak restricted) state space, only feasible for one or two upgrades.
+//!
+//! Same state space as the optimizer: juice and books are each a streak from the start plus a streak from the end,
+//! adv upgrades pick every value of every slot, and the special order goes through every permutation if there's any special budget.
+//! This is the ground truth the optimizer gets tested against, not something the website should ever call.
+use crate::advanced_honing::utils::MAX_ADV_STATE;
+use crate::constants::juice_info::JuiceInfo;
+use crate::performance::Performance;
+use crate::state_bundle::{StateBundle, StateEssence};
+use crate::upgrade::Upgrade;
+use std::fmt;
+
+pub const MAX_EXACT_STATES: u128 = 5_000_000;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ExactError {
+    TooLarge { count: u128, max: u128 },
+}
+
+impl fmt::Display for ExactError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ExactError::TooLarge { count, max } => write!(
+                f,
+                "{} states to enumerate, more than the max of {}",
+                count, max
+            ),
+        }
+    }
+}
+
+impl std::error::Error for ExactError {}
+
+/// Every prefix + suffix streak of this length, each one exactly once (so all true only shows up once)
+fn streak_masks(len: usize) -> Vec<Vec<bool>> {
+    let mut out: Vec<Vec<bool>> = Vec::with_capacity(len * (len + 1) / 2 + 1);
+    for count in 0..len {
+        for prefix in 0..=count {
+            let suffix = count - prefix;
+            out.push((0..len).map(|i| i < prefix || i >= len - suffix).collect());
+        }
+    }
+    out.push(vec![true; len]);
+    out
+}
+
+/// Every state this upgrade can take in the optimizer
+fn upgrade_candidates(upgrade: &Upgrade, juice_info: &JuiceInfo) -> Vec<Vec<(bool, usize)>> {
+    let len = upgrade.state.len();
+    if !upgrade.is_normal_honing {
+        let mut out: Vec<Vec<(bool, usize)>> = vec![upgrade.state.payload.clone()];
+        for slot in 0..len {
+            out = out
+                .into_iter()
+                .flat_map(|x| {
+                    (0..=MAX_ADV_STATE).map(move |val| {
+                        let mut new = x.clone();
+                        new[slot].1 = val;
+                        new
+                    })
+                })
+                .collect();
+        }
+        return out;
+    }
+
+    // same as perturb_normal, one book id and nothing to do below +3
+    let Some(&book_id) = juice_info.normal_uindex_to_id[upgrade.upgrade_index].last() else {
+        return vec![upgrade.state.payload.clone()];
+    };
+    let juice_masks = streak_masks(len);
+    let book_masks = if book_id != 0 {
+        streak_masks(len)
+    } else {
+        vec![vec![false; len]]
+    };
+    juice_masks
+        .iter()
+        .flat_map(|juice| {
+            book_masks.iter().map(move |book| {
+                juice
+                    .iter()
+                    .zip(book.iter())
+                    .map(|(j, b)| (*j, if *b { book_id } else { 0 }))
+                    .collect()
+            })
+        })
+        .collect()
+}
+
+fn permutations(n: usize) -> Vec<Vec<usize>> {
+    if n == 0 {
+        return vec![Vec::new()];
+    }
+    let mut out: Vec<Vec<usize>> = Vec::new();
+    for smaller in permutations(n - 1) {
+        for pos in 0..n {
+            let mut new = smaller.clone();
+            new.insert(pos, n - 1);
+            out.push(new);
+        }
+    }
+    out
+}
+
+fn special_orders(state_bundle: &StateBundle) -> Vec<Vec<usize>> {
+    if state_bundle.prep_output.special_budget > 0 {
+        permutations(state_bundle.upgrade_arr.len())
+    } else {
+        vec![state_bundle.special_state.clone()]
+    }
+}
+
+fn candidate_count(upgrade: &Upgrade, juice_info: &JuiceInfo) -> u128 {
+    let len = upgrade.state.len() as u128;
+    let streaks = len * (len + 1) / 2 + 1;
+    if !upgrade.is_normal_honing {
+        return (MAX_ADV_STATE as u128 + 1).saturating_pow(len as u32);
+    }
+    match juice_info.normal_uindex_to_id[upgrade.upgrade_index].last() {
+        None => 1,
+        Some(0) => streaks,
+        Some(_) => streaks * streaks,
+    }
+}
+
+/// How many metric evaluations exact_solve would do, without building anything
+pub fn exact_state_count(state_bundle: &StateBundle) -> u128 {
+    let orders: u128 = if state_bundle.prep_output.special_budget > 0 {
+        (1..=state_bundle.upgrade_arr.len() as u128).fold(1, |acc, x| acc.saturating_mul(x))
+    } else {
+        1
+    };
+    state_bundle
+        .upgrade_arr
+        .iter()
+        .map(|x| candidate_count(x, &state_bundle.prep_output.juice_info))
+        .fold(orders, |acc, x| acc.saturating_mul(x))
+}
+
+/// Returns the best state with its metric, or an error if there's more than max_states to go through
+pub fn exact_solve(
+    mut state_bundle: StateBundle,
+    max_states: u128,
+    performance: &mut Performance,
+) -> Result<StateBundle, ExactError> {
+    let count = exact_state_count(&state_bundle);
+    if count > max_states {
+        return Err(ExactError::TooLarge {
+            count,
+            max: max_states,
+        });
+    }
+    let candidates: Vec<Vec<Vec<(bool, usize)>>> = state_bundle
+        .upgrade_arr
+        .iter()
+        .map(|x| upgrade_candidates(x, &state_bundle.prep_output.juice_info))
+        .collect();
+    let orders = special_orders(&state_bundle);
+
+    let mut digits: Vec<usize> = vec![0; candidates.len()];
+    for (upgrade, c) in state_bundle.upgrade_arr.iter_mut().zip(candidates.iter()) {
+        upgrade.state.update_payload(c[0].clone());
+    }
+    let mut best: Option<(StateEssence, f64)> = None;
+    loop {
+        for order in orders.iter() {
+            state_bundle.special_state.clone_from(order);
+            let metric = state_bundle.metric_router(performance);
+            if best.as_ref().is_none_or(|(_, b)| metric > *b) {
+                best = Some((state_bundle.to_essence(), metric));
+            }
+        }
+
+        // mixed radix increment, only the upgrades whose digit changed get a new state (and a new hash)
+        let mut i = 0;
+        while i < digits.len() {
+            digits[i] += 1;
+            if digits[i] < candidates[i].len() {
+                break;
+            }
+            digits[i] = 0;
+            i += 1;
+        }
+        if i == digits.len() {
+            break;
+        }
+        for j in 0..=i {
+            state_bundle.upgrade_arr[j]
+                .state
+                .update_payload(candidates[j][digits[j]].clone());
+        }
+    }
+
+    let (essence, metric) = best.unwrap();
+    state_bundle.clone_from_essence(&essence, &metric.into());
+    Ok(state_bundle)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::helpers::test_cases_dir;
+    use crate::payload::{Payload, parse_to_payloads};
+
+    fn payload(name: &str) -> Payload {
+        parse_to_payloads(&test_cases_dir().join("payloads"))
+            .into_iter()
+            .find(|(x, _)| x == name)
+            .unwrap()
+            .1
+    }
+
+    /// two_+25 moved down to +10 so both upgrades are short enough to enumerate together, with some special so the order matters
+    fn two_small() -> StateBundle {
+        let mut payload = payload("two_+25");
+        for upgrade in payload.upgrade_info.iter_mut() {
+            upgrade.upgrade_index = 9;
+        }
+        payload.special_budget = 1000;
+        StateBundle::init_from_payload(payload).unwrap()
+    }
+
+    #[test]
+    fn streak_masks_are_distinct() {
+        for len in 0..8 {
+            let mut masks = streak_masks(len);
+            assert_eq!(masks.len(), len * (len + 1) / 2 + 1);
+            masks.sort();
+            masks.dedup();
+            assert_eq!(masks.len(), len * (len + 1) / 2 + 1);
+        }
+        assert_eq!(permutations(3).len(), 6);
+    }
+
+    #[test]
+    fn exact_beats_simple_plans() {
+        let state_bundle = StateBundle::init_from_payload(payload("single_+25")).unwrap();
+        let mut performance = Performance::new();
+        let count = exact_state_count(&state_bundle);
+        let best = exact_solve(state_bundle.clone(), MAX_EXACT_STATES, &mut performance).unwrap();
+
+        for juice in [false, true] {
+            let mut simple = state_bundle.clone();
+            for upgrade in simple.upgrade_arr.iter_mut() {
+                let len = upgrade.state.len();
+                upgrade.state.update_payload(vec![(juice, 0); len]);
+            }
+            assert!(best.metric >= simple.metric_router(&mut performance));
+        }
+        assert_eq!(
+            exact_solve(state_bundle, count - 1, &mut performance).unwrap_err(),
+            ExactError::TooLarge {
+                count,
+                max: count - 1
+            }
+        );
+    }
+
+    /// needs the annealer, so only runs with `cargo test -p hf-core --features v35 solve_reaches_exact_optimum`
+    #[cfg(all(feature = "v35", not(feature = "wasm")))] // solve reports progress to js under wasm
+    #[test]
+    fn solve_reaches_exact_optimum() {
+        use crate::constants::SIMULATED_ANNEALING_DIFF_TOL;
+        use crate::helpers::my_pct_diff;
+        use crate::optimizer::solve;
+        use rand::SeedableRng;
+        use rand::rngs::StdRng;
+
+        let mut performance = Performance::new();
+        for state_bundle in [
+            StateBundle::init_from_payload(payload("single_+25")).unwrap(),
+            two_small(),
+        ] {
+            let exact = exact_solve(state_bundle.clone(), MAX_EXACT_STATES, &mut performance)
+                .unwrap()
+                .metric;
+            let mut rng = StdRng::seed_from_u64(0);
+            let annealed = solve(&mut rng, state_bundle, &mut performance).metric;
+            assert!(annealed <= exact + 1e-6 * exact.abs().max(1.0));
+            assert!(
+                my_pct_diff(annealed, exact) <= SIMULATED_ANNEALING_DIFF_TOL,
+                "annealed {} vs exact {}",
+                annealed,
+                exact
+            );
+        }
+    }
+
+    #[test]
+    fn two_upgrades_enumerate() {
+        let state_bundle = two_small();
+        assert!(exact_state_count(&state_bundle) < MAX_EXACT_STATES);
+        let best = exact_solve(state_bundle, MAX_EXACT_STATES, &mut Performance::new()).unwrap();
+        assert!(best.metric.is_finite());
+    }
+}
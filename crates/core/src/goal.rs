@@ -0,0 +1,559 @@
+//! Picks which upgrades to do from a goal ("everything +20, weapon +22" or "item level 1700") instead of a hand-made upgrade_info
+//!
+//! Every step a piece could take towards the goal is parsed once into one bundle, candidates are subsets of it (like schedule.rs).
+//! A Pieces goal already says which upgrades to do. An ItemLevel goal greedily adds whichever next step (normal or advanced, any piece)
+//! loses the least per item level according to the objective, until the target is reached.
+//! Greedy isn't optimal (a pricey step can unlock a cheap one) but steps mostly get pricier with level so it does fine.
+//! MinGold with a gold_budget only takes steps that keep the expected gold left above -gold_budget.
+//! The plan itself (juice, special leap order, treatment) is left to solve, same as for any other payload,
+//! so picking goes by the payload's own plan and solve_goal checks the budget again once it's solved.
+use crate::constants::FLOAT_TOL;
+use crate::instructions::upgrade_label;
+use crate::model::{NUM_ADV_UPGRADES, NUM_NORMAL_UPGRADES, PayloadError, Piece};
+use crate::parser::OneUpgradeInput;
+use crate::payload::Payload;
+use crate::performance::Performance;
+use crate::schedule::prerequisites_done;
+use crate::simulation::simulate;
+use crate::state_bundle::StateBundle;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+#[cfg(feature = "v35")]
+use crate::optimizer::solve;
+#[cfg(feature = "v35")]
+use crate::treatment::solve_treatments;
+#[cfg(feature = "v35")]
+use rand::Rng;
+
+fn default_per_normal() -> f64 {
+    5.0
+}
+fn default_per_adv_level() -> f64 {
+    1.0
+}
+fn default_num_samples() -> usize {
+    2000
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub enum Goal {
+    Pieces {
+        normal: Vec<usize>, // [piece_type] target +N
+        adv: Vec<usize>,    // [piece_type] target advanced honing level (0, 10, .. 40)
+    },
+    /// Item level is the average over the 6 pieces. How much a level is worth depends on the tier and on
+    /// which +N it is, the defaults are rough guesses so override them if you know better
+    ItemLevel {
+        current: f64,
+        target: f64,
+        #[serde(default = "default_per_normal")]
+        per_normal: f64, // piece item level per +1
+        #[serde(default = "default_per_adv_level")]
+        per_adv_level: f64, // piece item level per advanced honing level (so 10x this per adv upgrade)
+    },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+pub enum GoalObjective {
+    MinGold, // most gold left according to metric_type, same as solve, and within gold_budget on average if there is one
+    MaxSuccess, // most likely to not spend more than gold_budget, only changes which steps an ItemLevel goal picks
+}
+
+#[derive(Deserialize, Clone, Serialize)]
+pub struct GoalPayload {
+    pub payload: Payload, // upgrade_info and special_state get replaced by whatever gets picked
+    pub current_normal: Vec<usize>, // [piece_type] +N right now
+    pub current_adv: Vec<usize>, // [piece_type] advanced honing level right now (0, 10, .. 40)
+    pub goal: Goal,
+    pub objective: GoalObjective,
+    #[serde(default)]
+    pub gold_budget: Option<f64>, // needed for MaxSuccess, a limit on the metric for MinGold
+    #[serde(default = "default_num_samples")]
+    pub num_samples: usize, // simulations per success probability
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum GoalError {
+    WrongNumberOfPieces(usize),
+    NormalOutOfRange { piece_type: usize, plus: usize },
+    BadAdvLevel { piece_type: usize, level: usize },
+    TargetBelowCurrent { piece_type: usize },
+    Unreachable { needed: f64, possible: f64 },
+    NoBudget,
+    OverBudget(f64),
+    Payload(PayloadError),
+}
+
+impl From<PayloadError> for GoalError {
+    fn from(e: PayloadError) -> Self {
+        GoalError::Payload(e)
+    }
+}
+
+impl fmt::Display for GoalError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            GoalError::WrongNumberOfPieces(n) => write!(f, "expected 6 pieces, got {}", n),
+            GoalError::NormalOutOfRange { piece_type, plus } => write!(
+                f,
+                "piece {} can't be +{}, normal honing goes up to +{}",
+                piece_type, plus, NUM_NORMAL_UPGRADES
+            ),
+            GoalError::BadAdvLevel { piece_type, level } => write!(
+                f,
+                "piece {} can't be advanced level {}, it has to be a multiple of 10 up to {}",
+                piece_type,
+                level,
+                NUM_ADV_UPGRADES * 10
+            ),
+            GoalError::TargetBelowCurrent { piece_type } => {
+                write!(f, "piece {} is already past its target", piece_type)
+            }
+            GoalError::Unreachable { needed, possible } => write!(
+                f,
+                "goal needs {} piece item levels but only {} are left to gain",
+                needed, possible
+            ),
+            GoalError::NoBudget => write!(f, "MaxSuccess needs a gold_budget"),
+            GoalError::OverBudget(budget) => {
+                write!(
+                    f,
+                    "the goal costs more than the gold budget of {} on average",
+                    budget
+                )
+            }
+            GoalError::Payload(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl std::error::Error for GoalError {}
+
+/// The objective only decides which upgrades get picked. solve_goal's juice and special leap plan maximizes the metric
+/// like any other solve, even for MaxSuccess, and a Pieces goal has nothing to pick so the objective does nothing there.
+#[derive(Debug, Clone, Serialize)]
+pub struct GoalPlan {
+    pub state_bundle: StateBundle,
+    pub names: Vec<String>,   // picked upgrades, in upgrade_arr order
+    pub item_level_gain: f64, // average over the 6 pieces, 0 for a Pieces goal
+    pub metric: f64,
+    pub success_prob: Option<f64>, // chance of spending at most gold_budget, if there is one
+}
+
+/// Chance that the gold left is at least -budget
+pub fn success_prob(state_bundle: &StateBundle, budget: f64, num_samples: usize) -> f64 {
+    if state_bundle.upgrade_arr.is_empty() {
+        return 1.0;
+    }
+    simulate(state_bundle, 0, num_samples)
+        .filter(|x| x.gold >= -budget)
+        .count() as f64
+        / num_samples as f64
+}
+
+impl GoalPayload {
+    fn check(&self) -> Result<(), GoalError> {
+        for levels in [&self.current_normal, &self.current_adv] {
+            if levels.len() != Piece::ALL.len() {
+                return Err(GoalError::WrongNumberOfPieces(levels.len()));
+            }
+        }
+        let mut normal: Vec<usize> = self.current_normal.clone();
+        let mut adv: Vec<usize> = self.current_adv.clone();
+        if let Goal::Pieces {
+            normal: target_normal,
+            adv: target_adv,
+        } = &self.goal
+        {
+            for levels in [target_normal, target_adv] {
+                if levels.len() != Piece::ALL.len() {
+                    return Err(GoalError::WrongNumberOfPieces(levels.len()));
+                }
+            }
+            for piece_type in 0..Piece::ALL.len() {
+                if target_normal[piece_type] < normal[piece_type]
+                    || target_adv[piece_type] < adv[piece_type]
+                {
+                    return Err(GoalError::TargetBelowCurrent { piece_type });
+                }
+            }
+            normal.extend(target_normal);
+            adv.extend(target_adv);
+        }
+        for (i, &plus) in normal.iter().enumerate() {
+            if plus > NUM_NORMAL_UPGRADES {
+                return Err(GoalError::NormalOutOfRange {
+                    piece_type: i % Piece::ALL.len(),
+                    plus,
+                });
+            }
+        }
+        for (i, &level) in adv.iter().enumerate() {
+            if level % 10 != 0 || level > NUM_ADV_UPGRADES * 10 {
+                return Err(GoalError::BadAdvLevel {
+                    piece_type: i % Piece::ALL.len(),
+                    level,
+                });
+            }
+        }
+        Ok(())
+    }
+
+    /// (target +N, target advanced level) per piece that the candidates go up to
+    fn candidate_targets(&self) -> (Vec<usize>, Vec<usize>) {
+        match &self.goal {
+            Goal::Pieces { normal, adv } => (normal.clone(), adv.clone()),
+            Goal::ItemLevel {
+                current,
+                target,
+                per_normal,
+                per_adv_level,
+            } => {
+                // no single piece needs to gain more than the whole goal
+                let needed = ((target - current) * Piece::ALL.len() as f64).max(0.0);
+                let normal_steps = (needed / per_normal).ceil() as usize;
+                let adv_steps = (needed / (per_adv_level * 10.0)).ceil() as usize;
+                (
+                    self.current_normal
+                        .iter()
+                        .map(|x| (x + normal_steps).min(NUM_NORMAL_UPGRADES))
+                        .collect(),
+                    self.current_adv
+                        .iter()
+                        .map(|x| (x + adv_steps * 10).min(NUM_ADV_UPGRADES * 10))
+                        .collect(),
+                )
+            }
+        }
+    }
+
+    fn candidates(&self) -> Vec<OneUpgradeInput> {
+        let (target_normal, target_adv) = self.candidate_targets();
+        let mut out: Vec<OneUpgradeInput> = Vec::new();
+        for piece in Piece::ALL {
+            let piece_type = piece.index();
+            for upgrade_index in self.current_normal[piece_type]..target_normal[piece_type] {
+                out.push(OneUpgradeInput {
+                    piece_type,
+                    upgrade_index,
+                    is_normal_honing: true,
+                    starting_artisan: Some(0.0),
+                    starting_num_taps: Some(0),
+                    state: None,
+                    unlocked: false,
+                    adv_progress: None,
+                });
+            }
+            for upgrade_index in self.current_adv[piece_type] / 10..target_adv[piece_type] / 10 {
+                out.push(OneUpgradeInput {
+                    piece_type,
+                    upgrade_index,
+                    is_normal_honing: false,
+                    starting_artisan: None,
+                    starting_num_taps: None,
+                    state: None,
+                    unlocked: false,
+                    adv_progress: Some((0, 0, false, false)),
+                });
+            }
+        }
+        out
+    }
+
+    /// MinGold's metric has to stay above -gold_budget
+    fn within_budget(&self, score: f64) -> bool {
+        match (self.objective, self.gold_budget) {
+            (GoalObjective::MinGold, Some(budget)) => score >= -budget,
+            _ => true,
+        }
+    }
+
+    fn check_budget(&self, plan: GoalPlan) -> Result<GoalPlan, GoalError> {
+        if self.within_budget(plan.metric) {
+            Ok(plan)
+        } else {
+            Err(GoalError::OverBudget(self.gold_budget.unwrap()))
+        }
+    }
+
+    /// Higher is better, MinGold's is the metric and MaxSuccess's is the chance of staying within budget
+    fn score(&self, state_bundle: &mut StateBundle, performance: &mut Performance) -> f64 {
+        match self.objective {
+            GoalObjective::MinGold => {
+                if state_bundle.upgrade_arr.is_empty() {
+                    0.0
+                } else {
+                    state_bundle.metric_router(performance)
+                }
+            }
+            GoalObjective::MaxSuccess => {
+                success_prob(state_bundle, self.gold_budget.unwrap(), self.num_samples)
+            }
+        }
+    }
+
+    fn finish(
+        &self,
+        mut state_bundle: StateBundle,
+        item_level_gain: f64,
+        performance: &mut Performance,
+    ) -> GoalPlan {
+        let metric = if state_bundle.upgrade_arr.is_empty() {
+            0.0
+        } else {
+            state_bundle.metric_router(performance)
+        };
+        state_bundle.metric = metric;
+        GoalPlan {
+            names: state_bundle.upgrade_arr.iter().map(upgrade_label).collect(),
+            item_level_gain,
+            metric,
+            success_prob: self
+                .gold_budget
+                .map(|budget| success_prob(&state_bundle, budget, self.num_samples)),
+            state_bundle,
+        }
+    }
+}
+
+/// Picks the upgrades for the goal, with whatever optimizer_plan / special_state the payload came with
+pub fn plan_goal(
+    goal_payload: &GoalPayload,
+    performance: &mut Performance,
+) -> Result<GoalPlan, GoalError> {
+    goal_payload.check_budget(pick_upgrades(goal_payload, performance)?)
+}
+
+/// plan_goal without the final budget check, solve_goal does that on the solved plan instead
+fn pick_upgrades(
+    goal_payload: &GoalPayload,
+    performance: &mut Performance,
+) -> Result<GoalPlan, GoalError> {
+    goal_payload.check()?;
+    if goal_payload.objective == GoalObjective::MaxSuccess && goal_payload.gold_budget.is_none() {
+        return Err(GoalError::NoBudget);
+    }
+    let mut payload: Payload = goal_payload.payload.clone();
+    payload.upgrade_info = goal_payload.candidates();
+    payload.special_state = None;
+    let full: StateBundle = StateBundle::init_from_payload(payload)?;
+    let num_candidates = full.upgrade_arr.len();
+
+    let (per_normal, per_adv_level, needed) = match goal_payload.goal {
+        Goal::Pieces { .. } => {
+            let everything: Vec<usize> = (0..num_candidates).collect();
+            return Ok(goal_payload.finish(full.subset(&everything), 0.0, performance));
+        }
+        Goal::ItemLevel {
+            current,
+            target,
+            per_normal,
+            per_adv_level,
+        } => (
+            per_normal,
+            per_adv_level,
+            (target - current) * Piece::ALL.len() as f64,
+        ),
+    };
+    let gain_of = |u_index: usize| {
+        if full.upgrade_arr[u_index].is_normal_honing {
+            per_normal
+        } else {
+            per_adv_level * 10.0
+        }
+    };
+    let possible: f64 = (0..num_candidates).map(gain_of).sum();
+    if possible < needed - FLOAT_TOL {
+        return Err(GoalError::Unreachable { needed, possible });
+    }
+
+    let mut done: Vec<bool> = vec![false; num_candidates];
+    let mut chosen: Vec<usize> = Vec::new();
+    let mut gain: f64 = 0.0;
+    let mut score_now = goal_payload.score(&mut full.subset(&chosen), performance);
+    while gain < needed - FLOAT_TOL {
+        let mut best: Option<(f64, usize, f64)> = None;
+        for u_index in 0..num_candidates {
+            if done[u_index] || !prerequisites_done(&full, &done, u_index) {
+                continue;
+            }
+            let mut tentative = chosen.clone();
+            tentative.push(u_index);
+            let score = goal_payload.score(&mut full.subset(&tentative), performance);
+            if !goal_payload.within_budget(score) {
+                continue;
+            }
+            let loss_per_level = (score_now - score) / gain_of(u_index);
+            if best.is_none_or(|(b, _, _)| loss_per_level < b) {
+                best = Some((loss_per_level, u_index, score));
+            }
+        }
+        // possible >= needed so there's always something left on the frontier, unless it's all over budget
+        let Some((_, u_index, score)) = best else {
+            return Err(GoalError::OverBudget(goal_payload.gold_budget.unwrap()));
+        };
+        done[u_index] = true;
+        chosen.push(u_index);
+        gain += gain_of(u_index);
+        score_now = score;
+    }
+    chosen.sort();
+    Ok(goal_payload.finish(
+        full.subset(&chosen),
+        gain / Piece::ALL.len() as f64,
+        performance,
+    ))
+}
+
+/// plan_goal then solve (or solve_treatments if the payload lets the optimizer choose)
+#[cfg(feature = "v35")] // needs an optimizer version
+pub fn solve_goal<R: Rng>(
+    rng: &mut R,
+    goal_payload: &GoalPayload,
+    performance: &mut Performance,
+) -> Result<GoalPlan, GoalError> {
+    let picked = pick_upgrades(goal_payload, performance)?;
+    if picked.state_bundle.upgrade_arr.is_empty() {
+        return goal_payload.check_budget(picked);
+    }
+    let state_bundle = if goal_payload.payload.choose_treatment {
+        solve_treatments(rng, picked.state_bundle, performance).0
+    } else {
+        solve(rng, picked.state_bundle, performance)
+    };
+    goal_payload.check_budget(goal_payload.finish(
+        state_bundle,
+        picked.item_level_gain,
+        performance,
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::helpers::test_cases_dir;
+    use crate::payload::parse_to_payloads;
+
+    fn goal_payload(goal: Goal) -> GoalPayload {
+        let (_, payload) = parse_to_payloads(&test_cases_dir().join("payloads"))
+            .into_iter()
+            .find(|(name, _)| name == "2122")
+            .unwrap();
+        GoalPayload {
+            payload,
+            current_normal: vec![20; 6],
+            current_adv: vec![0; 6],
+            goal,
+            objective: GoalObjective::MinGold,
+            gold_budget: None,
+            num_samples: 200,
+        }
+    }
+
+    #[test]
+    fn pieces_and_item_level_goals() {
+        let mut performance = Performance::new();
+        let pieces = plan_goal(
+            &goal_payload(Goal::Pieces {
+                normal: vec![21, 21, 21, 21, 21, 22],
+                adv: vec![0, 0, 0, 0, 0, 10],
+            }),
+            &mut performance,
+        )
+        .unwrap();
+        let mut names = pieces.names.clone();
+        names.sort();
+        assert_eq!(
+            names,
+            vec![
+                "Chest +21",
+                "Glove +21",
+                "Helmet +21",
+                "Pants +21",
+                "Shoulder +21",
+                "Weapon +21",
+                "Weapon +22",
+                "Weapon adv 10",
+            ]
+        );
+
+        let mut item_level = goal_payload(Goal::ItemLevel {
+            current: 0.0,
+            target: 10.0,
+            per_normal: 5.0,
+            per_adv_level: 1.0,
+        });
+        let picked = plan_goal(&item_level, &mut performance).unwrap();
+        assert!(picked.item_level_gain >= 10.0);
+        assert!(picked.metric.is_finite());
+
+        item_level.objective = GoalObjective::MaxSuccess;
+        assert_eq!(
+            plan_goal(&item_level, &mut performance).unwrap_err(),
+            GoalError::NoBudget
+        );
+        item_level.gold_budget = Some(-picked.metric);
+        let safest = plan_goal(&item_level, &mut performance).unwrap();
+        assert!(safest.item_level_gain >= 10.0);
+        assert!(
+            safest
+                .success_prob
+                .is_some_and(|p| (0.0..=1.0).contains(&p))
+        );
+
+        item_level.objective = GoalObjective::MinGold;
+        item_level.gold_budget = Some(-picked.metric * 1.01);
+        let capped = plan_goal(&item_level, &mut performance).unwrap();
+        assert!(capped.metric >= -item_level.gold_budget.unwrap());
+        item_level.gold_budget = Some(-picked.metric * 0.5);
+        assert!(matches!(
+            plan_goal(&item_level, &mut performance),
+            Err(GoalError::OverBudget(_))
+        ));
+
+        item_level.gold_budget = None;
+        item_level.goal = Goal::ItemLevel {
+            current: 0.0,
+            target: 1000.0,
+            per_normal: 5.0,
+            per_adv_level: 1.0,
+        };
+        assert!(matches!(
+            plan_goal(&item_level, &mut performance),
+            Err(GoalError::Unreachable { .. })
+        ));
+    }
+
+    /// needs the annealer, so only runs with --features v35
+    #[cfg(all(feature = "v35", not(feature = "wasm")))] // solve reports progress to js under wasm
+    #[test]
+    fn budget_is_checked_on_the_solved_plan() {
+        use rand::SeedableRng;
+        use rand::rngs::StdRng;
+
+        let mut performance = Performance::new();
+        let mut pieces = goal_payload(Goal::Pieces {
+            normal: vec![20, 20, 20, 20, 20, 21],
+            adv: vec![0; 6],
+        });
+        let unsolved = plan_goal(&pieces, &mut performance).unwrap();
+        let solved = solve_goal(&mut StdRng::seed_from_u64(0), &pieces, &mut performance).unwrap();
+        assert!(solved.metric > unsolved.metric);
+
+        // only affordable once solve has planned the juice
+        pieces.gold_budget = Some(-(unsolved.metric + solved.metric) / 2.0);
+        assert!(matches!(
+            plan_goal(&pieces, &mut performance),
+            Err(GoalError::OverBudget(_))
+        ));
+        let within = solve_goal(&mut StdRng::seed_from_u64(0), &pieces, &mut performance).unwrap();
+        assert!(within.metric >= -pieces.gold_budget.unwrap());
+
+        pieces.gold_budget = Some(-solved.metric * 0.5);
+        assert!(matches!(
+            solve_goal(&mut StdRng::seed_from_u64(0), &pieces, &mut performance),
+            Err(GoalError::OverBudget(_))
+        ));
+    }
+}
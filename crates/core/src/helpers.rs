@@ -1,74 +1,89 @@
 use crate::constants::FLOAT_TOL;
 // use crate::my_dbg;
-use crate::parser::MaterialInput;
+use crate::parser::{MaterialInput, ThreshPrices};
 use crate::upgrade::Upgrade;
 use rand::Rng;
 use serde::Serialize;
 use std::fs::{File, OpenOptions};
 use std::io::{BufWriter, Error, Write};
+use std::path::PathBuf;
 
-/// Outputs in the form of [(threshold1, price1),(threshold2, price2) ... ]
-/// Where price(n+1) refers to the cost inccured for exceeding the owned_n
-/// This is insanely scuffed i probably could've made it more elegant but this works (i think) and i dont want to touch it
-pub fn distribute_budgets(material_info: &MaterialInput, plan: &[usize]) -> MaterialInput {
-    let mut pass_1: MaterialInput =
-        vec![vec![(0.0_f64, f64::NAN); material_info[0].len()]; material_info.len()];
-    for (support_index, thresh_price_pairs) in material_info.iter().enumerate() {
-        for (thresh_index, (owned, _)) in thresh_price_pairs.iter().enumerate() {
-            pass_1[support_index][plan[thresh_index]].0 += owned;
-            pass_1[support_index][plan[thresh_index]].1 = thresh_price_pairs[plan[thresh_index]].1;
-        }
-    }
-    // my_dbg!(&pass_1);
-    pass_1
-        .into_iter()
-        .map(|mut row| {
-            let mut cumulative: f64 = 0.0;
-            row.retain(|x| {
-                if x.1.is_nan() {
-                    assert!(x.0 == 0.0);
-                    false
-                } else {
-                    true
-                }
-            });
-            for entry in &mut row {
-                cumulative += entry.0;
-                entry.0 = cumulative;
-            }
-
-            let mut last_thresh: f64 = row[0].0;
-            let mut pass_2: Vec<(f64, f64)> = vec![row[0]];
-            for &(thresh, price) in row.iter() {
-                if (thresh - last_thresh).abs() < FLOAT_TOL {
-                    pass_2.last_mut().unwrap().1 = price;
-                } else {
-                    pass_2.push((thresh, price));
-                }
-                last_thresh = thresh;
-            }
+/// The repo's test_cases folder, no matter which directory cargo was run from
+pub fn test_cases_dir() -> PathBuf {
+    PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("../../test_cases")
+}
 
-            let mut last_price: f64 = pass_2[0].1;
-            let mut out: Vec<(f64, f64)> = vec![pass_2[0]];
-            for &(thresh, price) in pass_2.iter() {
-                if (price - last_price).abs() < FLOAT_TOL {
-                    out.last_mut().unwrap().0 = thresh;
-                } else {
-                    out.push((thresh, price));
-                }
-                last_price = price;
+/// Outputs in the form of [(threshold1, price1),(threshold2, price2) ... ]
+/// Where price_n is what each mat between threshold_n and threshold_(n+1) is worth if it's left over,
+/// and the last price is what it costs to buy anything past all the owned mats.
+/// plan[source] is the column that source's mats are counted as, columns get used up in order and their leftovers
+/// are worth the column's leftover_value (sell price if it's sellable, nothing otherwise).
+/// The first threshold is always 0 and the last pair is always kept, one_dimension_average_gold measures from it
+pub fn distribute_budgets(material_info: &MaterialInput, plan: &[usize]) -> ThreshPrices {
+    material_info
+        .iter()
+        .map(|row| {
+            let mut owned: Vec<Option<f64>> = vec![None; row.len()];
+            for (source, tier) in row.iter().enumerate() {
+                *owned[plan[source]].get_or_insert(0.0) += tier.owned;
             }
+            let segments = owned
+                .iter()
+                .enumerate()
+                .filter_map(|(column, amount)| amount.map(|x| (x, row[column].leftover_value())));
+            merge_segments(segments, row.last().unwrap().buy)
+        })
+        .collect()
+}
 
-            if out[0].0 != 0.0 {
-                out.insert(0, (0.0, 0.0));
-            }
-            // my_dbg!(&out);
-            // assert!(out[0].1 == 0.0 || out.len() == 1);
-            out
+/// Same as distribute_budgets except every source's leftovers are worth that source's own leftover_value,
+/// the plan only decides which sources get used up first. This is the gold actually left whatever the plan pretends,
+/// so states optimized under different plans can be compared on it. The identity plan gives the same as distribute_budgets
+pub fn realized_budgets(material_info: &MaterialInput, plan: &[usize]) -> ThreshPrices {
+    let mut order: Vec<usize> = (0..plan.len()).collect();
+    order.sort_by_key(|&source| plan[source]); // stable, so sources sharing a column go in their own order
+    material_info
+        .iter()
+        .map(|row| {
+            let segments = order
+                .iter()
+                .map(|&source| (row[source].owned, row[source].leftover_value()));
+            merge_segments(segments, row.last().unwrap().buy)
         })
         .collect()
 }
 
+/// (amount, leftover value) segments in the order they get used up, plus the buy price past all of them, into (threshold, price) pairs
+fn merge_segments(segments: impl Iterator<Item = (f64, f64)>, buy: f64) -> Vec<(f64, f64)> {
+    let mut cumulative: f64 = 0.0;
+    let mut pass_1: Vec<(f64, f64)> = Vec::new();
+    for (amount, price) in segments {
+        pass_1.push((cumulative, price));
+        cumulative += amount;
+    }
+    pass_1.push((cumulative, buy));
+
+    // a column with nothing in it doesn't have a segment
+    let mut pass_2: Vec<(f64, f64)> = Vec::with_capacity(pass_1.len());
+    for (thresh, price) in pass_1 {
+        match pass_2.last_mut() {
+            Some(last) if (thresh - last.0).abs() < FLOAT_TOL => last.1 = price,
+            _ => pass_2.push((thresh, price)),
+        }
+    }
+
+    // same price as the segment before it means it's really the same segment, except for the buy price
+    let last_index: usize = pass_2.len() - 1;
+    let mut out: Vec<(f64, f64)> = Vec::with_capacity(pass_2.len());
+    for (index, (thresh, price)) in pass_2.into_iter().enumerate() {
+        match out.last() {
+            Some(last) if index < last_index && (price - last.1).abs() < FLOAT_TOL => {}
+            _ => out.push((thresh, price)),
+        }
+    }
+    out
+}
+
 #[macro_export]
 macro_rules! my_dbg {
     // Match 0 arguments
@@ -360,3 +375,22 @@ pub fn compress_runs(strings: Vec<String>, no_x: bool, mut out: Vec<String>) ->
     }
     out
 }
+
+/// One letter per tap of a State, x for no book and a, b, c.. for book id 1, 2, 3.., uppercase if the tap uses juice
+pub fn encode_one_positions(v1: &[(bool, usize)]) -> String {
+    v1.iter()
+        .map(|(uppercase, num)| {
+            let letter: char = if *num == 0 {
+                'x'
+            } else {
+                (b'a' + (*num as u8 - 1)) as char
+            };
+
+            if *uppercase {
+                letter.to_ascii_uppercase()
+            } else {
+                letter
+            }
+        })
+        .collect()
+}
@@ -1,3 +1,4 @@
+use crate::constants::ARTISAN_MULTIPLIER;
 use crate::constants::juice_info::JuiceInfo;
 use crate::upgrade::Upgrade;
 
@@ -39,6 +40,18 @@ pub fn new_prob_dist(
     )
 }
 
+/// Chance of the tap after count failed ones (ignoring artisan), extra is whatever juice and books add
+pub fn tap_chance(
+    base: f64,
+    event_extra: f64,
+    starting_num_taps: usize,
+    count: usize,
+    extra: f64,
+) -> f64 {
+    let min_count: f64 = std::cmp::min(count + starting_num_taps, 10) as f64;
+    (base + event_extra + (min_count * base) * 0.1 + extra).min(1.0)
+}
+
 // prob distribution of normal honing, adjusting for any juice usage
 pub fn probability_distribution(
     base: f64,
@@ -54,11 +67,13 @@ pub fn probability_distribution(
     let mut count: usize = 0;
 
     loop {
-        let min_count: f64 = std::cmp::min(count + starting_num_taps, 10) as f64;
-
-        let mut current_chance: f64 =
-            (base + event_extra + (min_count * base) * 0.1 + extra_arr.get(count).unwrap_or(&0.0))
-                .min(1.0);
+        let mut current_chance: f64 = tap_chance(
+            base,
+            event_extra,
+            starting_num_taps,
+            count,
+            *extra_arr.get(count).unwrap_or(&0.0),
+        );
         if artisan >= 1.0 {
             current_chance = 1.0;
             raw_chances.push(current_chance);
@@ -66,7 +81,7 @@ pub fn probability_distribution(
         }
         raw_chances.push(current_chance);
         count += 1;
-        artisan += 0.4651_f64 * current_chance * artisan_rate;
+        artisan += ARTISAN_MULTIPLIER * current_chance * artisan_rate;
         if current_chance == 1.0 {
             break; // for upgrades that have 100% passrate immediately or upgrades that have above 100% success rate (juicing last few taps of like +4 or something)
         }
@@ -183,6 +198,30 @@ impl Upgrade {
         }
     }
 
+    /// tap_chance with this upgrade's numbers, tap counts from the first tap after starting_num_taps
+    pub fn tap_chance(&self, tap: usize, extra: f64) -> f64 {
+        tap_chance(
+            self.base_chance,
+            self.extra_chance,
+            self.starting_num_taps,
+            tap,
+            extra,
+        )
+    }
+
+    /// Chance of the very next tap succeeding if it's done with this juice/book
+    pub fn next_tap_chance(&self, juice: bool, book: usize, juice_info: &JuiceInfo) -> f64 {
+        let dist: Vec<f64> = probability_distribution(
+            self.base_chance,
+            self.artisan_rate,
+            &get_extra_arr(&[(juice, book)], juice_info, self),
+            self.starting_artisan,
+            self.starting_num_taps,
+            self.extra_chance,
+        );
+        dist[1] // dist[0] is the 0 tap placeholder and nothing has failed before tap 1
+    }
+
     pub fn update_dist_normal(&mut self, juice_info: &JuiceInfo) {
         let prob_dist: Vec<f64> = new_prob_dist(&self.state, juice_info, self);
 
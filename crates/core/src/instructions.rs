@@ -0,0 +1,364 @@
+//! Turns a plan (Upgrade::state per upgrade + special_state) into instructions a player can follow without knowing what the vectors mean.
+//!
+//! Free taps (special leaps) come first in the order they should be used, then every upgrade in honing order
+//! (lower upgrades first, normal before advanced). Tap numbers start at 1 from wherever the upgrade is right now.
+use crate::model::Piece;
+use crate::state_bundle::StateBundle;
+use crate::upgrade::Upgrade;
+use serde::Serialize;
+
+const SHOWN_SPECIAL_TOL: f64 = 0.005; // free taps that finish the upgrade less often than this aren't worth mentioning
+
+/// first and last tap, both counted from 1 and inclusive
+pub type TapRange = (usize, usize);
+
+#[derive(Debug, Clone, Serialize, PartialEq)]
+pub struct SpecialStep {
+    pub upgrade: usize, // index into upgrade_arr
+    pub label: String,
+    pub success_prob: f64, // chance that this one gets done with free taps
+}
+
+/// 255 means every tap of that kind, same as AdvConfig
+#[derive(Debug, Clone, Copy, Serialize, PartialEq)]
+pub struct AdvTarget {
+    pub grace: u8,
+    pub non_grace: u8,
+}
+
+#[derive(Debug, Clone, Serialize, PartialEq)]
+pub enum UpgradeSteps {
+    Normal {
+        num_taps: usize,
+        juice: Vec<TapRange>,
+        books: Vec<(usize, Vec<TapRange>)>, // (book id, taps)
+    },
+    Advanced {
+        juice: AdvTarget,
+        scroll: AdvTarget,
+    },
+}
+
+#[derive(Debug, Clone, Serialize, PartialEq)]
+pub struct UpgradeInstruction {
+    pub upgrade: usize,
+    pub label: String,
+    pub steps: UpgradeSteps,
+}
+
+#[derive(Debug, Clone, Serialize, PartialEq)]
+pub struct Instructions {
+    pub special: Vec<SpecialStep>,
+    pub upgrades: Vec<UpgradeInstruction>,
+}
+
+/// "Weapon +21" or "Chest adv 30"
+pub fn upgrade_label(upgrade: &Upgrade) -> String {
+    let piece = Piece::from_index(upgrade.piece_type)
+        .map(|x| format!("{:?}", x))
+        .unwrap_or_else(|| format!("Piece {}", upgrade.piece_type));
+    if upgrade.is_normal_honing {
+        format!("{} +{}", piece, upgrade.upgrade_index + 1)
+    } else {
+        format!("{} adv {}", piece, (upgrade.upgrade_index + 1) * 10)
+    }
+}
+
+fn tap_ranges(used: impl Iterator<Item = bool>) -> Vec<TapRange> {
+    let mut out: Vec<TapRange> = Vec::new();
+    for (i, used) in used.enumerate() {
+        if !used {
+            continue;
+        }
+        match out.last_mut() {
+            Some((_, last)) if *last == i => *last = i + 1,
+            _ => out.push((i + 1, i + 1)),
+        }
+    }
+    out
+}
+
+fn normal_steps(upgrade: &Upgrade) -> UpgradeSteps {
+    let mut book_ids: Vec<usize> = upgrade
+        .state
+        .iter()
+        .map(|(_, book)| *book)
+        .filter(|x| *x > 0)
+        .collect();
+    book_ids.sort();
+    book_ids.dedup();
+    UpgradeSteps::Normal {
+        num_taps: upgrade.state.len(),
+        juice: tap_ranges(upgrade.state.iter().map(|(juice, _)| *juice)),
+        books: book_ids
+            .into_iter()
+            .map(|id| {
+                (
+                    id,
+                    tap_ranges(upgrade.state.iter().map(|(_, book)| *book == id)),
+                )
+            })
+            .collect(),
+    }
+}
+
+fn adv_steps(upgrade: &mut Upgrade) -> UpgradeSteps {
+    if upgrade.state.len() >= 2 {
+        upgrade.update_adv_config();
+    }
+    let config = upgrade.adv_config;
+    UpgradeSteps::Advanced {
+        juice: AdvTarget {
+            grace: config.grace_juice_target,
+            non_grace: config.non_grace_juice_target,
+        },
+        scroll: AdvTarget {
+            grace: config.grace_scroll_target,
+            non_grace: config.non_grace_scroll_target,
+        },
+    }
+}
+
+fn describe_ranges(ranges: &[TapRange], num_taps: usize) -> String {
+    if ranges == [(1, num_taps)] {
+        return "on every tap".to_owned();
+    }
+    let parts: Vec<String> = ranges
+        .iter()
+        .map(|&(first, last)| {
+            if last == num_taps {
+                format!("from tap {} onward", first)
+            } else if first == last {
+                format!("on tap {}", first)
+            } else {
+                format!("on taps {}–{}", first, last)
+            }
+        })
+        .collect();
+    join_and(&parts)
+}
+
+fn join_and(parts: &[String]) -> String {
+    match parts {
+        [] => String::new(),
+        [one] => one.clone(),
+        [rest @ .., last] => format!("{} and {}", rest.join(", "), last),
+    }
+}
+
+fn describe_target(target: AdvTarget) -> Option<String> {
+    let describe = |count: u8, kind: &str| match count {
+        0 => None,
+        255 => Some(format!("every {} tap", kind)),
+        1 => Some(format!("the first {} tap", kind)),
+        n => Some(format!("the first {} {} taps", n, kind)),
+    };
+    let parts: Vec<String> = [
+        describe(target.grace, "grace"),
+        describe(target.non_grace, "non-grace"),
+    ]
+    .into_iter()
+    .flatten()
+    .collect();
+    (!parts.is_empty()).then(|| join_and(&parts))
+}
+
+impl UpgradeInstruction {
+    pub fn to_text(&self) -> String {
+        let parts: Vec<String> = match &self.steps {
+            UpgradeSteps::Normal {
+                num_taps,
+                juice,
+                books,
+            } => {
+                let mut parts: Vec<String> = Vec::new();
+                if !juice.is_empty() {
+                    parts.push(format!("juice {}", describe_ranges(juice, *num_taps)));
+                }
+                for (id, ranges) in books.iter() {
+                    let name = if books.len() == 1 {
+                        "the book".to_owned()
+                    } else {
+                        format!("book {}", id)
+                    };
+                    parts.push(format!("{} {}", name, describe_ranges(ranges, *num_taps)));
+                }
+                parts
+            }
+            UpgradeSteps::Advanced { juice, scroll } => [
+                describe_target(*juice).map(|x| format!("juice on {}", x)),
+                describe_target(*scroll).map(|x| format!("scrolls on {}", x)),
+            ]
+            .into_iter()
+            .flatten()
+            .collect(),
+        };
+        if parts.is_empty() {
+            format!("{}: just tap, no juice or books.", self.label)
+        } else {
+            format!("{}: use {}.", self.label, join_and(&parts))
+        }
+    }
+}
+
+impl Instructions {
+    /// One line per step, free taps first
+    pub fn to_lines(&self) -> Vec<String> {
+        let mut out: Vec<String> = Vec::new();
+        if !self.special.is_empty() {
+            let steps: Vec<String> = self
+                .special
+                .iter()
+                .map(|x| format!("{} ({:.0}% to finish it)", x.label, x.success_prob * 100.0))
+                .collect();
+            out.push(format!(
+                "Use free taps on {}, until you run out.",
+                steps.join(", then ")
+            ));
+        }
+        out.extend(self.upgrades.iter().map(|x| x.to_text()));
+        out
+    }
+
+    pub fn to_text(&self) -> String {
+        self.to_lines().join("\n")
+    }
+}
+
+impl StateBundle {
+    pub fn instructions(&mut self) -> Instructions {
+        let mut special: Vec<SpecialStep> = Vec::new();
+        if self.prep_output.special_budget > 0 && !self.upgrade_arr.is_empty() {
+            self.set_latest_special_probs();
+            let probs = self.latest_special_probs.as_ref().unwrap();
+            for (u_index, prob) in self
+                .special_state
+                .iter()
+                .zip(probs.iter())
+                .take(self.special_invalid_index.unwrap())
+            {
+                if *prob < SHOWN_SPECIAL_TOL {
+                    break;
+                }
+                special.push(SpecialStep {
+                    upgrade: *u_index,
+                    label: upgrade_label(&self.upgrade_arr[*u_index]),
+                    success_prob: *prob,
+                });
+            }
+        }
+
+        let mut order: Vec<usize> = (0..self.upgrade_arr.len()).collect();
+        order.sort_by_key(|&i| {
+            let upgrade = &self.upgrade_arr[i];
+            (
+                !upgrade.is_normal_honing,
+                upgrade.upgrade_index,
+                upgrade.piece_type,
+            )
+        });
+        let upgrades: Vec<UpgradeInstruction> = order
+            .into_iter()
+            .map(|i| {
+                let upgrade = &mut self.upgrade_arr[i];
+                UpgradeInstruction {
+                    upgrade: i,
+                    label: upgrade_label(upgrade),
+                    steps: if upgrade.is_normal_honing {
+                        normal_steps(upgrade)
+                    } else {
+                        adv_steps(upgrade)
+                    },
+                }
+            })
+            .collect();
+        Instructions { special, upgrades }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::helpers::test_cases_dir;
+    use crate::payload::parse_to_payloads;
+
+    fn state_bundle(name: &str) -> StateBundle {
+        let (_, payload) = parse_to_payloads(&test_cases_dir().join("payloads"))
+            .into_iter()
+            .find(|(x, _)| x == name)
+            .unwrap();
+        StateBundle::init_from_payload(payload).unwrap()
+    }
+
+    #[test]
+    fn normal_and_special_instructions() {
+        let mut state_bundle = state_bundle("reaper");
+        let juice_info = state_bundle.prep_output.juice_info.clone();
+        let upgrade = state_bundle
+            .upgrade_arr
+            .iter_mut()
+            .find(|x| {
+                x.is_normal_honing
+                    && juice_info.normal_uindex_to_id[x.upgrade_index]
+                        .last()
+                        .is_some_and(|id| *id != 0)
+            })
+            .unwrap();
+        let book_id = *juice_info.normal_uindex_to_id[upgrade.upgrade_index]
+            .last()
+            .unwrap();
+        let len = upgrade.state.len();
+        upgrade.state.update_payload(
+            (0..len)
+                .map(|t| (t >= 5, if t < 3 { book_id } else { 0 }))
+                .collect(),
+        );
+        let label = upgrade_label(upgrade);
+
+        let instructions = state_bundle.instructions();
+        assert!(!instructions.special.is_empty());
+        let lines = instructions.to_lines();
+        assert!(lines[0].starts_with("Use free taps on "), "{:?}", lines);
+        assert!(
+            lines.contains(&format!(
+                "{}: use juice from tap 6 onward and the book on taps 1–3.",
+                label
+            )),
+            "{:?}",
+            lines
+        );
+        // lower upgrades come first, advanced after normal
+        let indices: Vec<(bool, usize)> = instructions
+            .upgrades
+            .iter()
+            .map(|x| {
+                let upgrade = &state_bundle.upgrade_arr[x.upgrade];
+                (!upgrade.is_normal_honing, upgrade.upgrade_index)
+            })
+            .collect();
+        assert!(indices.is_sorted());
+    }
+
+    #[test]
+    fn adv_instructions_use_the_targets() {
+        let mut state_bundle = state_bundle("20_adv40");
+        let upgrade = state_bundle
+            .upgrade_arr
+            .iter_mut()
+            .find(|x| !x.is_normal_honing)
+            .unwrap();
+        // juice index 13 = every grace tap, scroll index 15 = first 10 non-grace taps
+        upgrade.state.update_payload(vec![(false, 13), (false, 15)]);
+        let label = upgrade_label(upgrade);
+
+        let text = state_bundle.instructions().to_text();
+        assert!(
+            text.contains(&format!(
+                "{}: use juice on every grace tap and scrolls on every grace tap and the first 10 non-grace taps.",
+                label
+            )),
+            "{}",
+            text
+        );
+    }
+}
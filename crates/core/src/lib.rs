@@ -1,15 +1,31 @@
+pub mod adaptive;
 pub mod advanced_honing;
+pub mod attribution;
 pub mod constants;
 pub mod core;
+pub mod diff;
+pub mod exact;
+pub mod goal;
 pub mod helpers;
 pub mod honing_utils;
+pub mod instructions;
+pub mod market_depth;
+pub mod migration;
+pub mod model;
 pub mod optimizer;
 pub mod parser;
 pub mod payload;
 pub mod performance;
+pub mod plan_code;
+pub mod roster;
+pub mod schedule;
+pub mod session;
+pub mod simulation;
+pub mod stages;
 pub mod state_bundle;
 pub mod support;
 pub mod timer;
+pub mod treatment;
 pub mod upgrade;
 
 #[cfg(feature = "wasm")] // and this module is not in wasm because it is needed in the engine
@@ -0,0 +1,137 @@
+//! Market depth: buying a lot of something moves the price, so past everything owned the buy price can go up in steps.
+//!
+//! A MarketDepth is [(amount bought past everything owned, multiplier on the buy price from there on)], amounts increasing.
+//! Before the first step it's the plain buy price (the last column's), and the last multiplier goes on forever.
+//! Multipliers rather than prices so that price scenarios (which scale the buy price) move the whole ladder with it.
+//!
+//! Each step is one more breakpoint for one_dimension_average_gold, it's the same (x - thresh)^+ term as the owned thresholds
+//! just on the other side of the mean, so it costs one more saddlepoint evaluation per step.
+pub type MarketDepth = Vec<(f64, f64)>;
+
+/// Turns an order book into a ladder, orders are (quantity, price) cheapest first.
+/// Prices are relative to the cheapest order so that should be the buy price in material_info,
+/// anything past the whole book is assumed to cost as much as the last order
+pub fn from_order_book(orders: &[(f64, f64)]) -> MarketDepth {
+    let Some(&(_, cheapest)) = orders.first() else {
+        return Vec::new();
+    };
+    let mut cumulative: f64 = 0.0;
+    let mut out: MarketDepth = Vec::with_capacity(orders.len());
+    for window in orders.windows(2) {
+        cumulative += window[0].0;
+        out.push((cumulative, window[1].1 / cheapest));
+    }
+    out
+}
+
+/// Samples a price curve (multiplier as a function of how many were bought) every step, up to num_steps steps
+pub fn from_curve(curve: impl Fn(f64) -> f64, step: f64, num_steps: usize) -> MarketDepth {
+    (1..=num_steps)
+        .map(|i| (i as f64 * step, curve(i as f64 * step)))
+        .collect()
+}
+
+pub fn check(depth: &[(f64, f64)]) -> Result<(), String> {
+    let mut last_amount: f64 = 0.0;
+    for &(amount, multiplier) in depth {
+        if amount <= last_amount || !amount.is_finite() {
+            return Err(format!(
+                "market depth amounts have to be positive and increasing, got {} after {}",
+                amount, last_amount
+            ));
+        }
+        if multiplier < 0.0 || !multiplier.is_finite() {
+            return Err(format!(
+                "market depth multiplier {} isn't a price",
+                multiplier
+            ));
+        }
+        last_amount = amount;
+    }
+    Ok(())
+}
+
+/// Multiplier on the buy price for the next one after buying this many
+pub fn multiplier(bought: f64, depth: &[(f64, f64)]) -> f64 {
+    depth
+        .iter()
+        .take_while(|(amount, _)| *amount <= bought)
+        .last()
+        .map_or(1.0, |(_, multiplier)| *multiplier)
+}
+
+/// (amount, extra price per unit past it) for every step, the kinks that get added on top of the flat buy price
+pub fn steps(buy_price: f64, depth: &[(f64, f64)]) -> impl Iterator<Item = (f64, f64)> + '_ {
+    depth.iter().scan(1.0, move |last, &(amount, multiplier)| {
+        let extra = buy_price * (multiplier - *last);
+        *last = multiplier;
+        Some((amount, extra))
+    })
+}
+
+/// How much more buying this many costs than it would at the flat buy price
+pub fn extra_cost(bought: f64, buy_price: f64, depth: &[(f64, f64)]) -> f64 {
+    steps(buy_price, depth)
+        .map(|(amount, extra)| extra * (bought - amount).max(0.0))
+        .sum()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::helpers::test_cases_dir;
+    use crate::payload::parse_to_payloads;
+    use crate::performance::Performance;
+    use crate::simulation::simulate;
+    use crate::state_bundle::StateBundle;
+
+    #[test]
+    fn order_book_ladder() {
+        let depth = from_order_book(&[(100.0, 10.0), (50.0, 12.0), (1000.0, 15.0)]);
+        assert_eq!(depth, vec![(100.0, 1.2), (150.0, 1.5)]);
+        assert!(check(&depth).is_ok());
+        assert_eq!(multiplier(50.0, &depth), 1.0);
+        assert_eq!(multiplier(120.0, &depth), 1.2);
+        assert_eq!(multiplier(500.0, &depth), 1.5);
+
+        // 100 at 10, 50 at 12, 50 at 15
+        let flat: f64 = 200.0 * 10.0;
+        let laddered: f64 = 100.0 * 10.0 + 50.0 * 12.0 + 50.0 * 15.0;
+        assert!((extra_cost(200.0, 10.0, &depth) - (laddered - flat)).abs() < 1e-9);
+        assert_eq!(extra_cost(80.0, 10.0, &depth), 0.0);
+
+        assert!(check(&[(10.0, 1.0), (5.0, 1.1)]).is_err());
+        assert_eq!(
+            from_curve(|x| 1.0 + x / 1000.0, 100.0, 2),
+            vec![(100.0, 1.1), (200.0, 1.2)]
+        );
+    }
+
+    #[test]
+    fn average_matches_simulation() {
+        let (_, mut payload) = parse_to_payloads(&test_cases_dir().join("payloads"))
+            .into_iter()
+            .find(|(x, _)| x == "2122")
+            .unwrap();
+        let mut performance = Performance::new();
+        let flat = StateBundle::init_from_payload(payload.clone())
+            .unwrap()
+            .optimizer_average_gold_metric(&mut performance);
+        payload.market_depth = Some(vec![
+            vec![(1.0, 1.3), (1000.0, 1.6)];
+            payload.material_info.len()
+        ]);
+        let mut state_bundle = StateBundle::init_from_payload(payload).unwrap();
+        let metric = state_bundle.optimizer_average_gold_metric(&mut performance);
+        assert!(metric < flat);
+
+        let num_samples: usize = 20000;
+        let golds: Vec<f64> = simulate(&state_bundle, 0, num_samples)
+            .map(|x| x.gold)
+            .collect();
+        let n = num_samples as f64;
+        let mean: f64 = golds.iter().sum::<f64>() / n;
+        let var: f64 = golds.iter().map(|x| (x - mean).powi(2)).sum::<f64>() / n;
+        assert!((mean - metric).abs() < 4.0 * (var / n).sqrt() + 1e-6 * metric.abs());
+    }
+}
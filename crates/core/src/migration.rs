@@ -0,0 +1,352 @@
+//! Saved payloads (test cases, bug reports, sessions people kept around) go through here before becoming a Payload,
+//! so an old file either gets upgraded to what it used to mean or fails loudly instead of quietly meaning something else.
+//!
+//! Every schema change bumps PAYLOAD_SCHEMA_VERSION and adds one step to MIGRATIONS that takes the json from the version before it.
+//! Payloads without a schema_version are version 0, everything that was saved before this existed.
+use crate::parser::MaterialTier;
+use crate::payload::Payload;
+use crate::treatment::{default_material_sources, default_treatment_plans};
+use serde_json::{Map, Value};
+use std::fmt;
+
+pub const PAYLOAD_SCHEMA_VERSION: u32 = 3;
+
+type Migration = fn(&mut Map<String, Value>) -> Result<(), MigrationError>;
+
+/// MIGRATIONS[v] takes version v to v + 1
+const MIGRATIONS: [Migration; PAYLOAD_SCHEMA_VERSION as usize] = [v0_to_v1, v1_to_v2, v2_to_v3];
+
+/// UI_TREATMENTS as of version 2, v2_to_v3 translates them
+const V2_UI_TREATMENTS: [[usize; 4]; 2] = [[0, 1, 1, 3], [0, 1, 2, 3]];
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum MigrationError {
+    NotAnObject,
+    BadVersion(Value),
+    FromTheFuture(u32),
+    Invalid { field: String, reason: String },
+    Parse(String),
+}
+
+impl fmt::Display for MigrationError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MigrationError::NotAnObject => write!(f, "Payload is not a json object"),
+            MigrationError::BadVersion(value) => {
+                write!(f, "schema_version {} is not a version number", value)
+            }
+            MigrationError::FromTheFuture(version) => write!(
+                f,
+                "Payload is schema version {} but this build only knows up to {}",
+                version, PAYLOAD_SCHEMA_VERSION
+            ),
+            MigrationError::Invalid { field, reason } => write!(f, "{}: {}", field, reason),
+            MigrationError::Parse(reason) => {
+                write!(f, "Payload does not have the right shape: {}", reason)
+            }
+        }
+    }
+}
+
+impl std::error::Error for MigrationError {}
+
+fn invalid(field: impl Into<String>, reason: &str) -> MigrationError {
+    MigrationError::Invalid {
+        field: field.into(),
+        reason: reason.to_owned(),
+    }
+}
+
+/// Unversioned payloads leaned on serde defaults, those become explicit here.
+/// adv_progress is positional (start_xp, start_balls, next_free, next_big) and anything else used to be silently misread, so it gets checked
+fn v0_to_v1(payload: &mut Map<String, Value>) -> Result<(), MigrationError> {
+    payload.entry("metric_type").or_insert(Value::from(1));
+    payload.entry("num_threads").or_insert(Value::from(0));
+    payload.entry("events").or_insert(Value::Array(Vec::new()));
+
+    let Some(Value::Array(upgrade_info)) = payload.get("upgrade_info") else {
+        return Err(invalid("upgrade_info", "missing or not a list"));
+    };
+    for (i, upgrade) in upgrade_info.iter().enumerate() {
+        let field = format!("upgrade_info[{}].adv_progress", i);
+        match upgrade.get("adv_progress") {
+            None | Some(Value::Null) => {}
+            Some(Value::Array(x)) => {
+                let well_formed = x.len() == 4
+                    && x[0].is_u64()
+                    && x[1].is_u64()
+                    && x[2].is_boolean()
+                    && x[3].is_boolean();
+                if !well_formed {
+                    return Err(invalid(
+                        field,
+                        "expected [start_xp, start_balls, next_free, next_big]",
+                    ));
+                }
+            }
+            Some(_) => return Err(invalid(field, "expected a list or null")),
+        }
+    }
+    Ok(())
+}
+
+/// Material sources used to be exactly the four columns of UI_TREATMENTS, now they're named and there can be any number of them
+fn v1_to_v2(payload: &mut Map<String, Value>) -> Result<(), MigrationError> {
+    let Some(Value::Array(material_info)) = payload.get("material_info") else {
+        return Err(invalid("material_info", "missing or not a list"));
+    };
+    let num_sources = match material_info.first() {
+        Some(Value::Array(row)) => row.len(),
+        _ => return Err(invalid("material_info", "expected a list of rows")),
+    };
+    if material_info
+        .iter()
+        .any(|row| row.as_array().is_none_or(|x| x.len() != num_sources))
+    {
+        return Err(invalid(
+            "material_info",
+            "every row needs the same number of columns",
+        ));
+    }
+    payload.insert(
+        "material_sources".to_owned(),
+        serde_json::to_value(default_material_sources(num_sources)).unwrap(),
+    );
+    let mut treatment_plans = default_treatment_plans(num_sources);
+    if num_sources == V2_UI_TREATMENTS[0].len() {
+        for (plan, old) in treatment_plans.iter_mut().zip(V2_UI_TREATMENTS) {
+            plan.plan = old.to_vec();
+        }
+    }
+    payload.insert(
+        "treatment_plans".to_owned(),
+        serde_json::to_value(treatment_plans).unwrap(),
+    );
+    payload.insert("choose_treatment".to_owned(), Value::Bool(false));
+    Ok(())
+}
+
+/// Version 2 plans pointed at the column whose price the source got, and that price was for the mats of the column after it
+/// (the last one being the buy price), so a source is now counted as the column after the previous one the plan uses.
+/// Only differs from before if the plan doesn't use the last column, which now always sets the buy price
+fn v2_plan(old: &[usize]) -> Vec<usize> {
+    old.iter()
+        .map(|&column| {
+            old.iter()
+                .copied()
+                .filter(|x| *x < column)
+                .max()
+                .map_or(0, |x| x + 1)
+        })
+        .collect()
+}
+
+/// material_info used to be (owned, price) where the price was both what a column's mats cost and what the next column's
+/// leftovers were worth. Now every column has its own buy and sell price, so leftovers can be worth less than buying
+fn v2_to_v3(payload: &mut Map<String, Value>) -> Result<(), MigrationError> {
+    let Some(Value::Array(material_info)) = payload.get_mut("material_info") else {
+        return Err(invalid("material_info", "missing or not a list"));
+    };
+    for (i, row) in material_info.iter_mut().enumerate() {
+        let field = format!("material_info[{}]", i);
+        let Value::Array(row) = row else {
+            return Err(invalid(field, "expected a list of [owned, price]"));
+        };
+        let pairs: Vec<(f64, f64)> = row
+            .iter()
+            .map(|x| serde_json::from_value(x.clone()))
+            .collect::<Result<_, _>>()
+            .map_err(|_| invalid(field.clone(), "expected a list of [owned, price]"))?;
+        let mut sell: f64 = 0.0;
+        for (cell, (owned, price)) in row.iter_mut().zip(pairs) {
+            *cell = serde_json::to_value(MaterialTier {
+                owned,
+                buy: price,
+                sell,
+                sellable: sell > 0.0,
+            })
+            .unwrap();
+            sell = price;
+        }
+    }
+
+    if let Some(Value::Array(plan)) = payload.get("optimizer_plan") {
+        let old: Vec<usize> = serde_json::from_value(Value::Array(plan.clone()))
+            .map_err(|_| invalid("optimizer_plan", "expected a list of columns"))?;
+        payload.insert(
+            "optimizer_plan".to_owned(),
+            serde_json::to_value(v2_plan(&old)).unwrap(),
+        );
+    }
+    let Some(Value::Array(treatment_plans)) = payload.get_mut("treatment_plans") else {
+        return Err(invalid("treatment_plans", "missing or not a list"));
+    };
+    for (i, treatment_plan) in treatment_plans.iter_mut().enumerate() {
+        let field = format!("treatment_plans[{}].plan", i);
+        let old: Vec<usize> = treatment_plan
+            .get("plan")
+            .and_then(|x| serde_json::from_value(x.clone()).ok())
+            .ok_or_else(|| invalid(field, "expected a list of columns"))?;
+        treatment_plan["plan"] = serde_json::to_value(v2_plan(&old)).unwrap();
+    }
+    Ok(())
+}
+
+fn schema_version(payload: &Map<String, Value>) -> Result<u32, MigrationError> {
+    match payload.get("schema_version") {
+        None => Ok(0),
+        Some(value) => value
+            .as_u64()
+            .and_then(|x| u32::try_from(x).ok())
+            .ok_or_else(|| MigrationError::BadVersion(value.clone())),
+    }
+}
+
+/// Brings the json up to PAYLOAD_SCHEMA_VERSION, already current payloads come back as they are
+pub fn migrate_payload(mut value: Value) -> Result<Value, MigrationError> {
+    let payload = value.as_object_mut().ok_or(MigrationError::NotAnObject)?;
+    let version = schema_version(payload)?;
+    if version > PAYLOAD_SCHEMA_VERSION {
+        return Err(MigrationError::FromTheFuture(version));
+    }
+    for migration in MIGRATIONS.iter().skip(version as usize) {
+        migration(payload)?;
+    }
+    payload.insert(
+        "schema_version".to_owned(),
+        Value::from(PAYLOAD_SCHEMA_VERSION),
+    );
+    Ok(value)
+}
+
+impl Payload {
+    pub fn from_json_value(value: Value) -> Result<Payload, MigrationError> {
+        serde_json::from_value(migrate_payload(value)?)
+            .map_err(|e| MigrationError::Parse(e.to_string()))
+    }
+
+    /// Use this rather than serde_json::from_str for anything that was saved to a file
+    pub fn from_json(json: &str) -> Result<Payload, MigrationError> {
+        let value: Value =
+            serde_json::from_str(json).map_err(|e| MigrationError::Parse(e.to_string()))?;
+        Payload::from_json_value(value)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::constants::UI_TREATMENTS;
+    use crate::helpers::test_cases_dir;
+    use crate::state_bundle::StateBundle;
+    use std::fs;
+
+    fn fixture(name: &str) -> String {
+        fs::read_to_string(test_cases_dir().join("schema").join(name)).unwrap()
+    }
+
+    #[test]
+    fn old_fixtures_migrate_to_current_fixture() {
+        let migrated: Payload = Payload::from_json(&fixture("v0.json")).unwrap();
+        assert_eq!(migrated.schema_version, PAYLOAD_SCHEMA_VERSION);
+        assert_eq!(migrated.metric_type, 1);
+        assert_eq!(migrated.num_threads, 0);
+        assert_eq!(migrated.treatment_plans.len(), 2);
+        assert!(!migrated.choose_treatment);
+
+        assert_eq!(migrated.treatment_plans[0].plan, UI_TREATMENTS[0]);
+        assert!(!migrated.material_info[0][0].sellable);
+
+        let expected: Value = serde_json::from_str(&fixture("v3.json")).unwrap();
+        assert_eq!(serde_json::to_value(&migrated).unwrap(), expected);
+        for older in ["v1.json", "v2.json"] {
+            let from_older: Payload = Payload::from_json(&fixture(older)).unwrap();
+            assert_eq!(serde_json::to_value(&from_older).unwrap(), expected);
+        }
+        // the current version deserializes without any help and doesn't change on the way back out
+        let current: Payload = serde_json::from_value(expected.clone()).unwrap();
+        assert_eq!(serde_json::to_value(&current).unwrap(), expected);
+        assert_eq!(migrate_payload(expected.clone()).unwrap(), expected);
+
+        let mut a = StateBundle::init_from_payload(migrated).unwrap();
+        let mut b = StateBundle::init_from_payload(current).unwrap();
+        let mut performance = crate::performance::Performance::new();
+        assert_eq!(
+            a.optimizer_average_gold_metric(&mut performance),
+            b.optimizer_average_gold_metric(&mut performance)
+        );
+    }
+
+    #[test]
+    fn v1_fixture_is_v0_one_step_later() {
+        // prices like 0.22666666666666666 only survive the trip through a Value with float_roundtrip
+        let mut value: Value = serde_json::from_str(&fixture("v0.json")).unwrap();
+        let payload = value.as_object_mut().unwrap();
+        v0_to_v1(payload).unwrap();
+        payload.insert("schema_version".to_owned(), Value::from(1));
+        let expected: Value = serde_json::from_str(&fixture("v1.json")).unwrap();
+        assert_eq!(value, expected);
+    }
+
+    #[test]
+    fn bundled_payloads_round_trip() {
+        for (name, payload) in crate::payload::parse_to_payloads(&test_cases_dir().join("payloads"))
+        {
+            let saved = serde_json::to_string(&payload).unwrap();
+            let reloaded = Payload::from_json(&saved).unwrap_or_else(|e| panic!("{}: {}", name, e));
+            assert_eq!(saved, serde_json::to_string(&reloaded).unwrap(), "{}", name);
+        }
+    }
+
+    #[test]
+    fn rejects_what_it_cant_read() {
+        let mut value: Value = serde_json::from_str(&fixture("v0.json")).unwrap();
+        value["schema_version"] = Value::from(PAYLOAD_SCHEMA_VERSION + 1);
+        assert_eq!(
+            migrate_payload(value.clone()).unwrap_err(),
+            MigrationError::FromTheFuture(PAYLOAD_SCHEMA_VERSION + 1)
+        );
+
+        value["schema_version"] = Value::from("1");
+        assert!(matches!(
+            migrate_payload(value.clone()),
+            Err(MigrationError::BadVersion(_))
+        ));
+
+        let mut value: Value = serde_json::from_str(&fixture("v0.json")).unwrap();
+        let adv = value["upgrade_info"]
+            .as_array_mut()
+            .unwrap()
+            .iter_mut()
+            .find(|x| !x["adv_progress"].is_null())
+            .unwrap();
+        adv["adv_progress"] = serde_json::json!([0, false, 0, false]);
+        assert!(matches!(
+            migrate_payload(value),
+            Err(MigrationError::Invalid { .. })
+        ));
+
+        let mut value: Value = serde_json::from_str(&fixture("v1.json")).unwrap();
+        value["material_info"][0].as_array_mut().unwrap().pop();
+        assert!(matches!(
+            migrate_payload(value),
+            Err(MigrationError::Invalid { .. })
+        ));
+
+        let mut value: Value = serde_json::from_str(&fixture("v2.json")).unwrap();
+        value["material_info"][0][1] = serde_json::json!([1.0]);
+        assert!(matches!(
+            migrate_payload(value),
+            Err(MigrationError::Invalid { .. })
+        ));
+    }
+
+    #[test]
+    fn v2_plans_keep_their_meaning() {
+        for (old, new) in V2_UI_TREATMENTS.iter().zip(UI_TREATMENTS) {
+            assert_eq!(v2_plan(old), new);
+        }
+        // roster-bound used to be free by pointing it at column 0, now it's left over like column 1 (which was 0's price)
+        assert_eq!(v2_plan(&[0, 0, 2, 3]), vec![0, 0, 1, 3]);
+    }
+}
@@ -0,0 +1,728 @@
+//! Typed names for the magic numbers of the wire format, so that rust users don't have to know that piece_type 5 is the weapon
+//! or that row 7 + num_juice_avail of material_info is the first armor juice.
+//!
+//! Payload itself is unchanged (js sends it as is), PayloadBuilder checks the ranges and produces it.
+use crate::constants::events::{EXPRESS_EVENT_NAME, EventError, EventInput};
+use crate::constants::registry::{DataError, data, tier_by_name};
+use crate::constants::{MARKET_TAX, TreatmentsType};
+use crate::market_depth::{self, MarketDepth};
+use crate::migration::PAYLOAD_SCHEMA_VERSION;
+use crate::parser::{MaterialInput, MaterialTier, OneUpgradeInput, PriceScenario};
+use crate::payload::Payload;
+use crate::treatment::{TreatmentPlan, default_material_sources, default_treatment_plans};
+use std::fmt;
+
+pub const NUM_BASE_MATERIALS: usize = 7;
+pub const NUM_TREATMENTS: usize = 4;
+pub const NUM_NORMAL_UPGRADES: usize = 25;
+pub const NUM_ADV_UPGRADES: usize = 4;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Piece {
+    Helmet,
+    Shoulder,
+    Chest,
+    Pants,
+    Glove,
+    Weapon,
+}
+
+impl Piece {
+    pub const ALL: [Piece; 6] = [
+        Piece::Helmet,
+        Piece::Shoulder,
+        Piece::Chest,
+        Piece::Pants,
+        Piece::Glove,
+        Piece::Weapon,
+    ];
+    pub fn index(self) -> usize {
+        self as usize
+    }
+    pub fn from_index(piece_type: usize) -> Option<Piece> {
+        Self::ALL.get(piece_type).copied()
+    }
+    pub fn is_weapon(self) -> bool {
+        self == Piece::Weapon
+    }
+}
+
+/// The first NUM_BASE_MATERIALS rows of material_info (and of cost_dist)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Material {
+    Red,
+    Blue,
+    Leaps,
+    Shards,
+    Fusion,
+    Gold,
+    Silver,
+}
+
+impl Material {
+    pub const ALL: [Material; NUM_BASE_MATERIALS] = [
+        Material::Red,
+        Material::Blue,
+        Material::Leaps,
+        Material::Shards,
+        Material::Fusion,
+        Material::Gold,
+        Material::Silver,
+    ];
+    pub fn index(self) -> usize {
+        self as usize
+    }
+}
+
+/// Id of a juice (breath) or book in JUICE_BOOKS_AVAIL, every id has a weapon and an armor version
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct JuiceId(pub usize);
+
+/// A row of material_info
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum MaterialRow {
+    Base(Material),
+    Juice { id: JuiceId, weapon: bool },
+}
+
+impl MaterialRow {
+    /// weapon juices come right after the base materials, then armor juices
+    pub fn index(self, num_juice_avail: usize) -> Option<usize> {
+        match self {
+            MaterialRow::Base(material) => Some(material.index()),
+            MaterialRow::Juice { id, weapon } => (id.0 < num_juice_avail)
+                .then(|| NUM_BASE_MATERIALS + id.0 + if weapon { 0 } else { num_juice_avail }),
+        }
+    }
+    pub fn from_index(index: usize, num_juice_avail: usize) -> Option<MaterialRow> {
+        if index < NUM_BASE_MATERIALS {
+            return Some(MaterialRow::Base(Material::ALL[index]));
+        }
+        let juice_index = index - NUM_BASE_MATERIALS;
+        (juice_index < 2 * num_juice_avail).then(|| MaterialRow::Juice {
+            id: JuiceId(juice_index % num_juice_avail),
+            weapon: juice_index < num_juice_avail,
+        })
+    }
+}
+
+/// Columns of material_info (material sources), see treatment.rs
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Treatment {
+    CharBound,
+    RosterBound,
+    Tradable,
+    Market,
+    Source(usize), // added with PayloadBuilder::source, counting from 0 in the order they were added
+}
+
+impl Treatment {
+    pub const ALL: [Treatment; NUM_TREATMENTS] = [
+        Treatment::CharBound,
+        Treatment::RosterBound,
+        Treatment::Tradable,
+        Treatment::Market,
+    ];
+    /// Column in material_info without any extra sources
+    pub fn index(self) -> usize {
+        self.column(0)
+    }
+    /// Extra sources go between tradable and market, market is always last because that's where anything missing gets bought
+    pub fn column(self, num_extra_sources: usize) -> usize {
+        match self {
+            Treatment::CharBound => 0,
+            Treatment::RosterBound => 1,
+            Treatment::Tradable => 2,
+            Treatment::Source(i) => 3 + i,
+            Treatment::Market => 3 + num_extra_sources,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Tier {
+    T4,
+    Serca,
+    Named(String), // anything loaded into the registry
+}
+
+impl Tier {
+    pub fn index(&self) -> Result<usize, DataError> {
+        match self {
+            Tier::T4 => Ok(0),
+            Tier::Serca => Ok(1),
+            Tier::Named(name) => tier_by_name(name),
+        }
+    }
+    pub fn num_juice_avail(&self) -> Result<usize, DataError> {
+        Ok(data(self.index()?).base_juice_info.num_juice_avail)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Metric {
+    AverageGold = 1,
+    ExpectedScenarioGold = 2,
+    WorstScenarioGold = 3,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum PayloadError {
+    Data(DataError),
+    NormalOutOfRange {
+        piece: Piece,
+        plus: usize,
+    },
+    AdvOutOfRange {
+        piece: Piece,
+        level: usize,
+    },
+    JuiceOutOfRange {
+        id: JuiceId,
+        num_juice_avail: usize,
+    },
+    Duplicate {
+        piece: Piece,
+        is_normal_honing: bool,
+        upgrade_index: usize,
+    },
+    UnknownSource {
+        index: usize,
+        num_sources: usize, // added with PayloadBuilder::source
+    },
+    NoSources,
+    ColumnMismatch {
+        row: usize,
+        columns: usize,
+        num_sources: usize,
+    },
+    BadTreatmentPlan(String),
+    BadMarketDepth(String),
+    BadPriceScenario(String),
+    Event(EventError),
+}
+
+impl fmt::Display for PayloadError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PayloadError::Data(e) => write!(f, "{}", e),
+            PayloadError::NormalOutOfRange { piece, plus } => {
+                write!(f, "{:?} +{} is not a normal honing upgrade", piece, plus)
+            }
+            PayloadError::AdvOutOfRange { piece, level } => {
+                write!(
+                    f,
+                    "{:?} adv {} is not an advanced honing upgrade",
+                    piece, level
+                )
+            }
+            PayloadError::JuiceOutOfRange {
+                id,
+                num_juice_avail,
+            } => write!(
+                f,
+                "Juice id {} is out of range, this tier only has {}",
+                id.0, num_juice_avail
+            ),
+            PayloadError::Duplicate {
+                piece,
+                is_normal_honing,
+                upgrade_index,
+            } => write!(
+                f,
+                "{:?} {} upgrade {} was added twice",
+                piece,
+                if *is_normal_honing { "normal" } else { "adv" },
+                upgrade_index
+            ),
+            PayloadError::UnknownSource { index, num_sources } => write!(
+                f,
+                "Material source {} was never added, there are only {}",
+                index, num_sources
+            ),
+            PayloadError::NoSources => write!(f, "material_info has no material sources"),
+            PayloadError::ColumnMismatch {
+                row,
+                columns,
+                num_sources,
+            } => write!(
+                f,
+                "material_info row {} has {} columns but there are {} material sources",
+                row, columns, num_sources
+            ),
+            PayloadError::BadTreatmentPlan(reason) => write!(f, "{}", reason),
+            PayloadError::BadMarketDepth(reason) => write!(f, "{}", reason),
+            PayloadError::BadPriceScenario(reason) => write!(f, "{}", reason),
+            PayloadError::Event(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl std::error::Error for PayloadError {}
+
+impl From<DataError> for PayloadError {
+    fn from(e: DataError) -> Self {
+        PayloadError::Data(e)
+    }
+}
+
+impl From<EventError> for PayloadError {
+    fn from(e: EventError) -> Self {
+        PayloadError::Event(e)
+    }
+}
+
+impl OneUpgradeInput {
+    pub fn piece(&self) -> Option<Piece> {
+        Piece::from_index(self.piece_type)
+    }
+}
+
+enum UpgradeSpec {
+    Normal {
+        piece: Piece,
+        plus: usize,
+        artisan: f64,
+    },
+    Adv {
+        piece: Piece,
+        level: usize,
+    },
+}
+
+/// Builds a Payload without touching raw indices, nothing is checked until build() because the tier decides how many juices there are.
+/// Every material starts with nothing owned and a price of 0, tradable and market leftovers can be sold and everything else is bound.
+pub struct PayloadBuilder {
+    tier: Tier,
+    upgrades: Vec<UpgradeSpec>,
+    owned: Vec<(MaterialRow, Treatment, f64)>,
+    prices: Vec<(MaterialRow, Treatment, f64)>,
+    sell_prices: Vec<(MaterialRow, Treatment, Option<f64>)>, // None is unsellable
+    sources: Vec<String>,
+    treatments: Option<Vec<Treatment>>,
+    treatment_plans: Vec<(String, Vec<Treatment>)>,
+    choose_treatment: bool,
+    special_budget: i64,
+    events: Vec<EventInput>,
+    min_resolution: usize,
+    num_threads: usize,
+    metric: Metric,
+    price_scenarios: Option<Vec<PriceScenario>>,
+    market_depth: Vec<(MaterialRow, MarketDepth)>,
+}
+
+impl PayloadBuilder {
+    pub fn new(tier: Tier) -> Self {
+        Self {
+            tier,
+            upgrades: Vec::new(),
+            owned: Vec::new(),
+            prices: Vec::new(),
+            sell_prices: Vec::new(),
+            sources: Vec::new(),
+            treatments: None,
+            treatment_plans: Vec::new(),
+            choose_treatment: false,
+            special_budget: 0,
+            events: Vec::new(),
+            min_resolution: 1,
+            num_threads: 0,
+            metric: Metric::AverageGold,
+            price_scenarios: None,
+            market_depth: Vec::new(),
+        }
+    }
+
+    /// plus is what the piece will be after succeeding, so +21 is upgrade_index 20
+    pub fn normal(self, piece: Piece, plus: usize) -> Self {
+        self.normal_with_artisan(piece, plus, 0.0)
+    }
+    pub fn normal_with_artisan(mut self, piece: Piece, plus: usize, artisan: f64) -> Self {
+        self.upgrades.push(UpgradeSpec::Normal {
+            piece,
+            plus,
+            artisan,
+        });
+        self
+    }
+    /// level is 10, 20, 30 or 40
+    pub fn advanced(mut self, piece: Piece, level: usize) -> Self {
+        self.upgrades.push(UpgradeSpec::Adv { piece, level });
+        self
+    }
+    pub fn owned(mut self, row: MaterialRow, treatment: Treatment, amount: f64) -> Self {
+        self.owned.push((row, treatment, amount));
+        self
+    }
+    /// What buying one costs, leftovers sell for this minus MARKET_TAX if they can be sold
+    pub fn price(mut self, row: MaterialRow, treatment: Treatment, price: f64) -> Self {
+        self.prices.push((row, treatment, price));
+        self
+    }
+    /// What a leftover is worth instead of price minus tax, this also makes it sellable
+    pub fn sell_price(mut self, row: MaterialRow, treatment: Treatment, price: f64) -> Self {
+        self.sell_prices.push((row, treatment, Some(price)));
+        self
+    }
+    /// Leftovers are worth nothing
+    pub fn unsellable(mut self, row: MaterialRow, treatment: Treatment) -> Self {
+        self.sell_prices.push((row, treatment, None));
+        self
+    }
+    /// Adds a material source before market (and after any sources added before it), it's Treatment::Source(n) for the nth one
+    pub fn source(mut self, name: &str) -> Self {
+        self.sources.push(name.to_owned());
+        self
+    }
+    /// How each column is treated by the optimizer, defaults to every source as itself
+    pub fn treatments(mut self, treatments: &[Treatment]) -> Self {
+        self.treatments = Some(treatments.to_vec());
+        self
+    }
+    /// Adds a plan to compare (and choose from), defaults to UI_TREATMENTS if no sources were added
+    pub fn treatment_plan(mut self, name: &str, treatments: &[Treatment]) -> Self {
+        self.treatment_plans
+            .push((name.to_owned(), treatments.to_vec()));
+        self
+    }
+    /// Let the optimizer pick the best of the treatment plans
+    pub fn choose_treatment(mut self) -> Self {
+        self.choose_treatment = true;
+        self
+    }
+    pub fn special_budget(mut self, special_budget: i64) -> Self {
+        self.special_budget = special_budget;
+        self
+    }
+    pub fn event(mut self, event: EventInput) -> Self {
+        self.events.push(event);
+        self
+    }
+    pub fn express_event(self) -> Self {
+        self.event(EventInput::Named(EXPRESS_EVENT_NAME.to_owned()))
+    }
+    pub fn min_resolution(mut self, min_resolution: usize) -> Self {
+        self.min_resolution = min_resolution;
+        self
+    }
+    pub fn num_threads(mut self, num_threads: usize) -> Self {
+        self.num_threads = num_threads;
+        self
+    }
+    pub fn metric(mut self, metric: Metric) -> Self {
+        self.metric = metric;
+        self
+    }
+    pub fn price_scenarios(mut self, price_scenarios: Vec<PriceScenario>) -> Self {
+        self.price_scenarios = Some(price_scenarios);
+        self
+    }
+
+    /// How the market price goes up when buying a lot of this, see market_depth.rs
+    pub fn market_depth(mut self, row: MaterialRow, depth: MarketDepth) -> Self {
+        self.market_depth.push((row, depth));
+        self
+    }
+
+    pub fn build(self) -> Result<Payload, PayloadError> {
+        let tier: usize = self.tier.index()?;
+        let num_juice_avail: usize = self.tier.num_juice_avail()?;
+
+        let num_sources: usize = NUM_TREATMENTS + self.sources.len();
+        let column = |treatment: Treatment| match treatment {
+            Treatment::Source(index) if index >= self.sources.len() => {
+                Err(PayloadError::UnknownSource {
+                    index,
+                    num_sources: self.sources.len(),
+                })
+            }
+            _ => Ok(treatment.column(self.sources.len())),
+        };
+        let default_row: Vec<MaterialTier> = (0..num_sources)
+            .map(|column| {
+                if column == Treatment::Tradable.index() || column == num_sources - 1 {
+                    MaterialTier::tradable(0.0, 0.0)
+                } else {
+                    MaterialTier::bound(0.0, 0.0)
+                }
+            })
+            .collect();
+        let mut material_info: MaterialInput =
+            vec![default_row; NUM_BASE_MATERIALS + 2 * num_juice_avail];
+        let row_index = |row: MaterialRow| match row {
+            MaterialRow::Juice { id, .. } => {
+                row.index(num_juice_avail)
+                    .ok_or(PayloadError::JuiceOutOfRange {
+                        id,
+                        num_juice_avail,
+                    })
+            }
+            MaterialRow::Base(material) => Ok(material.index()),
+        };
+        for (row, treatment, amount) in self.owned.iter() {
+            material_info[row_index(*row)?][column(*treatment)?].owned = *amount;
+        }
+        for (row, treatment, price) in self.prices.iter() {
+            let tier = &mut material_info[row_index(*row)?][column(*treatment)?];
+            tier.buy = *price;
+            tier.sell = price * (1.0 - MARKET_TAX);
+        }
+        for (row, treatment, sell_price) in self.sell_prices.iter() {
+            let tier = &mut material_info[row_index(*row)?][column(*treatment)?];
+            tier.sellable = sell_price.is_some();
+            tier.sell = sell_price.unwrap_or(0.0);
+        }
+        let market_depth: Option<Vec<MarketDepth>> = if self.market_depth.is_empty() {
+            None
+        } else {
+            let mut out: Vec<MarketDepth> = vec![Vec::new(); material_info.len()];
+            for (row, depth) in self.market_depth.iter() {
+                market_depth::check(depth).map_err(PayloadError::BadMarketDepth)?;
+                out[row_index(*row)?] = depth.clone();
+            }
+            Some(out)
+        };
+        if let Some(scenarios) = &self.price_scenarios {
+            PriceScenario::check_all(scenarios, material_info.len())
+                .map_err(PayloadError::BadPriceScenario)?;
+        }
+
+        let mut upgrade_info: Vec<OneUpgradeInput> = Vec::with_capacity(self.upgrades.len());
+        for spec in self.upgrades.iter() {
+            let (piece, is_normal_honing, upgrade_index) = match *spec {
+                UpgradeSpec::Normal { piece, plus, .. } => {
+                    if plus == 0 || plus > NUM_NORMAL_UPGRADES {
+                        return Err(PayloadError::NormalOutOfRange { piece, plus });
+                    }
+                    (piece, true, plus - 1)
+                }
+                UpgradeSpec::Adv { piece, level } => {
+                    if level == 0 || level % 10 != 0 || level / 10 > NUM_ADV_UPGRADES {
+                        return Err(PayloadError::AdvOutOfRange { piece, level });
+                    }
+                    (piece, false, level / 10 - 1)
+                }
+            };
+            if upgrade_info.iter().any(|x| {
+                x.piece_type == piece.index()
+                    && x.is_normal_honing == is_normal_honing
+                    && x.upgrade_index == upgrade_index
+            }) {
+                return Err(PayloadError::Duplicate {
+                    piece,
+                    is_normal_honing,
+                    upgrade_index,
+                });
+            }
+            upgrade_info.push(OneUpgradeInput {
+                piece_type: piece.index(),
+                upgrade_index,
+                is_normal_honing,
+                starting_artisan: match *spec {
+                    UpgradeSpec::Normal { artisan, .. } => Some(artisan),
+                    UpgradeSpec::Adv { .. } => None,
+                },
+                starting_num_taps: is_normal_honing.then_some(0),
+                state: None,
+                unlocked: false,
+                adv_progress: (!is_normal_honing).then_some((0, 0, false, false)),
+            });
+        }
+
+        let to_plan =
+            |name: &str, treatments: &[Treatment]| -> Result<TreatmentPlan, PayloadError> {
+                let plan = TreatmentPlan {
+                    name: name.to_owned(),
+                    plan: treatments
+                        .iter()
+                        .map(|x| column(*x))
+                        .collect::<Result<TreatmentsType, PayloadError>>()?,
+                };
+                plan.check(num_sources)
+                    .map_err(PayloadError::BadTreatmentPlan)?;
+                Ok(plan)
+            };
+        let plan: TreatmentPlan = match &self.treatments {
+            Some(treatments) => to_plan("optimizer_plan", treatments)?,
+            None => TreatmentPlan::identity(num_sources),
+        };
+        let treatment_plans: Vec<TreatmentPlan> = if self.treatment_plans.is_empty() {
+            default_treatment_plans(num_sources)
+        } else {
+            self.treatment_plans
+                .iter()
+                .map(|(name, treatments)| to_plan(name, treatments))
+                .collect::<Result<Vec<TreatmentPlan>, PayloadError>>()?
+        };
+        let mut material_sources: Vec<String> = default_material_sources(NUM_TREATMENTS);
+        material_sources.splice(
+            Treatment::Market.index()..Treatment::Market.index(),
+            self.sources.iter().cloned(),
+        );
+        Ok(Payload {
+            schema_version: PAYLOAD_SCHEMA_VERSION,
+            material_info,
+            material_sources,
+            optimizer_plan: Some(plan.plan),
+            treatment_plans,
+            choose_treatment: self.choose_treatment,
+            upgrade_info,
+            special_budget: self.special_budget,
+            special_state: None,
+            tier,
+            data_name: None,
+            express_event: false,
+            events: self.events,
+            min_resolution: self.min_resolution,
+            num_threads: self.num_threads,
+            metric_type: self.metric as i64,
+            adv_cache: None,
+            price_scenarios: self.price_scenarios,
+            market_depth,
+            upgrade_attribution: true,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::performance::Performance;
+    use crate::state_bundle::StateBundle;
+    use crate::treatment::best_treatment;
+
+    #[test]
+    fn builder_matches_wire_format() {
+        let breath = MaterialRow::Juice {
+            id: JuiceId(0),
+            weapon: false,
+        };
+        let payload = PayloadBuilder::new(Tier::T4)
+            .normal(Piece::Weapon, 21)
+            .advanced(Piece::Helmet, 40)
+            .owned(MaterialRow::Base(Material::Red), Treatment::Tradable, 100.0)
+            .price(breath, Treatment::Market, 400.0)
+            .build()
+            .unwrap();
+        let num_juice_avail = Tier::T4.num_juice_avail().unwrap();
+        assert_eq!(payload.material_info.len(), 7 + 2 * num_juice_avail);
+        assert_eq!(payload.material_info[0][2].owned, 100.0);
+        assert_eq!(
+            payload.material_info[7 + num_juice_avail][3],
+            MaterialTier::tradable(0.0, 400.0)
+        );
+        assert!(!payload.material_info[0][1].sellable);
+        assert_eq!(
+            MaterialRow::from_index(7 + num_juice_avail, num_juice_avail),
+            Some(breath)
+        );
+        assert_eq!(payload.upgrade_info[0].piece_type, 5);
+        assert_eq!(payload.upgrade_info[0].upgrade_index, 20);
+        assert_eq!(payload.upgrade_info[1].upgrade_index, 3);
+
+        let mut state_bundle = StateBundle::init_from_payload(payload).unwrap();
+        let mut performance = Performance::new();
+        assert!(state_bundle.metric_router(&mut performance).is_finite());
+
+        assert_eq!(
+            PayloadBuilder::new(Tier::Serca)
+                .price(
+                    MaterialRow::Juice {
+                        id: JuiceId(num_juice_avail),
+                        weapon: true
+                    },
+                    Treatment::Market,
+                    1.0
+                )
+                .build()
+                .err(),
+            Some(PayloadError::JuiceOutOfRange {
+                id: JuiceId(num_juice_avail),
+                num_juice_avail: Tier::Serca.num_juice_avail().unwrap()
+            })
+        );
+        assert!(matches!(
+            PayloadBuilder::new(Tier::T4)
+                .normal(Piece::Glove, 26)
+                .build(),
+            Err(PayloadError::NormalOutOfRange { .. })
+        ));
+    }
+
+    #[test]
+    fn extra_sources_go_before_market() {
+        let red = MaterialRow::Base(Material::Red);
+        let payload = PayloadBuilder::new(Tier::T4)
+            .normal(Piece::Weapon, 21)
+            .source("guild shop")
+            .owned(red, Treatment::Source(0), 500.0)
+            .price(red, Treatment::Source(0), 1.0)
+            .price(red, Treatment::Market, 2.0)
+            .sell_price(red, Treatment::Source(0), 0.8)
+            .treatment_plan(
+                "shop as bound",
+                &[
+                    Treatment::CharBound,
+                    Treatment::RosterBound,
+                    Treatment::RosterBound,
+                    Treatment::RosterBound,
+                    Treatment::Market,
+                ],
+            )
+            .treatment_plan(
+                "shop sold",
+                &[
+                    Treatment::CharBound,
+                    Treatment::RosterBound,
+                    Treatment::RosterBound,
+                    Treatment::Source(0),
+                    Treatment::Market,
+                ],
+            )
+            .choose_treatment()
+            .build()
+            .unwrap();
+        assert_eq!(payload.material_sources[3], "guild shop");
+        assert_eq!(payload.material_sources[4], "market");
+        assert_eq!(
+            payload.material_info[0][3],
+            MaterialTier {
+                owned: 500.0,
+                buy: 1.0,
+                sell: 0.8,
+                sellable: true
+            }
+        );
+        assert_eq!(payload.material_info[0][4].buy, 2.0);
+        assert_eq!(payload.treatment_plans[1].plan, vec![0, 1, 1, 3, 4]);
+        assert_eq!(payload.optimizer_plan, Some(vec![0, 1, 2, 3, 4]));
+
+        let state_bundle = StateBundle::init_from_payload(payload).unwrap();
+        let candidates: Vec<StateBundle> = (0..2)
+            .map(|treatment_index| {
+                let mut this = state_bundle.clone();
+                this.set_treatment(treatment_index);
+                this
+            })
+            .collect();
+        let (best, index) = best_treatment(candidates, &mut Performance::new());
+        assert_eq!(best.prep_output.treatment_index(), Some(index));
+
+        assert_eq!(
+            PayloadBuilder::new(Tier::T4)
+                .price(red, Treatment::Source(0), 1.0)
+                .build()
+                .err(),
+            Some(PayloadError::UnknownSource {
+                index: 0,
+                num_sources: 0
+            })
+        );
+        assert!(matches!(
+            PayloadBuilder::new(Tier::T4)
+                .treatment_plan("too short", &[Treatment::Market])
+                .build(),
+            Err(PayloadError::BadTreatmentPlan(_))
+        ));
+    }
+}
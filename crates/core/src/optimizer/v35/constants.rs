@@ -28,3 +28,5 @@ pub const COOLING_PHASE_START: f64 = 0.65;
 // pub const DEFAULT_RESOLUTION: usize = 10;
 pub const SPECIAL_AFFINITY_DECAY: f64 = 0.999;
 pub const SPECIAL_AFFINITY_GROWTH: f64 = 1.02;
+pub const SCALER_BATCH_SIZE: usize = 50;
+pub const SCALER_LEARNING_RATE: f64 = 0.1;
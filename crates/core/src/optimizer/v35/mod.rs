@@ -11,9 +11,11 @@
 //! Which is also why I'm commenting here rather than the actual algorithm (and not cleaning up) cos they're very much subject to change
 
 mod simulated_annealing;
-pub use simulated_annealing::solve;
+pub use simulated_annealing::{solve, solve_with_params};
 mod constants;
 mod neighbour;
 mod one_batch;
+mod params;
+pub use params::AnnealParams;
 mod scaler;
 pub const NOTES: &str = "v35, v34 but with self crossover ";
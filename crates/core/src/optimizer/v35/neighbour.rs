@@ -12,7 +12,6 @@ use rand::distr::weighted::WeightedIndex;
 use rand::{Rng, random_bool, random_range};
 
 // use std::f64::{MAX, MIN};
-use super::constants::*;
 
 impl SolverStateBundle {
     pub fn neighbour(&mut self) -> bool {
@@ -45,6 +44,7 @@ impl SolverStateBundle {
             let num_upgrades = self.state_bundle.upgrade_arr.len();
             let max_mutations = (num_upgrades as f64 * progress).ceil().min(1.0) as usize;
             // let num_to_mutate = rng.random_range(1..=max_mutations.max(1));
+            let non_impact_weight = self.params.non_impact_weight;
             let mut already_mutated: Vec<bool> = vec![false; self.state_bundle.upgrade_arr.len()];
 
             for _ in 0..max_mutations.max(2.min(num_upgrades)) {
@@ -54,7 +54,7 @@ impl SolverStateBundle {
                             if *alr {
                                 0.0
                             } else {
-                                x * (1.0 - progress) + NON_IMPACT_WEIGHT * progress
+                                x * (1.0 - progress) + non_impact_weight * progress
                             }
                         },
                     ))
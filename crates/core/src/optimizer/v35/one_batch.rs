@@ -1,6 +1,7 @@
 // use std::f64::NAN;
 
 use super::constants::*;
+use super::params::AnnealParams;
 use super::scaler::AdaptiveScaler;
 use crate::performance::Performance;
 use crate::state_bundle::StateBundle;
@@ -26,17 +27,18 @@ pub struct SolverStateBundle {
     pub temps_without_improvement: i64,
     pub upgrade_impact: Vec<f64>,
     pub special_affinity: f64,
+    pub params: AnnealParams,
 }
 
 impl SolverStateBundle {
     pub fn initialize(
         state_bundle: &StateBundle,
         scaler: AdaptiveScaler,
-        _max_state_len: usize,
         performance: Performance,
         seed: u64,
         best_n_states: &DoublePriorityQueue<StateEssence, OrderedFloat<f64>>,
         upgrade_impact: &Vec<f64>,
+        params: AnnealParams,
     ) -> Self {
         Self {
             state_bundle: state_bundle.clone(),
@@ -52,6 +54,7 @@ impl SolverStateBundle {
             count: 0,
             temps_without_improvement: 0,
             upgrade_impact: upgrade_impact.clone(),
+            params,
         }
     }
     pub fn perform_crossover(&mut self) {
@@ -108,16 +111,22 @@ impl SolverStateBundle {
     }
 
     pub fn lam_rate(&self) -> f64 {
-        if self.progress() < WARM_UP_PHASE_END {
-            MAGIC_NUMBER
-                + (1.0 - MAGIC_NUMBER)
-                    * (MAGIC_NUMBER * 1000.0).powf(-self.progress() / WARM_UP_PHASE_END)
-        } else if self.progress() < COOLING_PHASE_START {
-            MAGIC_NUMBER
+        let AnnealParams {
+            magic_number,
+            warm_up_phase_end,
+            cooling_phase_start,
+            ..
+        } = self.params;
+        if self.progress() < warm_up_phase_end {
+            magic_number
+                + (1.0 - magic_number)
+                    * (magic_number * 1000.0).powf(-self.progress() / warm_up_phase_end)
+        } else if self.progress() < cooling_phase_start {
+            magic_number
         } else {
-            MAGIC_NUMBER
-                * (MAGIC_NUMBER * 1000.0)
-                    .powf(-(self.progress() - COOLING_PHASE_START) / (1.0 - COOLING_PHASE_START)) // i mean this 1000 seems to work fine for all MAX_ITERS so whatever
+            magic_number
+                * (magic_number * 1000.0)
+                    .powf(-(self.progress() - cooling_phase_start) / (1.0 - cooling_phase_start)) // i mean this 1000 seems to work fine for all MAX_ITERS so whatever
         }
     }
     // pub fn one_batch(&mut self, batch_iters: i64) {
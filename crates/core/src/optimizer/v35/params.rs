@@ -0,0 +1,31 @@
+//! The hand-tuned knobs of the annealing, defaults are the constants in constants.rs.
+//! Only `arena tune` really needs anything other than the default.
+use super::constants::*;
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct AnnealParams {
+    pub magic_number: f64, // target acceptance rate in the middle phase
+    pub warm_up_phase_end: f64,
+    pub cooling_phase_start: f64,
+    pub special_affinity_decay: f64,
+    pub special_affinity_growth: f64,
+    pub non_impact_weight: f64,
+    pub scaler_batch_size: usize,
+    pub scaler_learning_rate: f64,
+}
+
+impl Default for AnnealParams {
+    fn default() -> Self {
+        Self {
+            magic_number: MAGIC_NUMBER,
+            warm_up_phase_end: WARM_UP_PHASE_END,
+            cooling_phase_start: COOLING_PHASE_START,
+            special_affinity_decay: SPECIAL_AFFINITY_DECAY,
+            special_affinity_growth: SPECIAL_AFFINITY_GROWTH,
+            non_impact_weight: NON_IMPACT_WEIGHT,
+            scaler_batch_size: SCALER_BATCH_SIZE,
+            scaler_learning_rate: SCALER_LEARNING_RATE,
+        }
+    }
+}
@@ -14,13 +14,13 @@ pub struct AdaptiveScaler {
 }
 
 impl AdaptiveScaler {
-    pub fn new(initial_guess: f64, batch_size: usize) -> Self {
+    pub fn new(initial_guess: f64, batch_size: usize, learning_rate: f64) -> Self {
         Self {
             current_scale: initial_guess,
             uphill_count: 0,
             accepted_count: 0,
             batch_size,
-            learning_rate: 0.1,
+            learning_rate,
         }
     }
 
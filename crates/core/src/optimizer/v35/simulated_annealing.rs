@@ -16,6 +16,7 @@ use rand::Rng;
 
 use super::constants::*;
 use super::one_batch::SolverStateBundle;
+use super::params::AnnealParams;
 use crate::timer::Timer;
 
 pub fn my_push(
@@ -117,20 +118,26 @@ fn maybe_send_wasm_progress(
     elapsed
 }
 pub fn solve<R: Rng>(
+    rng: &mut R,
+    state_bundle: StateBundle,
+    overall_performance: &mut Performance,
+) -> StateBundle {
+    solve_with_params(
+        rng,
+        state_bundle,
+        overall_performance,
+        AnnealParams::default(),
+    )
+}
+
+pub fn solve_with_params<R: Rng>(
     rng: &mut R,
     mut state_bundle: StateBundle,
     overall_performance: &mut Performance,
+    params: AnnealParams,
 ) -> StateBundle {
     let timer = Timer::start();
 
-    let max_state_len = state_bundle
-        .upgrade_arr
-        .iter()
-        .map(|u| u.state.len())
-        .max()
-        .unwrap_or(state_bundle.min_resolution)
-        .max(state_bundle.min_resolution);
-
     state_bundle.metric = state_bundle.metric_router(overall_performance);
     state_bundle.set_latest_special_probs();
 
@@ -139,7 +146,11 @@ pub fn solve<R: Rng>(
     }
 
     let mut eqv_wall_time_iters: i64 = 0;
-    let scaler = AdaptiveScaler::new(state_bundle.metric.abs(), 50);
+    let scaler = AdaptiveScaler::new(
+        state_bundle.metric.abs(),
+        params.scaler_batch_size,
+        params.scaler_learning_rate,
+    );
 
     let upgrade_impacts = compute_upgrade_impact(&mut state_bundle);
 
@@ -152,11 +163,11 @@ pub fn solve<R: Rng>(
     let mut solver_bundle: SolverStateBundle = SolverStateBundle::initialize(
         &state_bundle,
         scaler.clone(),
-        max_state_len,
         Performance::new(),
         rng.next_u64(),
         &best_n_states,
         &upgrade_impacts,
+        params,
     );
 
     send_initial_progress(
@@ -190,7 +201,7 @@ pub fn solve<R: Rng>(
         if solver_bundle.state_bundle.metric > best_metric {
             if mutate_special {
                 solver_bundle.special_affinity =
-                    (solver_bundle.special_affinity * SPECIAL_AFFINITY_GROWTH).min(1.0);
+                    (solver_bundle.special_affinity * params.special_affinity_growth).min(1.0);
             }
             my_push(
                 &mut solver_bundle.best_n_states,
@@ -199,7 +210,7 @@ pub fn solve<R: Rng>(
             );
             solver_bundle.temps_without_improvement = 0;
         } else if mutate_special {
-            solver_bundle.special_affinity *= SPECIAL_AFFINITY_DECAY;
+            solver_bundle.special_affinity *= params.special_affinity_decay;
         }
 
         let delta = (solver_bundle.prev_state.metric - solver_bundle.state_bundle.metric)
@@ -1,10 +1,15 @@
 use crate::advanced_honing::utils::{AdvConfig, AdvDistTriplet};
+use crate::constants::MARKET_TAX;
 use crate::constants::accessor::{
     get_artisan, get_data, get_event_extra_chance, get_normal_hone_chances, get_special_leap_cost,
 };
+use crate::constants::events::{EventEffects, EventInput};
 use crate::constants::juice_info::{JuiceInfo, get_priced_juice_info};
-use crate::constants::*;
+use crate::constants::registry::data;
 use crate::helpers::distribute_budgets;
+use crate::market_depth::{self, MarketDepth};
+use crate::model::{PayloadError, Piece};
+use crate::treatment::TreatmentPlan;
 use crate::upgrade::Upgrade;
 use ahash::AHashMap;
 use serde::{Deserialize, Serialize};
@@ -14,13 +19,137 @@ pub struct PreparationOutput {
     pub special_budget: i64,
     pub raw_material_info: MaterialInput,
     pub optimizer_plan: Vec<usize>,
-    pub optimizer_material_info: MaterialInput,
-    pub raw_num_breakpoints: usize,
+    pub optimizer_material_info: ThreshPrices,
+    pub raw_num_breakpoints: usize, // number of material sources (columns of raw_material_info)
+    pub treatment_plans: Vec<TreatmentPlan>, // what the UI shows and what the optimizer can choose from
     pub test_case: i64,
     pub juice_info: JuiceInfo,
+    pub events: EventEffects,
+    pub price_scenarios: Option<Vec<PriceScenario>>,
+    pub scenario_material_info: Vec<(f64, ThreshPrices)>, // (normalized weight, distributed budgets) per price scenario
+    pub market_depth: Vec<MarketDepth>, // [material type], empty is a flat buy price
 }
 
-pub type MaterialInput = Vec<Vec<(f64, f64)>>; // [material type][treatment plan].0 = owned, .1 = price
+pub type MaterialInput = Vec<Vec<MaterialTier>>; // [material type][material source]
+pub type ThreshPrices = Vec<Vec<(f64, f64)>>; // [material type] = (threshold, price) pairs from distribute_budgets
+
+/// One material source of one material
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct MaterialTier {
+    pub owned: f64,
+    pub buy: f64, // only the last source's gets used, that's what anything past all the owned mats costs
+    pub sell: f64, // what one left over is worth, after market tax
+    pub sellable: bool, // bound mats are worth nothing left over no matter what sell says
+}
+
+impl MaterialTier {
+    /// Can go on the market, so leftovers sell for the price minus MARKET_TAX
+    pub fn tradable(owned: f64, price: f64) -> MaterialTier {
+        MaterialTier {
+            owned,
+            buy: price,
+            sell: price * (1.0 - MARKET_TAX),
+            sellable: true,
+        }
+    }
+
+    pub fn bound(owned: f64, price: f64) -> MaterialTier {
+        MaterialTier {
+            owned,
+            buy: price,
+            sell: 0.0,
+            sellable: false,
+        }
+    }
+
+    pub fn leftover_value(&self) -> f64 {
+        if self.sellable { self.sell } else { 0.0 }
+    }
+}
+
+/// One possible state of the market, buy and sell prices in material_info are multiplied by multipliers[material type]
+#[derive(Debug, Deserialize, Clone, Serialize)]
+pub struct PriceScenario {
+    pub weight: f64,
+    pub multipliers: Vec<f64>,
+}
+
+impl PriceScenario {
+    /// Turns a (low, high) multiplier range per material into num_points equally weighted scenarios,
+    /// going from every material at its low end to every material at its high end.
+    /// We assume prices move together, which is usually the case on patch days / events
+    pub fn from_ranges(
+        ranges: &[(f64, f64)],
+        num_points: usize,
+    ) -> Result<Vec<PriceScenario>, String> {
+        if num_points == 0 {
+            return Err("price scenario ranges need at least 1 point".to_owned());
+        }
+        Ok((0..num_points)
+            .map(|point| {
+                let t: f64 = if num_points == 1 {
+                    0.5
+                } else {
+                    point as f64 / (num_points - 1) as f64
+                };
+                PriceScenario {
+                    weight: 1.0,
+                    multipliers: ranges
+                        .iter()
+                        .map(|(low, high)| low + t * (high - low))
+                        .collect(),
+                }
+            })
+            .collect())
+    }
+
+    /// Weights are normalized later so any non-negative weights do, as long as they don't add up to 0
+    pub fn check_all(scenarios: &[PriceScenario], num_mats: usize) -> Result<(), String> {
+        for (index, scenario) in scenarios.iter().enumerate() {
+            if scenario.multipliers.len() != num_mats {
+                return Err(format!(
+                    "price scenario {} has {} multipliers but there are {} materials",
+                    index,
+                    scenario.multipliers.len(),
+                    num_mats
+                ));
+            }
+            if !scenario.weight.is_finite()
+                || scenario.weight < 0.0
+                || scenario
+                    .multipliers
+                    .iter()
+                    .any(|x| !x.is_finite() || *x < 0.0)
+            {
+                return Err(format!(
+                    "price scenario {} has a negative or non-finite number in it",
+                    index
+                ));
+            }
+        }
+        if !scenarios.is_empty() && scenarios.iter().map(|x| x.weight).sum::<f64>() <= 0.0 {
+            return Err("price scenario weights add up to 0".to_owned());
+        }
+        Ok(())
+    }
+
+    /// multipliers has to be as long as material_info, see check_all
+    pub fn apply(&self, material_info: &MaterialInput) -> MaterialInput {
+        material_info
+            .iter()
+            .zip(self.multipliers.iter())
+            .map(|(row, mult)| {
+                row.iter()
+                    .map(|tier| MaterialTier {
+                        buy: tier.buy * mult,
+                        sell: tier.sell * mult,
+                        ..*tier
+                    })
+                    .collect()
+            })
+            .collect()
+    }
+}
 
 #[derive(Deserialize, Clone, Serialize)]
 pub struct OneUpgradeInput {
@@ -34,22 +163,44 @@ pub struct OneUpgradeInput {
     pub adv_progress: Option<(usize, usize, bool, bool)>,
 }
 
+/// What initialize hands back to the StateBundle, the adv cache is the input one plus whatever parsing had to add
+pub type Prepared = (
+    PreparationOutput,
+    Vec<Upgrade>,
+    AHashMap<AdvConfig, AdvDistTriplet>,
+);
+
 impl PreparationOutput {
     pub fn initialize(
         raw_material_info: MaterialInput,
         inp_optimizer_plan: Option<Vec<usize>>,
+        treatment_plans: Vec<TreatmentPlan>,
         upgrade_info: Vec<OneUpgradeInput>,
         special_budget: i64,
-        express_event: bool,
+        event_inputs: &[EventInput],
         tier: usize,
         inp_adv_cache: Option<AHashMap<AdvConfig, AdvDistTriplet>>,
-    ) -> (
-        PreparationOutput,
-        Vec<Upgrade>,
-        AHashMap<AdvConfig, AdvDistTriplet>,
-    ) {
+        price_scenarios: Option<Vec<PriceScenario>>,
+        market_depth: Option<Vec<MarketDepth>>,
+    ) -> Result<Prepared, PayloadError> {
+        let raw_num_breakpoints = raw_material_info.first().map_or(0, |x| x.len());
+        if raw_num_breakpoints == 0 {
+            return Err(PayloadError::NoSources);
+        }
+        if let Some((row, x)) = raw_material_info
+            .iter()
+            .enumerate()
+            .find(|(_, x)| x.len() != raw_num_breakpoints)
+        {
+            return Err(PayloadError::ColumnMismatch {
+                row,
+                columns: x.len(),
+                num_sources: raw_num_breakpoints,
+            });
+        }
+        let events: EventEffects = EventEffects::resolve(event_inputs, tier)?;
         let juice_info: JuiceInfo =
-            get_priced_juice_info(&BASE_JUICE_INFOS[tier], &raw_material_info, express_event);
+            get_priced_juice_info(&data(tier).base_juice_info, &raw_material_info, &events);
         let mut adv_cache: AHashMap<AdvConfig, AdvDistTriplet> = if inp_adv_cache.is_none() {
             AHashMap::new()
         } else {
@@ -65,7 +216,8 @@ impl PreparationOutput {
                             && start_balls == key.start_balls
                             && next_free == key.next_free
                             && next_big == key.next_big
-                            && (express_event && upgrade.upgrade_index < 2) == key.double_balls
+                            && (events.double_balls && upgrade.upgrade_index < 2)
+                                == key.double_balls
                             && ((upgrade.upgrade_index >= 2) == key.is_30_40);
                     }
                     false
@@ -74,53 +226,117 @@ impl PreparationOutput {
             inp
         };
 
-        let upgrade_arr: Vec<Upgrade> = parser(
-            upgrade_info,
-            express_event,
-            &juice_info,
-            tier,
-            &mut adv_cache,
-        );
+        let upgrade_arr: Vec<Upgrade> =
+            parser(upgrade_info, &events, &juice_info, tier, &mut adv_cache);
         let optimizer_plan = if inp_optimizer_plan.is_none() {
-            (0..raw_material_info.len()).collect::<Vec<usize>>()
+            (0..raw_num_breakpoints).collect::<Vec<usize>>()
         } else {
             inp_optimizer_plan.unwrap()
         };
+        for plan in treatment_plans
+            .iter()
+            .chain(std::iter::once(&TreatmentPlan {
+                name: "optimizer_plan".to_owned(),
+                plan: optimizer_plan.clone(),
+            }))
+        {
+            plan.check(raw_num_breakpoints)
+                .map_err(PayloadError::BadTreatmentPlan)?;
+        }
+        let market_depth: Vec<MarketDepth> =
+            market_depth.unwrap_or_else(|| vec![Vec::new(); raw_material_info.len()]);
+        if market_depth.len() != raw_material_info.len() {
+            return Err(PayloadError::BadMarketDepth(format!(
+                "market_depth has {} rows but there are {} materials",
+                market_depth.len(),
+                raw_material_info.len()
+            )));
+        }
+        for depth in market_depth.iter() {
+            market_depth::check(depth).map_err(PayloadError::BadMarketDepth)?;
+        }
+        if let Some(scenarios) = &price_scenarios {
+            PriceScenario::check_all(scenarios, raw_material_info.len())
+                .map_err(PayloadError::BadPriceScenario)?;
+        }
         let optimizer_material_info = distribute_budgets(&raw_material_info, &optimizer_plan);
         // my_dbg!(
         //     &raw_material_info,
         //     &optimizer_material_info,
         //     &optimizer_plan
         // );
-        let raw_num_breakpoints = raw_material_info[0].len();
-        assert!(raw_num_breakpoints > 0);
-        let out: PreparationOutput = Self {
+        let mut out: PreparationOutput = Self {
             // upgrade_arr,
             raw_material_info,
             optimizer_material_info,
             optimizer_plan,
             raw_num_breakpoints,
+            treatment_plans,
             special_budget,
             test_case: -1, // arena will overwrite this
             juice_info,
+            events,
+            price_scenarios,
+            scenario_material_info: Vec::new(),
+            market_depth,
+        };
+        out.distribute_scenarios();
+
+        Ok((out, upgrade_arr, adv_cache))
+    }
+}
+
+impl PreparationOutput {
+    fn distribute_scenarios(&mut self) {
+        self.scenario_material_info = match &self.price_scenarios {
+            Some(scenarios) if !scenarios.is_empty() => {
+                let total_weight: f64 = scenarios.iter().map(|x| x.weight).sum(); // > 0, see PriceScenario::check_all
+                scenarios
+                    .iter()
+                    .map(|scenario| {
+                        (
+                            scenario.weight / total_weight,
+                            distribute_budgets(
+                                &scenario.apply(&self.raw_material_info),
+                                &self.optimizer_plan,
+                            ),
+                        )
+                    })
+                    .collect()
+            }
+            _ => vec![(1.0, self.optimizer_material_info.clone())],
         };
+    }
+
+    /// Swaps out owned amounts / prices without re-parsing the upgrades (which is expensive for adv honing)
+    pub fn set_material_info(&mut self, raw_material_info: MaterialInput) {
+        assert!(raw_material_info.len() == self.raw_material_info.len());
+        self.optimizer_material_info = distribute_budgets(&raw_material_info, &self.optimizer_plan);
+        self.raw_material_info = raw_material_info;
+        self.distribute_scenarios();
+    }
 
-        (out, upgrade_arr, adv_cache)
+    /// Same as set_material_info but for the treatment plan, see treatment.rs
+    pub fn set_optimizer_plan(&mut self, optimizer_plan: Vec<usize>) {
+        assert!(optimizer_plan.len() == self.raw_num_breakpoints);
+        self.optimizer_material_info = distribute_budgets(&self.raw_material_info, &optimizer_plan);
+        self.optimizer_plan = optimizer_plan;
+        self.distribute_scenarios();
     }
 }
 
 /// Constructs vector of Upgrade objects according to what upgrades were selected and the appropriate juice applied
 pub fn parser(
     upgrade_info: Vec<OneUpgradeInput>,
-    express_event: bool,
+    events: &EventEffects,
     juice_info: &JuiceInfo,
     tier: usize,
     adv_cache: &mut AHashMap<AdvConfig, AdvDistTriplet>,
 ) -> Vec<Upgrade> {
     let mut out: Vec<Upgrade> = Vec::new();
 
-    let artisan_rate_arr = get_artisan(express_event, tier);
-    let event_extra_arr = get_event_extra_chance(express_event, tier);
+    let artisan_rate_arr = get_artisan(events);
+    let event_extra_arr = get_event_extra_chance(events);
     let special_leap_cost = get_special_leap_cost(tier);
     let normal_hone_chances = get_normal_hone_chances(tier);
 
@@ -135,20 +351,9 @@ pub fn parser(
         adv_progress,
     } in upgrade_info
     {
-        let relevant_cost = get_data(
-            express_event,
-            tier,
-            !is_normal_honing,
-            piece_type == 5,
-            false,
-        );
-        let relevant_unlock = get_data(
-            express_event,
-            tier,
-            !is_normal_honing,
-            piece_type == 5,
-            true,
-        );
+        let is_weapon: bool = piece_type == Piece::Weapon.index();
+        let relevant_cost = get_data(events, tier, !is_normal_honing, is_weapon, false);
+        let relevant_unlock = get_data(events, tier, !is_normal_honing, is_weapon, true);
         let this_cost =
             &Vec::from_iter((0..7).map(|cost_type| relevant_cost[cost_type][upgrade_index]));
         let this_unlock =
@@ -157,8 +362,7 @@ pub fn parser(
         let this_state_given: Vec<(bool, usize)> = state.unwrap_or(Vec::new());
 
         if is_normal_honing {
-            let special_cost: i64 =
-                special_leap_cost[if piece_type == 5 { 1 } else { 0 }][upgrade_index];
+            let special_cost: i64 = special_leap_cost[if is_weapon { 1 } else { 0 }][upgrade_index];
             let event_artisan_rate: f64 = artisan_rate_arr[upgrade_index];
             let starting_artisan: f64 = starting_artisan.unwrap();
             let starting_num_taps: usize = starting_num_taps.unwrap_or(0);
@@ -166,7 +370,7 @@ pub fn parser(
                 normal_hone_chances[upgrade_index],
                 this_cost,
                 special_cost,
-                piece_type == 5,
+                is_weapon,
                 piece_type,
                 event_artisan_rate,
                 upgrade_index,
@@ -183,13 +387,13 @@ pub fn parser(
 
             out.push(Upgrade::new_adv(
                 this_cost,
-                piece_type == 5,
+                is_weapon,
                 piece_type,
                 upgrade_index,
                 this_unlock,
                 this_unlocked,
                 this_adv_progress,
-                express_event,
+                events.double_balls,
                 juice_info,
                 adv_cache,
                 this_state_given,
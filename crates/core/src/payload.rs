@@ -1,8 +1,12 @@
 //! Payload is how js and rust communicates, we also use payload as our test cases in arena
 use crate::advanced_honing::utils::{AdvConfig, AdvDistTriplet};
-use crate::parser::{MaterialInput, OneUpgradeInput, PreparationOutput};
+use crate::constants::events::{EXPRESS_EVENT_NAME, EventInput};
+use crate::constants::registry::{DataError, data_entry, tier_by_name};
+use crate::market_depth::MarketDepth;
+use crate::model::PayloadError;
+use crate::parser::{MaterialInput, OneUpgradeInput, PreparationOutput, Prepared, PriceScenario};
 use crate::state_bundle::StateBundle;
-use crate::upgrade::Upgrade;
+use crate::treatment::TreatmentPlan;
 use ahash::AHashMap;
 use serde::{Deserialize, Serialize};
 use std::fs;
@@ -10,58 +14,105 @@ use std::path::Path;
 
 #[derive(Deserialize, Clone, Serialize)]
 pub struct Payload {
+    pub schema_version: u32, // PAYLOAD_SCHEMA_VERSION, anything older has to go through Payload::from_json
     pub material_info: MaterialInput,
+    pub material_sources: Vec<String>, // names of the columns of material_info
     pub optimizer_plan: Option<Vec<usize>>,
+    pub treatment_plans: Vec<TreatmentPlan>,
+    pub choose_treatment: bool, // let the optimizer pick from treatment_plans instead of sticking to optimizer_plan
 
     pub upgrade_info: Vec<OneUpgradeInput>,
     pub special_budget: i64,
 
     pub special_state: Option<Vec<usize>>,
     pub tier: usize,
-    pub express_event: bool,
+    #[serde(default)]
+    pub data_name: Option<String>, // name of the data in the registry, takes priority over tier
+    pub express_event: bool, // shorthand for listing the express event in events
+    #[serde(default)]
+    pub events: Vec<EventInput>,
 
     pub min_resolution: usize,
-    #[serde(default)]
     pub num_threads: usize,
-    #[serde(default = "default_one")]
-    pub metric_type: i64,
+    pub metric_type: i64, // 1 = average gold, 2 = expected over price_scenarios, 3 = worst case over price_scenarios
 
     pub adv_cache: Option<AHashMap<AdvConfig, AdvDistTriplet>>,
+    #[serde(default)]
+    pub price_scenarios: Option<Vec<PriceScenario>>,
+    #[serde(default)]
+    pub market_depth: Option<Vec<MarketDepth>>, // [material type], see market_depth.rs
+    #[serde(default = "default_true")]
+    pub upgrade_attribution: bool, // histogram also splits the cost per upgrade, that's n + 2 more metric evaluations
 }
-fn default_one() -> i64 {
+pub(crate) fn default_one() -> i64 {
     1
 }
+pub(crate) fn default_true() -> bool {
+    true
+}
+impl Payload {
+    pub fn active_events(&self) -> Vec<EventInput> {
+        let mut out = self.events.clone();
+        let express = EventInput::Named(EXPRESS_EVENT_NAME.to_owned());
+        if self.express_event && !out.contains(&express) {
+            out.insert(0, express);
+        }
+        out
+    }
+
+    pub fn resolve_tier(&self) -> Result<usize, DataError> {
+        match &self.data_name {
+            Some(name) => tier_by_name(name),
+            None => data_entry(self.tier).map(|_| self.tier),
+        }
+    }
+
+    /// Takes spent (per material row) out of the owned amounts in column order (char-bound first), returns what had to be bought
+    pub fn use_up(&mut self, spent: &[f64]) -> Vec<f64> {
+        let mut bought: Vec<f64> = spent.to_vec();
+        for (row, remaining) in self.material_info.iter_mut().zip(bought.iter_mut()) {
+            for tier in row.iter_mut() {
+                let used = tier.owned.min(*remaining);
+                tier.owned -= used;
+                *remaining -= used;
+            }
+        }
+        bought
+    }
+}
 impl StateBundle {
     pub fn init_from_inputs(
         material_info: MaterialInput,
         optimizer_plan: Option<Vec<usize>>,
+        treatment_plans: Vec<TreatmentPlan>,
         upgrade_info: Vec<OneUpgradeInput>,
         special_budget: i64,
-        express_event: bool,
+        events: Vec<EventInput>,
         tier: usize,
         special_state: Option<Vec<usize>>,
         min_resolution: usize,
         num_threads: usize,
         metric_type: i64,
         adv_cache: Option<AHashMap<AdvConfig, AdvDistTriplet>>,
-    ) -> StateBundle {
-        let (prep_output, upgrade_arr, adv_cache): (
-            PreparationOutput,
-            Vec<Upgrade>,
-            AHashMap<AdvConfig, AdvDistTriplet>,
-        ) = PreparationOutput::initialize(
+        price_scenarios: Option<Vec<PriceScenario>>,
+        market_depth: Option<Vec<MarketDepth>>,
+    ) -> Result<StateBundle, PayloadError> {
+        let (prep_output, upgrade_arr, adv_cache): Prepared = PreparationOutput::initialize(
             material_info,
             optimizer_plan,
+            treatment_plans,
             upgrade_info,
             special_budget,
-            express_event,
+            &events,
             tier,
             adv_cache,
-        );
+            price_scenarios,
+            market_depth,
+        )?;
         let u_len = upgrade_arr.len();
         // web_sys::console::log_1(&"2".into());
 
-        StateBundle {
+        Ok(StateBundle {
             upgrade_arr,
             special_state: if special_state.is_none()
                 || special_state.as_ref().unwrap().len() != u_len
@@ -80,21 +131,40 @@ impl StateBundle {
             num_threads,
 
             adv_cache,
-        }
+        })
     }
-    pub fn init_from_payload(payload: Payload) -> Self {
+    /// Fails if the payload points at data that isn't registered (or doesn't fit it)
+    pub fn init_from_payload(payload: Payload) -> Result<Self, PayloadError> {
+        let events = payload.active_events();
+        let tier = payload.resolve_tier()?;
+        let num_sources = payload.material_sources.len();
+        if let Some((row, x)) = payload
+            .material_info
+            .iter()
+            .enumerate()
+            .find(|(_, x)| x.len() != num_sources)
+        {
+            return Err(PayloadError::ColumnMismatch {
+                row,
+                columns: x.len(),
+                num_sources,
+            });
+        }
         StateBundle::init_from_inputs(
             payload.material_info,
             payload.optimizer_plan,
+            payload.treatment_plans,
             payload.upgrade_info,
             payload.special_budget,
-            payload.express_event,
-            payload.tier,
+            events,
+            tier,
             payload.special_state,
             payload.min_resolution,
             payload.num_threads,
             payload.metric_type,
             payload.adv_cache,
+            payload.price_scenarios,
+            payload.market_depth,
         )
     }
 }
@@ -128,7 +198,8 @@ pub fn parse_to_payloads(path: &Path) -> Vec<(String, Payload)> {
             .map(|stem| stem.to_string_lossy().to_string())
             .unwrap_or_else(|| "N/A".to_string());
         let contents = fs::read_to_string(&file_path).unwrap();
-        let payload: Payload = serde_json::from_str(&contents).unwrap();
+        let payload: Payload = Payload::from_json(&contents)
+            .unwrap_or_else(|e| panic!("Failed to load {:?}: {}", file_path, e));
         out.push((test_case_name, payload));
     }
     out
@@ -137,7 +208,8 @@ pub fn parse_to_payloads(path: &Path) -> Vec<(String, Payload)> {
 pub fn parse_to_state_bundles(path: &Path) -> Vec<(String, StateBundle)> {
     let mut out: Vec<(String, StateBundle)> = Vec::new();
     for (test_case_name, payload) in parse_to_payloads(path) {
-        let state_bundle = StateBundle::init_from_payload(payload);
+        let state_bundle = StateBundle::init_from_payload(payload)
+            .unwrap_or_else(|e| panic!("Failed to load {}: {}", test_case_name, e));
 
         out.push((test_case_name, state_bundle));
     }
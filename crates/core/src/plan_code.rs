@@ -0,0 +1,414 @@
+//! Short codes for sharing a plan (every upgrade's state + special_state) in chat, and loading it back.
+//!
+//! A code is "hf1." followed by url safe base64 of:
+//! - a flags byte (bit 0 = the payload is included)
+//! - every upgrade's state run length encoded, which is tiny because states are streaks
+//! - special_state
+//! - the payload as json if included (without the states, those are already in the plan)
+//! - a crc32 of everything before it, so a code that got cut off or mistyped is rejected instead of loading the wrong plan
+use crate::advanced_honing::utils::MAX_ADV_STATE;
+use crate::migration::MigrationError;
+use crate::model::PayloadError;
+use crate::payload::Payload;
+use crate::state_bundle::{StateBundle, StateEssence};
+use base64::Engine;
+use base64::alphabet::URL_SAFE;
+use base64::engine::{DecodePaddingMode, GeneralPurpose, GeneralPurposeConfig};
+use std::fmt;
+
+pub const PLAN_CODE_PREFIX: &str = "hf1.";
+const FLAG_PAYLOAD: u8 = 1;
+// lenient about the last few bits so that a code that got cut off fails the checksum rather than the base64
+const ENGINE: GeneralPurpose = GeneralPurpose::new(
+    &URL_SAFE,
+    GeneralPurposeConfig::new()
+        .with_encode_padding(false)
+        .with_decode_allow_trailing_bits(true)
+        .with_decode_padding_mode(DecodePaddingMode::Indifferent),
+);
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum PlanCodeError {
+    BadPrefix,
+    BadBase64,
+    BadChecksum,
+    Truncated,
+    UnknownFlags(u8),
+    Payload(MigrationError),
+    NoPayload,
+    Mismatch(String),
+    BadInputs(PayloadError), // the payload parsed fine but doesn't fit the data
+}
+
+impl fmt::Display for PlanCodeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PlanCodeError::BadPrefix => {
+                write!(
+                    f,
+                    "Not a plan code (should start with {})",
+                    PLAN_CODE_PREFIX
+                )
+            }
+            PlanCodeError::BadBase64 => write!(f, "Plan code has characters it shouldn't have"),
+            PlanCodeError::BadChecksum => {
+                write!(
+                    f,
+                    "Plan code doesn't check out, it was probably cut off or mistyped"
+                )
+            }
+            PlanCodeError::Truncated => write!(f, "Plan code ends too early"),
+            PlanCodeError::UnknownFlags(flags) => {
+                write!(
+                    f,
+                    "Plan code has flags {} that this version doesn't know",
+                    flags
+                )
+            }
+            PlanCodeError::Payload(e) => write!(f, "Plan code's payload is broken: {}", e),
+            PlanCodeError::NoPayload => {
+                write!(
+                    f,
+                    "Plan code doesn't include the inputs and none were given"
+                )
+            }
+            PlanCodeError::Mismatch(reason) => {
+                write!(f, "Plan code doesn't fit these upgrades: {}", reason)
+            }
+            PlanCodeError::BadInputs(e) => write!(f, "Plan code's inputs don't work: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for PlanCodeError {}
+
+fn crc32(bytes: &[u8]) -> u32 {
+    let mut crc: u32 = !0;
+    for byte in bytes {
+        crc ^= *byte as u32;
+        for _ in 0..8 {
+            crc = if crc & 1 == 1 {
+                (crc >> 1) ^ 0xEDB88320
+            } else {
+                crc >> 1
+            };
+        }
+    }
+    !crc
+}
+
+fn push_varint(out: &mut Vec<u8>, mut x: u64) {
+    while x >= 0x80 {
+        out.push((x as u8) | 0x80);
+        x >>= 7;
+    }
+    out.push(x as u8);
+}
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+}
+
+impl Reader<'_> {
+    fn byte(&mut self) -> Result<u8, PlanCodeError> {
+        let out = *self.bytes.get(self.pos).ok_or(PlanCodeError::Truncated)?;
+        self.pos += 1;
+        Ok(out)
+    }
+    fn varint(&mut self) -> Result<usize, PlanCodeError> {
+        let mut out: u64 = 0;
+        for shift in (0..64).step_by(7) {
+            let byte = self.byte()?;
+            out |= ((byte & 0x7F) as u64) << shift;
+            if byte & 0x80 == 0 {
+                return usize::try_from(out).map_err(|_| PlanCodeError::Truncated);
+            }
+        }
+        Err(PlanCodeError::Truncated)
+    }
+    fn rest(&mut self) -> &[u8] {
+        let out = &self.bytes[self.pos..];
+        self.pos = self.bytes.len();
+        out
+    }
+}
+
+fn push_state(out: &mut Vec<u8>, state: &[(bool, usize)]) {
+    let mut runs: Vec<((bool, usize), usize)> = Vec::new();
+    for x in state.iter() {
+        match runs.last_mut() {
+            Some((value, len)) if value == x => *len += 1,
+            _ => runs.push((*x, 1)),
+        }
+    }
+    push_varint(out, runs.len() as u64);
+    for ((juice, book), len) in runs {
+        push_varint(out, ((book as u64) << 1) | juice as u64);
+        push_varint(out, len as u64);
+    }
+}
+
+fn read_state(reader: &mut Reader) -> Result<Vec<(bool, usize)>, PlanCodeError> {
+    let num_runs = reader.varint()?;
+    let mut out: Vec<(bool, usize)> = Vec::new();
+    for _ in 0..num_runs {
+        let value = reader.varint()?;
+        let len = reader.varint()?;
+        if len > reader.bytes.len() * 1000 {
+            return Err(PlanCodeError::Truncated); // no real state is this long, don't allocate it
+        }
+        out.extend(std::iter::repeat_n((value & 1 == 1, value >> 1), len));
+    }
+    Ok(out)
+}
+
+/// The payload without anything the plan already says
+fn shareable_payload(payload: &Payload) -> Payload {
+    let mut out = payload.clone();
+    for upgrade in out.upgrade_info.iter_mut() {
+        upgrade.state = None;
+    }
+    out.special_state = None;
+    out.adv_cache = None;
+    out
+}
+
+pub fn encode_plan(essence: &StateEssence, payload: Option<&Payload>) -> String {
+    let mut bytes: Vec<u8> = vec![if payload.is_some() { FLAG_PAYLOAD } else { 0 }];
+    push_varint(&mut bytes, essence.state_arr.len() as u64);
+    for state in essence.state_arr.iter() {
+        push_state(&mut bytes, state);
+    }
+    push_varint(&mut bytes, essence.special_state.len() as u64);
+    for x in essence.special_state.iter() {
+        push_varint(&mut bytes, *x as u64);
+    }
+    if let Some(payload) = payload {
+        bytes.extend(serde_json::to_vec(&shareable_payload(payload)).unwrap());
+    }
+    let checksum = crc32(&bytes);
+    bytes.extend(checksum.to_le_bytes());
+    PLAN_CODE_PREFIX.to_owned() + &ENGINE.encode(bytes)
+}
+
+pub fn decode_plan(code: &str) -> Result<(StateEssence, Option<Payload>), PlanCodeError> {
+    let encoded = code
+        .trim()
+        .strip_prefix(PLAN_CODE_PREFIX)
+        .ok_or(PlanCodeError::BadPrefix)?;
+    let bytes = ENGINE
+        .decode(encoded)
+        .map_err(|_| PlanCodeError::BadBase64)?;
+    if bytes.len() < 4 {
+        return Err(PlanCodeError::Truncated);
+    }
+    let (body, checksum) = bytes.split_at(bytes.len() - 4);
+    if crc32(body).to_le_bytes() != checksum {
+        return Err(PlanCodeError::BadChecksum);
+    }
+
+    let mut reader = Reader {
+        bytes: body,
+        pos: 0,
+    };
+    let flags = reader.byte()?;
+    if flags & !FLAG_PAYLOAD != 0 {
+        return Err(PlanCodeError::UnknownFlags(flags));
+    }
+    let num_upgrades = reader.varint()?;
+    let state_arr = (0..num_upgrades)
+        .map(|_| read_state(&mut reader))
+        .collect::<Result<Vec<_>, _>>()?;
+    let num_special = reader.varint()?;
+    let special_state = (0..num_special)
+        .map(|_| reader.varint())
+        .collect::<Result<Vec<_>, _>>()?;
+    let payload = if flags & FLAG_PAYLOAD != 0 {
+        let json = std::str::from_utf8(reader.rest())
+            .map_err(|e| PlanCodeError::Payload(MigrationError::Parse(e.to_string())))?;
+        Some(Payload::from_json(json).map_err(PlanCodeError::Payload)?)
+    } else {
+        None
+    };
+    Ok((
+        StateEssence {
+            state_arr,
+            special_state,
+        },
+        payload,
+    ))
+}
+
+impl StateBundle {
+    /// The code's own payload is used if it has one, otherwise the given one (the inputs the player already has)
+    pub fn from_plan_code(code: &str, payload: Option<Payload>) -> Result<Self, PlanCodeError> {
+        let (essence, included) = decode_plan(code)?;
+        let payload = included.or(payload).ok_or(PlanCodeError::NoPayload)?;
+        let mut state_bundle =
+            StateBundle::init_from_payload(payload).map_err(PlanCodeError::BadInputs)?;
+
+        if essence.state_arr.len() != state_bundle.upgrade_arr.len() {
+            return Err(PlanCodeError::Mismatch(format!(
+                "{} upgrades in the code, {} in the inputs",
+                essence.state_arr.len(),
+                state_bundle.upgrade_arr.len()
+            )));
+        }
+        let mut sorted = essence.special_state.clone();
+        sorted.sort();
+        if sorted != (0..state_bundle.upgrade_arr.len()).collect::<Vec<usize>>() {
+            return Err(PlanCodeError::Mismatch(
+                "special order isn't a permutation of the upgrades".to_owned(),
+            ));
+        }
+        let juice_info = &state_bundle.prep_output.juice_info;
+        for (upgrade, state) in state_bundle.upgrade_arr.iter_mut().zip(essence.state_arr) {
+            if upgrade.state.len() != state.len() {
+                return Err(PlanCodeError::Mismatch(format!(
+                    "{} has {} taps in the code but {} in the inputs",
+                    upgrade.name_string,
+                    state.len(),
+                    upgrade.state.len()
+                )));
+            }
+            // same rules as recording a tap, a code from other data can't sneak in juice that isn't there
+            let usable = |&(juice, book): &(bool, usize)| {
+                if upgrade.is_normal_honing {
+                    let avail = &juice_info.normal_uindex_to_id[upgrade.upgrade_index];
+                    (!juice || avail.contains(&0)) && (book == 0 || avail.contains(&book))
+                } else {
+                    book <= MAX_ADV_STATE
+                }
+            };
+            if let Some((juice, book)) = state.iter().find(|x| !usable(x)) {
+                return Err(PlanCodeError::Mismatch(format!(
+                    "{} can't use juice {} with book {}",
+                    upgrade.name_string, juice, book
+                )));
+            }
+            upgrade.state.update_payload(state);
+        }
+        state_bundle.special_state = essence.special_state;
+        Ok(state_bundle)
+    }
+
+    pub fn plan_code(&self, payload: Option<&Payload>) -> String {
+        encode_plan(&self.to_essence(), payload)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::helpers::{my_pct_diff, test_cases_dir};
+    use crate::payload::parse_to_payloads;
+    use crate::performance::Performance;
+
+    /// Some streaks so the codes aren't all false, same shapes the optimizer makes
+    fn with_streaks(mut state_bundle: StateBundle) -> StateBundle {
+        let juice_info = state_bundle.prep_output.juice_info.clone();
+        for (i, upgrade) in state_bundle.upgrade_arr.iter_mut().enumerate() {
+            let len = upgrade.state.len();
+            let new: Vec<(bool, usize)> = if upgrade.is_normal_honing {
+                let Some(&book_id) = juice_info.normal_uindex_to_id[upgrade.upgrade_index].last()
+                else {
+                    continue;
+                };
+                (0..len)
+                    .map(|t| (t < i % 5 || t + 2 >= len, if t < 3 { book_id } else { 0 }))
+                    .collect()
+            } else {
+                (0..len).map(|t| (false, (i + t) % 3)).collect()
+            };
+            upgrade.state.update_payload(new);
+        }
+        state_bundle.special_state.reverse();
+        state_bundle
+    }
+
+    #[test]
+    fn plan_codes_round_trip() {
+        let mut performance = Performance::new();
+        for (name, payload) in parse_to_payloads(&test_cases_dir().join("payloads")) {
+            let mut original =
+                with_streaks(StateBundle::init_from_payload(payload.clone()).unwrap());
+            let expected = original.metric_router(&mut performance);
+
+            let short = original.plan_code(None);
+            let full = original.plan_code(Some(&payload));
+            assert!(short.len() < full.len());
+            for (code, given) in [(&short, Some(payload.clone())), (&full, None)] {
+                let mut decoded = StateBundle::from_plan_code(code, given).unwrap();
+                assert_eq!(decoded.to_essence(), original.to_essence(), "{}", name);
+                let metric = decoded.metric_router(&mut performance);
+                // hashmap order can move the last digit around
+                assert!(my_pct_diff(metric, expected) < 1e-9, "{}", name);
+            }
+        }
+    }
+
+    #[test]
+    fn broken_codes_are_rejected() {
+        let (_, payload) = parse_to_payloads(&test_cases_dir().join("payloads"))
+            .into_iter()
+            .find(|(x, _)| x == "two_+25")
+            .unwrap();
+        let code =
+            with_streaks(StateBundle::init_from_payload(payload.clone()).unwrap()).plan_code(None);
+        assert!(code.len() < 48, "{}", code);
+
+        let mut typo: Vec<char> = code.chars().collect();
+        let middle = PLAN_CODE_PREFIX.len() + 5;
+        typo[middle] = if typo[middle] == 'A' { 'B' } else { 'A' };
+        let typo: String = typo.into_iter().collect();
+        assert_eq!(decode_plan(&typo).err(), Some(PlanCodeError::BadChecksum));
+        assert_eq!(
+            decode_plan(&code[..code.len() - 4]).err(),
+            Some(PlanCodeError::BadChecksum)
+        );
+        assert_eq!(decode_plan("hello").err(), Some(PlanCodeError::BadPrefix));
+        assert_eq!(
+            StateBundle::from_plan_code(&code, None).unwrap_err(),
+            PlanCodeError::NoPayload
+        );
+
+        let mut juiced = StateBundle::init_from_payload(payload.clone()).unwrap();
+        let upgrade = juiced
+            .upgrade_arr
+            .iter_mut()
+            .find(|x| x.is_normal_honing)
+            .unwrap();
+        let mut state = upgrade.state.payload.clone();
+        state[0].1 = 999;
+        upgrade.state.update_payload(state);
+        assert!(matches!(
+            StateBundle::from_plan_code(&juiced.plan_code(None), Some(payload.clone())),
+            Err(PlanCodeError::Mismatch(_))
+        ));
+        let (_, adv_payload) = parse_to_payloads(&test_cases_dir().join("payloads"))
+            .into_iter()
+            .find(|(x, _)| x == "1920_adv3040")
+            .unwrap();
+        let mut adv = StateBundle::init_from_payload(adv_payload.clone()).unwrap();
+        let upgrade = adv
+            .upgrade_arr
+            .iter_mut()
+            .find(|x| !x.is_normal_honing)
+            .unwrap();
+        let mut state = upgrade.state.payload.clone();
+        state[0].1 = MAX_ADV_STATE + 1;
+        upgrade.state.update_payload(state);
+        assert!(matches!(
+            StateBundle::from_plan_code(&adv.plan_code(None), Some(adv_payload)),
+            Err(PlanCodeError::Mismatch(_))
+        ));
+
+        let mut other = payload.clone();
+        other.upgrade_info.truncate(1);
+        assert!(matches!(
+            StateBundle::from_plan_code(&code, Some(other)),
+            Err(PlanCodeError::Mismatch(_))
+        ));
+    }
+}
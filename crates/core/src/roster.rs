@@ -0,0 +1,485 @@
+//! Several characters honing at once, sharing roster-bound / tradable / market materials but not char-bound ones
+//!
+//! Average gold is a sum over material types of one_dimension_average_gold, and each of those only depends on that material's budget.
+//! So for a fixed plan, splitting a shared pool between characters is separable per material, and because the gold left
+//! is concave in the budget, greedily handing out chunks to whoever gains the most is (up to chunk size) optimal.
+//!
+//! What isn't separable is the plan itself (how much juice you use depends on what you own), so we alternate between
+//! solving every character with their current share and re-splitting the shared pools with the new plans.
+use crate::constants::events::EventInput;
+use crate::helpers::distribute_budgets;
+use crate::market_depth::MarketDepth;
+use crate::migration::PAYLOAD_SCHEMA_VERSION;
+use crate::model::PayloadError;
+#[cfg(feature = "v35")]
+use crate::optimizer::solve;
+use crate::parser::{MaterialInput, MaterialTier, OneUpgradeInput, PriceScenario};
+use crate::payload::{Payload, default_one};
+use crate::performance::Performance;
+use crate::state_bundle::StateBundle;
+#[cfg(feature = "v35")]
+use crate::treatment::solve_treatments;
+use crate::treatment::{TreatmentPlan, default_material_sources, default_treatment_plans};
+#[cfg(feature = "v35")]
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+pub const CHAR_BOUND_INDEX: usize = 0; // column of material_info that can't be shared (see UI_TREATMENTS), whatever material_sources calls it
+pub const ROSTER_CHUNKS: usize = 20; // how finely each shared pool is split
+pub const ROSTER_ROUNDS: usize = 3; // solve -> re-split rounds
+
+#[derive(Deserialize, Clone, Serialize)]
+pub struct CharacterInput {
+    pub name: String,
+    pub upgrade_info: Vec<OneUpgradeInput>,
+    pub bound_owned: Vec<f64>, // char-bound owned per material type, replaces column CHAR_BOUND_INDEX of material_info
+    pub special_budget: i64,
+    pub special_state: Option<Vec<usize>>,
+}
+
+#[derive(Deserialize, Clone, Serialize)]
+pub struct RosterPayload {
+    pub material_info: MaterialInput, // owned in column CHAR_BOUND_INDEX is ignored, every other column is shared by the roster
+    #[serde(default)]
+    pub material_sources: Option<Vec<String>>, // same as Payload, the default four if left out
+    pub optimizer_plan: Option<Vec<usize>>,
+    #[serde(default)]
+    pub treatment_plans: Option<Vec<TreatmentPlan>>,
+    #[serde(default)]
+    pub choose_treatment: bool,
+    pub characters: Vec<CharacterInput>,
+    pub tier: usize,
+    #[serde(default)]
+    pub data_name: Option<String>,
+    pub express_event: bool,
+    #[serde(default)]
+    pub events: Vec<EventInput>,
+
+    pub min_resolution: usize,
+    #[serde(default)]
+    pub num_threads: usize,
+    #[serde(default = "default_one")]
+    pub metric_type: i64, // only 1, shared pools are split by average gold (see module docs)
+    #[serde(default)]
+    pub price_scenarios: Option<Vec<PriceScenario>>, // only used by metric_type 2 and 3, so has to be empty
+    #[serde(default)]
+    pub market_depth: Option<Vec<MarketDepth>>,
+}
+
+#[derive(Serialize)]
+pub struct RosterResult {
+    pub state_bundles: Vec<StateBundle>,
+    pub shares: Vec<Vec<f64>>, // [character][material type] fraction of the shared owned amounts
+    pub metric: f64,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum RosterError {
+    NoCharacters,
+    BoundOwnedLength {
+        name: String,
+        expected: usize,
+        got: usize,
+    },
+    MetricType(i64),
+    PriceScenarios,
+    Payload(PayloadError),
+}
+
+impl fmt::Display for RosterError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RosterError::NoCharacters => write!(f, "the roster has no characters"),
+            RosterError::BoundOwnedLength {
+                name,
+                expected,
+                got,
+            } => write!(
+                f,
+                "{} has {} char-bound amounts but there are {} material types",
+                name, got, expected
+            ),
+            RosterError::MetricType(metric_type) => write!(
+                f,
+                "rosters split shared materials by average gold, metric_type {} isn't supported",
+                metric_type
+            ),
+            RosterError::PriceScenarios => {
+                write!(f, "rosters don't support price scenarios")
+            }
+            RosterError::Payload(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl std::error::Error for RosterError {}
+
+impl From<PayloadError> for RosterError {
+    fn from(e: PayloadError) -> Self {
+        RosterError::Payload(e)
+    }
+}
+
+impl RosterPayload {
+    pub fn check(&self) -> Result<(), RosterError> {
+        if self.characters.is_empty() {
+            return Err(RosterError::NoCharacters);
+        }
+        if self.metric_type != 1 {
+            return Err(RosterError::MetricType(self.metric_type));
+        }
+        if self.price_scenarios.as_ref().is_some_and(|x| !x.is_empty()) {
+            return Err(RosterError::PriceScenarios);
+        }
+        for character in self.characters.iter() {
+            if character.bound_owned.len() != self.material_info.len() {
+                return Err(RosterError::BoundOwnedLength {
+                    name: character.name.clone(),
+                    expected: self.material_info.len(),
+                    got: character.bound_owned.len(),
+                });
+            }
+        }
+        Ok(())
+    }
+
+    /// material_info as seen by one character, given its share of every shared pool (check has to have passed)
+    pub fn character_material_info(&self, char_index: usize, shares: &[f64]) -> MaterialInput {
+        let character = &self.characters[char_index];
+        self.material_info
+            .iter()
+            .enumerate()
+            .map(|(support_index, row)| {
+                row.iter()
+                    .enumerate()
+                    .map(|(treatment, tier)| MaterialTier {
+                        owned: if treatment == CHAR_BOUND_INDEX {
+                            character.bound_owned[support_index]
+                        } else {
+                            tier.owned * shares[support_index]
+                        },
+                        ..*tier
+                    })
+                    .collect()
+            })
+            .collect()
+    }
+
+    pub fn character_payload(&self, char_index: usize, shares: &[f64]) -> Payload {
+        let character = &self.characters[char_index];
+        let num_sources = self.material_info[0].len();
+        Payload {
+            schema_version: PAYLOAD_SCHEMA_VERSION,
+            material_info: self.character_material_info(char_index, shares),
+            material_sources: self
+                .material_sources
+                .clone()
+                .unwrap_or_else(|| default_material_sources(num_sources)),
+            optimizer_plan: self.optimizer_plan.clone(),
+            treatment_plans: self
+                .treatment_plans
+                .clone()
+                .unwrap_or_else(|| default_treatment_plans(num_sources)),
+            choose_treatment: self.choose_treatment,
+            upgrade_info: character.upgrade_info.clone(),
+            special_budget: character.special_budget,
+            special_state: character.special_state.clone(),
+            tier: self.tier,
+            data_name: self.data_name.clone(),
+            express_event: self.express_event,
+            events: self.events.clone(),
+            min_resolution: self.min_resolution,
+            num_threads: self.num_threads,
+            metric_type: self.metric_type,
+            adv_cache: None,
+            price_scenarios: self.price_scenarios.clone(),
+            market_depth: self.market_depth.clone(),
+            upgrade_attribution: true,
+        }
+    }
+
+    /// Everyone gets an equal share to start with
+    pub fn equal_shares(&self) -> Vec<Vec<f64>> {
+        let num_chars = self.characters.len();
+        vec![vec![1.0 / num_chars as f64; self.material_info.len()]; num_chars]
+    }
+
+    pub fn init_state_bundles(&self, shares: &[Vec<f64>]) -> Result<Vec<StateBundle>, RosterError> {
+        self.check()?;
+        Ok((0..self.characters.len())
+            .map(|c| StateBundle::init_from_payload(self.character_payload(c, &shares[c])))
+            .collect::<Result<_, _>>()?)
+    }
+}
+
+/// Re-splits every shared pool between characters for their current plans, see module docs
+pub fn allocate_shares(
+    roster: &RosterPayload,
+    state_bundles: &mut [StateBundle],
+    performance: &mut Performance,
+) -> Vec<Vec<f64>> {
+    let num_chars = roster.characters.len();
+    let num_mats = roster.material_info.len();
+    let mut shares: Vec<Vec<f64>> = vec![vec![0.0; num_mats]; num_chars];
+    if num_chars == 0 {
+        return shares;
+    }
+
+    for state_bundle in state_bundles.iter_mut() {
+        state_bundle.update_prob_dist();
+        state_bundle.update_cost_dist();
+        state_bundle.compute_special_probs(false);
+    }
+
+    for support_index in 0..num_mats {
+        let has_shared = roster.material_info[support_index]
+            .iter()
+            .enumerate()
+            .any(|(treatment, tier)| treatment != CHAR_BOUND_INDEX && tier.owned > 0.0);
+        if !has_shared {
+            for row in shares.iter_mut() {
+                row[support_index] = 1.0 / num_chars as f64;
+            }
+            continue;
+        }
+
+        let gold_with = |c: usize,
+                         share: f64,
+                         state_bundle: &StateBundle,
+                         performance: &mut Performance| {
+            let mut this_shares = vec![0.0; num_mats];
+            this_shares[support_index] = share;
+            let row = roster.character_material_info(c, &this_shares)[support_index].clone();
+            let thresh_price_pairs =
+                &distribute_budgets(&vec![row], &state_bundle.prep_output.optimizer_plan)[0];
+            state_bundle.one_material_average_gold(support_index, thresh_price_pairs, performance)
+        };
+
+        let mut current: Vec<f64> = state_bundles
+            .iter()
+            .enumerate()
+            .map(|(c, state_bundle)| gold_with(c, 0.0, state_bundle, performance))
+            .collect();
+        let chunk: f64 = 1.0 / ROSTER_CHUNKS as f64;
+        for _ in 0..ROSTER_CHUNKS {
+            let mut best: (usize, f64, f64) = (0, f64::NEG_INFINITY, f64::NAN); // char, gain, new gold
+            for (c, state_bundle) in state_bundles.iter().enumerate() {
+                let new_gold = gold_with(
+                    c,
+                    shares[c][support_index] + chunk,
+                    state_bundle,
+                    performance,
+                );
+                if new_gold - current[c] > best.1 {
+                    best = (c, new_gold - current[c], new_gold);
+                }
+            }
+            shares[best.0][support_index] += chunk;
+            current[best.0] = best.2;
+        }
+    }
+
+    for (c, state_bundle) in state_bundles.iter_mut().enumerate() {
+        state_bundle
+            .prep_output
+            .set_material_info(roster.character_material_info(c, &shares[c]));
+    }
+    shares
+}
+
+/// Sum of every character's metric, this is what we're maximizing
+pub fn roster_metric(state_bundles: &mut [StateBundle], performance: &mut Performance) -> f64 {
+    state_bundles
+        .iter_mut()
+        .map(|state_bundle| {
+            state_bundle.metric = state_bundle.metric_router(performance);
+            state_bundle.metric
+        })
+        .sum()
+}
+
+#[cfg(feature = "v35")] // needs an optimizer version
+pub fn solve_roster<R: Rng>(
+    rng: &mut R,
+    roster: &RosterPayload,
+    overall_performance: &mut Performance,
+) -> Result<RosterResult, RosterError> {
+    let mut shares = roster.equal_shares();
+    let mut state_bundles = roster.init_state_bundles(&shares)?;
+    let mut best: Option<(f64, Vec<StateBundle>, Vec<Vec<f64>>)> = None;
+
+    for _ in 0..ROSTER_ROUNDS {
+        state_bundles = state_bundles
+            .into_iter()
+            .map(|state_bundle| {
+                if roster.choose_treatment {
+                    solve_treatments(rng, state_bundle, overall_performance).0
+                } else {
+                    solve(rng, state_bundle, overall_performance)
+                }
+            })
+            .collect();
+        let metric = roster_metric(&mut state_bundles, overall_performance);
+        if best.as_ref().is_none_or(|(b, _, _)| metric > *b) {
+            best = Some((metric, state_bundles.clone(), shares.clone()));
+        }
+
+        let new_shares = allocate_shares(roster, &mut state_bundles, overall_performance);
+        if new_shares == shares {
+            break;
+        }
+        shares = new_shares;
+    }
+
+    let (metric, state_bundles, shares) = best.unwrap();
+    Ok(RosterResult {
+        state_bundles,
+        shares,
+        metric,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::helpers::test_cases_dir;
+    use crate::payload::parse_to_payloads;
+
+    fn character(name: &str, payload: &Payload) -> CharacterInput {
+        CharacterInput {
+            name: name.to_owned(),
+            upgrade_info: payload.upgrade_info.clone(),
+            bound_owned: payload
+                .material_info
+                .iter()
+                .map(|row| row[CHAR_BOUND_INDEX].owned)
+                .collect(),
+            special_budget: payload.special_budget,
+            special_state: None,
+        }
+    }
+
+    fn roster(base: &Payload, characters: Vec<CharacterInput>) -> RosterPayload {
+        RosterPayload {
+            material_info: base.material_info.clone(),
+            material_sources: None,
+            optimizer_plan: base.optimizer_plan.clone(),
+            treatment_plans: None,
+            choose_treatment: false,
+            characters,
+            tier: base.tier,
+            data_name: None,
+            express_event: base.express_event,
+            events: Vec::new(),
+            min_resolution: base.min_resolution,
+            num_threads: 0,
+            metric_type: 1,
+            price_scenarios: None,
+            market_depth: None,
+        }
+    }
+
+    #[test]
+    fn allocation_beats_equal_split() {
+        let payloads = parse_to_payloads(&test_cases_dir().join("payloads"));
+        let (_, base) = payloads.iter().find(|(name, _)| name == "2122").unwrap();
+        let (_, other) = payloads
+            .iter()
+            .find(|(name, _)| name == "single_+25")
+            .unwrap();
+        let roster = roster(base, vec![character("0", base), character("1", other)]);
+
+        let mut performance = Performance::new();
+        let mut state_bundles = roster.init_state_bundles(&roster.equal_shares()).unwrap();
+        let equal = roster_metric(&mut state_bundles, &mut performance);
+        let shares = allocate_shares(&roster, &mut state_bundles, &mut performance);
+        let allocated = roster_metric(&mut state_bundles, &mut performance);
+
+        for support_index in 0..roster.material_info.len() {
+            let total: f64 = shares.iter().map(|x| x[support_index]).sum();
+            assert!((total - 1.0).abs() < 1e-9);
+        }
+        assert!(allocated >= equal - 1e-6 * equal.abs());
+    }
+
+    #[test]
+    fn shared_pools_go_to_who_needs_them() {
+        let payloads = parse_to_payloads(&test_cases_dir().join("payloads"));
+        let (_, base) = payloads.iter().find(|(name, _)| name == "2122").unwrap();
+        // same upgrades, but one of them already owns way more char-bound mats than it could use
+        let mut rich = character("rich", base);
+        rich.bound_owned = vec![1e9; rich.bound_owned.len()];
+        let mut needy = character("needy", base);
+        needy.bound_owned = vec![0.0; needy.bound_owned.len()];
+        let roster = roster(base, vec![rich, needy]);
+
+        let mut performance = Performance::new();
+        let mut state_bundles = roster.init_state_bundles(&roster.equal_shares()).unwrap();
+        roster_metric(&mut state_bundles, &mut performance);
+        let equal: Vec<f64> = state_bundles.iter().map(|x| x.metric).collect();
+        let shares = allocate_shares(&roster, &mut state_bundles, &mut performance);
+        let allocated = roster_metric(&mut state_bundles, &mut performance);
+
+        // the rich one only loses what its leftovers would have sold for, the needy one doesn't have to buy as much
+        assert!(allocated > equal.iter().sum::<f64>());
+        assert!(state_bundles[1].metric > equal[1]);
+        assert!((0..roster.material_info.len()).any(|i| shares[1][i] > 1.0 - 1e-9));
+    }
+
+    #[test]
+    fn bad_rosters_are_errors() {
+        let payloads = parse_to_payloads(&test_cases_dir().join("payloads"));
+        let (_, base) = payloads.iter().find(|(name, _)| name == "2122").unwrap();
+        let mut empty = roster(base, Vec::new());
+        assert_eq!(
+            empty.init_state_bundles(&[]).unwrap_err(),
+            RosterError::NoCharacters
+        );
+        let mut short = character("short", base);
+        short.bound_owned.pop();
+        empty.characters.push(short);
+        assert!(matches!(
+            empty.init_state_bundles(&empty.equal_shares()),
+            Err(RosterError::BoundOwnedLength { .. })
+        ));
+
+        let mut scenarios = roster(base, vec![character("0", base)]);
+        scenarios.metric_type = 3;
+        assert_eq!(
+            scenarios
+                .init_state_bundles(&scenarios.equal_shares())
+                .unwrap_err(),
+            RosterError::MetricType(3)
+        );
+        scenarios.metric_type = 1;
+        scenarios.price_scenarios = Some(
+            PriceScenario::from_ranges(&vec![(0.8, 1.2); base.material_info.len()], 2).unwrap(),
+        );
+        assert_eq!(
+            scenarios
+                .init_state_bundles(&scenarios.equal_shares())
+                .unwrap_err(),
+            RosterError::PriceScenarios
+        );
+    }
+
+    #[test]
+    fn treatment_inputs_reach_characters() {
+        let payloads = parse_to_payloads(&test_cases_dir().join("payloads"));
+        let (_, base) = payloads.iter().find(|(name, _)| name == "2122").unwrap();
+        let num_sources = base.material_info[0].len();
+        let mut custom = roster(base, vec![character("0", base)]);
+        custom.material_sources = Some((0..num_sources).map(|i| i.to_string()).collect());
+        custom.treatment_plans = Some(vec![TreatmentPlan::identity(num_sources)]);
+        custom.choose_treatment = true;
+
+        let payload = custom.character_payload(0, &custom.equal_shares()[0]);
+        assert_eq!(payload.material_sources, custom.material_sources.unwrap());
+        assert_eq!(
+            payload.treatment_plans,
+            vec![TreatmentPlan::identity(num_sources)]
+        );
+        assert!(payload.choose_treatment);
+    }
+}
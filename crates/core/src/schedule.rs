@@ -0,0 +1,350 @@
+//! Plans which upgrades to do in which week, given that materials come in every week rather than all being owned right now
+//!
+//! The bundle of everything honed by the end of week w is evaluated against everything owned by then (owned + w * income),
+//! reusing the cost_dist of every Upgrade (we only ever take subsets, never re-parse).
+//! Buying never gets cheaper by waiting, so if purchases were all that mattered everything would be done in the last week.
+//! What makes honing earlier worth it is week_value, the gold the player would pay to have one upgrade done a week sooner.
+//! So the schedule minimizes expected purchases + week_value * (weeks every upgrade waited), greedily:
+//! every week keeps adding whichever upgrade raises the schedule's expected purchases the least,
+//! as long as that's no more than week_value (i.e. cheaper than waiting another week). The last week does whatever is left.
+use crate::constants::FLOAT_TOL;
+use crate::model::PayloadError;
+use crate::parser::ThreshPrices;
+use crate::payload::Payload;
+use crate::performance::Performance;
+use crate::state_bundle::StateBundle;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+#[derive(Deserialize, Clone, Serialize)]
+pub struct SchedulePayload {
+    pub payload: Payload, // upgrade_info is the target set, owned amounts are what's owned right now
+    pub weekly_income: Vec<f64>, // per material type, arrives at the start of every week after week 0
+    pub num_weeks: usize,        // deadline, everything is done by the last week
+    #[serde(default)]
+    pub week_value: f64, // gold per upgrade per week it gets done sooner, 0 only minimizes purchases
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ScheduleError {
+    NoWeeks,
+    IncomeLength { expected: usize, got: usize },
+    BadWeekValue(f64),
+    Payload(PayloadError),
+}
+
+impl fmt::Display for ScheduleError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ScheduleError::NoWeeks => write!(f, "num_weeks has to be at least 1"),
+            ScheduleError::IncomeLength { expected, got } => write!(
+                f,
+                "weekly_income has {} entries but there are {} material types",
+                got, expected
+            ),
+            ScheduleError::BadWeekValue(x) => {
+                write!(f, "week_value has to be a non-negative number, got {}", x)
+            }
+            ScheduleError::Payload(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl std::error::Error for ScheduleError {}
+
+impl From<PayloadError> for ScheduleError {
+    fn from(e: PayloadError) -> Self {
+        ScheduleError::Payload(e)
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ScheduledWeek {
+    pub week: usize,
+    pub upgrades: Vec<usize>, // indices into upgrade_arr, in the order they should be honed
+    pub names: Vec<String>,
+    pub budget: Vec<f64>, // total owned per material type at the start of this week
+    pub expected_purchase: f64, // expected gold spent buying materials for everything done up to and including this week
+    pub afford_prob: f64, // chance that everything up to and including this week needs no buying
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Schedule {
+    pub weeks: Vec<ScheduledWeek>,
+    pub expected_purchase: f64,
+    pub waiting_cost: f64, // week_value * weeks every upgrade waited
+}
+
+impl StateBundle {
+    /// Expected gold spent buying at price buy_price[material] whatever the budget doesn't cover. Leftovers are worth nothing here.
+    pub fn expected_purchase(
+        &mut self,
+        budget: &[f64],
+        buy_price: &[f64],
+        performance: &mut Performance,
+    ) -> f64 {
+        if self.upgrade_arr.is_empty() {
+            return 0.0;
+        }
+        self.update_prob_dist();
+        self.update_cost_dist();
+        self.compute_special_probs(false);
+
+        let thresh_prices: ThreshPrices = budget
+            .iter()
+            .zip(buy_price.iter())
+            .map(|(&b, &p)| {
+                if b > 0.0 {
+                    vec![(0.0, 0.0), (b, p)]
+                } else {
+                    vec![(0.0, p)]
+                }
+            })
+            .collect();
+        // gold left with nothing to sell is minus what we had to buy
+        -self.average_gold(&thresh_prices, performance)
+    }
+
+    /// Chance that no material runs out, costs of different materials scale with the same taps so we treat them as comonotone (min over materials)
+    pub fn afford_prob(&mut self, budget: &[f64], performance: &mut Performance) -> f64 {
+        if self.upgrade_arr.is_empty() {
+            return 1.0;
+        }
+        self.update_prob_dist();
+        self.update_cost_dist();
+        self.compute_special_probs(false);
+        budget
+            .iter()
+            .enumerate()
+            .map(|(support_index, &b)| {
+                self.one_dimension_prob(support_index as i64, b, performance)
+            })
+            .fold(1.0, f64::min)
+    }
+}
+
+/// Upgrades of the same piece (and same kind of honing) have to be done in ascending order
+pub(crate) fn prerequisites_done(
+    state_bundle: &StateBundle,
+    done: &[bool],
+    u_index: usize,
+) -> bool {
+    let upgrade = &state_bundle.upgrade_arr[u_index];
+    state_bundle
+        .upgrade_arr
+        .iter()
+        .enumerate()
+        .all(|(other_index, other)| {
+            done[other_index]
+                || other.piece_type != upgrade.piece_type
+                || other.is_normal_honing != upgrade.is_normal_honing
+                || other.upgrade_index >= upgrade.upgrade_index
+        })
+}
+
+pub fn plan_schedule(
+    schedule_payload: &SchedulePayload,
+    performance: &mut Performance,
+) -> Result<Schedule, ScheduleError> {
+    if schedule_payload.num_weeks == 0 {
+        return Err(ScheduleError::NoWeeks);
+    }
+    let week_value = schedule_payload.week_value;
+    if !(week_value >= 0.0 && week_value.is_finite()) {
+        return Err(ScheduleError::BadWeekValue(week_value));
+    }
+    let full: StateBundle = StateBundle::init_from_payload(schedule_payload.payload.clone())?;
+    let num_mats = full.prep_output.raw_material_info.len();
+    if schedule_payload.weekly_income.len() != num_mats {
+        return Err(ScheduleError::IncomeLength {
+            expected: num_mats,
+            got: schedule_payload.weekly_income.len(),
+        });
+    }
+
+    let owned: Vec<f64> = full
+        .prep_output
+        .raw_material_info
+        .iter()
+        .map(|row| row.iter().map(|x| x.owned).sum())
+        .collect();
+    let buy_price: Vec<f64> = full
+        .prep_output
+        .raw_material_info
+        .iter()
+        .map(|row| row.last().unwrap().buy)
+        .collect();
+
+    // lower upgrade_index first so that prerequisites come before whatever needs them
+    let mut candidates: Vec<usize> = (0..full.upgrade_arr.len()).collect();
+    candidates.sort_by_key(|&u_index| full.upgrade_arr[u_index].upgrade_index);
+
+    let mut done: Vec<bool> = vec![false; full.upgrade_arr.len()];
+    let mut scheduled: Vec<usize> = Vec::with_capacity(full.upgrade_arr.len());
+    let mut weeks: Vec<ScheduledWeek> = Vec::with_capacity(schedule_payload.num_weeks);
+    // purchases can't be undone, so it's the worst week that counts (this is a lower bound of E[max], which is what we actually pay)
+    let mut expected_purchase: f64 = 0.0;
+    let mut waiting_cost: f64 = 0.0;
+
+    for week in 0..schedule_payload.num_weeks {
+        let budget: Vec<f64> = owned
+            .iter()
+            .zip(schedule_payload.weekly_income.iter())
+            .map(|(o, income)| o + income * week as f64)
+            .collect();
+        let is_last = week == schedule_payload.num_weeks - 1;
+        let mut this_week: Vec<usize> = Vec::new();
+
+        if is_last {
+            for &u_index in candidates.iter() {
+                if done[u_index] {
+                    continue;
+                }
+                done[u_index] = true;
+                scheduled.push(u_index);
+                this_week.push(u_index);
+            }
+        } else {
+            let mut now = expected_purchase.max(full.subset(&scheduled).expected_purchase(
+                &budget,
+                &buy_price,
+                performance,
+            ));
+            loop {
+                // (extra purchases, u_index, schedule purchases with it)
+                let mut best: Option<(f64, usize, f64)> = None;
+                for &u_index in candidates.iter() {
+                    if done[u_index] || !prerequisites_done(&full, &done, u_index) {
+                        continue;
+                    }
+                    let mut tentative = scheduled.clone();
+                    tentative.push(u_index);
+                    let with = now.max(full.subset(&tentative).expected_purchase(
+                        &budget,
+                        &buy_price,
+                        performance,
+                    ));
+                    if best.is_none_or(|(b, _, _)| with - now < b) {
+                        best = Some((with - now, u_index, with));
+                    }
+                }
+                match best {
+                    Some((extra, u_index, with))
+                        if extra <= week_value + FLOAT_TOL * now.max(1.0) =>
+                    {
+                        done[u_index] = true;
+                        scheduled.push(u_index);
+                        this_week.push(u_index);
+                        now = with;
+                    }
+                    _ => break,
+                }
+            }
+        }
+        waiting_cost += week_value * (week * this_week.len()) as f64;
+
+        let mut cumulative = full.subset(&scheduled);
+        let this_purchase = cumulative.expected_purchase(&budget, &buy_price, performance);
+        expected_purchase = expected_purchase.max(this_purchase);
+        weeks.push(ScheduledWeek {
+            week,
+            names: this_week
+                .iter()
+                .map(|&u_index| full.upgrade_arr[u_index].name_string.clone())
+                .collect(),
+            upgrades: this_week,
+            expected_purchase: this_purchase,
+            afford_prob: cumulative.afford_prob(&budget, performance),
+            budget,
+        });
+    }
+
+    Ok(Schedule {
+        weeks,
+        expected_purchase,
+        waiting_cost,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::helpers::test_cases_dir;
+    use crate::payload::parse_to_payloads;
+
+    fn schedule_payload(weekly_income: f64, num_weeks: usize, week_value: f64) -> SchedulePayload {
+        let (_, payload) = parse_to_payloads(&test_cases_dir().join("payloads"))
+            .into_iter()
+            .find(|(name, _)| name == "2122")
+            .unwrap();
+        SchedulePayload {
+            weekly_income: vec![weekly_income; payload.material_info.len()],
+            payload,
+            num_weeks,
+            week_value,
+        }
+    }
+
+    fn all_upgrades(schedule: &Schedule) -> Vec<usize> {
+        let mut out: Vec<usize> = schedule
+            .weeks
+            .iter()
+            .flat_map(|x| x.upgrades.clone())
+            .collect();
+        out.sort();
+        out
+    }
+
+    #[test]
+    fn schedule_covers_every_upgrade_once() {
+        let mut performance = Performance::new();
+        let num_upgrades = schedule_payload(0.0, 1, 0.0).payload.upgrade_info.len();
+        let generous = plan_schedule(&schedule_payload(1e9, 4, 0.0), &mut performance).unwrap();
+        assert_eq!(
+            all_upgrades(&generous),
+            (0..num_upgrades).collect::<Vec<usize>>()
+        );
+        // with that much income everything is free from week 1 on, and free upgrades don't wait
+        assert!(generous.weeks.iter().skip(2).all(|x| x.upgrades.is_empty()));
+        assert!(generous.expected_purchase < 1e-6);
+
+        let broke = plan_schedule(&schedule_payload(0.0, 3, 0.0), &mut performance).unwrap();
+        assert!(broke.expected_purchase >= generous.expected_purchase);
+        assert_eq!(broke.waiting_cost, 0.0);
+    }
+
+    #[test]
+    fn week_value_pulls_upgrades_earlier() {
+        let mut performance = Performance::new();
+        let num_upgrades = schedule_payload(0.0, 1, 0.0).payload.upgrade_info.len();
+        // no income, so waiting never saves anything and only week_value decides
+        let patient = plan_schedule(&schedule_payload(0.0, 3, 0.0), &mut performance).unwrap();
+        assert_eq!(
+            all_upgrades(&patient),
+            (0..num_upgrades).collect::<Vec<usize>>()
+        );
+        let eager = plan_schedule(&schedule_payload(0.0, 3, 1e12), &mut performance).unwrap();
+        assert_eq!(eager.weeks[0].upgrades.len(), num_upgrades);
+        assert_eq!(eager.waiting_cost, 0.0);
+        assert!(patient.weeks[0].upgrades.len() < num_upgrades);
+        assert!(
+            (eager.expected_purchase - patient.expected_purchase).abs()
+                <= 1e-6 * patient.expected_purchase
+        );
+
+        assert_eq!(
+            plan_schedule(&schedule_payload(0.0, 0, 0.0), &mut performance).unwrap_err(),
+            ScheduleError::NoWeeks
+        );
+        let mut short = schedule_payload(0.0, 2, 0.0);
+        short.weekly_income.pop();
+        assert!(matches!(
+            plan_schedule(&short, &mut performance),
+            Err(ScheduleError::IncomeLength { .. })
+        ));
+        assert!(matches!(
+            plan_schedule(&schedule_payload(0.0, 2, -1.0), &mut performance),
+            Err(ScheduleError::BadWeekValue(_))
+        ));
+    }
+}
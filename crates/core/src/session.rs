@@ -0,0 +1,438 @@
+//! Tracks an actual honing session tap by tap.
+//!
+//! The session is just a Payload that keeps getting rewritten to describe what's left (artisan and taps so far,
+//! owned materials, special budget, upgrades that are done get removed) plus a log of the taps,
+//! so it serializes as is and the wasm side can keep it between page loads.
+//! Everything else (remaining cost distribution, new recommendation) comes from a StateBundle of that payload,
+//! which is kept around and patched after every tap (only the tapped upgrade gets parsed again, adv dists are slow).
+use crate::constants::ARTISAN_MULTIPLIER;
+use crate::model::PayloadError;
+use crate::parser::parser;
+use crate::payload::Payload;
+use crate::performance::Performance;
+use crate::state_bundle::StateBundle;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+#[cfg(feature = "v35")]
+use crate::optimizer::solve;
+#[cfg(feature = "v35")]
+use crate::treatment::solve_treatments;
+#[cfg(feature = "v35")]
+use rand::Rng;
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub enum TapKind {
+    // same as one entry of Upgrade::state
+    Normal {
+        juice: bool,
+        book: usize,
+    },
+    Special,
+    Advanced {
+        progress: (usize, usize, bool, bool), // adv_progress after this tap
+        spent: Vec<f64>, // per material type, adv taps aren't tracked closely enough for us to work it out
+    },
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Tap {
+    pub piece_type: usize,
+    pub upgrade_index: usize,
+    pub kind: TapKind,
+    pub succeeded: bool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TapRecord {
+    pub tap: Tap,
+    pub chance: f64,      // NAN for adv
+    pub spent: Vec<f64>,  // per material type
+    pub bought: Vec<f64>, // the part of spent that wasn't owned
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum SessionError {
+    UnknownUpgrade {
+        piece_type: usize,
+        upgrade_index: usize,
+    },
+    JuiceNotAvailable(usize),
+    NotEnoughSpecial {
+        needed: i64,
+        left: i64,
+    },
+    Payload(PayloadError),
+}
+
+impl From<PayloadError> for SessionError {
+    fn from(e: PayloadError) -> Self {
+        SessionError::Payload(e)
+    }
+}
+
+impl fmt::Display for SessionError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SessionError::UnknownUpgrade {
+                piece_type,
+                upgrade_index,
+            } => write!(
+                f,
+                "Piece {} upgrade {} isn't part of this session",
+                piece_type, upgrade_index
+            ),
+            SessionError::JuiceNotAvailable(id) => {
+                write!(f, "Juice {} can't be used on this upgrade", id)
+            }
+            SessionError::NotEnoughSpecial { needed, left } => {
+                write!(f, "Special leap needs {} but only {} is left", needed, left)
+            }
+            SessionError::Payload(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl std::error::Error for SessionError {}
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct HoningSession {
+    pub payload: Payload,
+    pub taps: Vec<TapRecord>,
+    #[serde(skip)]
+    cache: Option<StateBundle>, // always describes payload, built on first use
+}
+
+impl HoningSession {
+    pub fn new(mut payload: Payload) -> Self {
+        payload.adv_cache = None; // rebuilt on demand, not worth persisting
+        Self {
+            payload,
+            taps: Vec::new(),
+            cache: None,
+        }
+    }
+
+    fn cached(&mut self) -> Result<&mut StateBundle, PayloadError> {
+        if self.cache.is_none() {
+            self.cache = Some(StateBundle::init_from_payload(self.payload.clone())?);
+        }
+        Ok(self.cache.as_mut().unwrap())
+    }
+
+    pub fn state_bundle(&mut self) -> Result<StateBundle, PayloadError> {
+        self.cached().cloned()
+    }
+
+    /// Brings the cache in line with the payload after a tap on upgrade_info[position]
+    fn update_cache(&mut self, position: usize, succeeded: bool) -> Result<(), PayloadError> {
+        let tier = self.payload.resolve_tier()?;
+        let Some(state_bundle) = self.cache.as_mut() else {
+            return Ok(());
+        };
+        if succeeded {
+            state_bundle.upgrade_arr.remove(position);
+            state_bundle.special_state = (0..state_bundle.upgrade_arr.len()).collect();
+        } else {
+            state_bundle.upgrade_arr[position] = parser(
+                vec![self.payload.upgrade_info[position].clone()],
+                &state_bundle.prep_output.events,
+                &state_bundle.prep_output.juice_info,
+                tier,
+                &mut state_bundle.adv_cache,
+            )
+            .remove(0);
+        }
+        state_bundle
+            .prep_output
+            .set_material_info(self.payload.material_info.clone());
+        state_bundle.prep_output.special_budget = self.payload.special_budget;
+        state_bundle.special_cache.clear();
+        state_bundle.special_invalid_index = None;
+        state_bundle.latest_special_probs = None;
+        state_bundle.metric = -1.0;
+        Ok(())
+    }
+
+    pub fn record_tap(&mut self, tap: Tap) -> Result<&TapRecord, SessionError> {
+        let is_normal_honing = !matches!(tap.kind, TapKind::Advanced { .. });
+        let position: usize = self
+            .payload
+            .upgrade_info
+            .iter()
+            .position(|x| {
+                x.piece_type == tap.piece_type
+                    && x.upgrade_index == tap.upgrade_index
+                    && x.is_normal_honing == is_normal_honing
+            })
+            .ok_or(SessionError::UnknownUpgrade {
+                piece_type: tap.piece_type,
+                upgrade_index: tap.upgrade_index,
+            })?;
+        self.cached()?;
+        let state_bundle: &StateBundle = self.cache.as_ref().unwrap();
+        let upgrade = &state_bundle.upgrade_arr[position];
+        let juice_info = &state_bundle.prep_output.juice_info;
+
+        let mut spent: Vec<f64> = vec![0.0; self.payload.material_info.len()];
+        let mut chance: f64 = f64::NAN;
+        match &tap.kind {
+            TapKind::Normal { juice, book } => {
+                let avail = &juice_info.normal_uindex_to_id[upgrade.upgrade_index];
+                if *juice && !avail.contains(&0) {
+                    return Err(SessionError::JuiceNotAvailable(0));
+                }
+                if *book > 0 && !avail.contains(book) {
+                    return Err(SessionError::JuiceNotAvailable(*book));
+                }
+                chance = upgrade.next_tap_chance(*juice, *book, juice_info);
+
+                for (t_index, cost) in upgrade.costs.iter().enumerate() {
+                    spent[t_index] += cost
+                        + if upgrade.unlocked {
+                            0.0
+                        } else {
+                            upgrade.unlock_costs[t_index]
+                        };
+                }
+                // same as update_support_normal, juice does nothing below +4
+                if upgrade.upgrade_index >= 3 {
+                    let offset = 7 + if upgrade.is_weapon {
+                        0
+                    } else {
+                        juice_info.num_juice_avail
+                    };
+                    if *juice {
+                        spent[offset] +=
+                            juice_info.access(0, upgrade.upgrade_index).normal_amt_used as f64;
+                    }
+                    if *book > 0 {
+                        spent[offset + book] += juice_info
+                            .access(*book, upgrade.upgrade_index)
+                            .normal_amt_used as f64;
+                    }
+                }
+
+                let this = &mut self.payload.upgrade_info[position];
+                this.unlocked = true;
+                if !tap.succeeded {
+                    this.starting_artisan = Some(
+                        this.starting_artisan.unwrap_or(0.0)
+                            + ARTISAN_MULTIPLIER * chance * upgrade.artisan_rate,
+                    );
+                    this.starting_num_taps = Some(this.starting_num_taps.unwrap_or(0) + 1);
+                    if let Some(state) = this.state.as_mut().filter(|x| !x.is_empty()) {
+                        state.remove(0); // the plan for the rest of the taps stays the same
+                    }
+                }
+            }
+            TapKind::Special => {
+                if self.payload.special_budget < upgrade.special_cost {
+                    return Err(SessionError::NotEnoughSpecial {
+                        needed: upgrade.special_cost,
+                        left: self.payload.special_budget,
+                    });
+                }
+                chance = upgrade.base_chance;
+                self.payload.special_budget -= upgrade.special_cost;
+            }
+            TapKind::Advanced {
+                progress,
+                spent: adv_spent,
+            } => {
+                for (s, a) in spent.iter_mut().zip(adv_spent.iter()) {
+                    *s += a;
+                }
+                let this = &mut self.payload.upgrade_info[position];
+                this.unlocked = true;
+                this.adv_progress = Some(*progress);
+            }
+        }
+
+        let bought: Vec<f64> = self.payload.use_up(&spent);
+
+        if tap.succeeded {
+            self.payload.upgrade_info.remove(position);
+            self.payload.special_state = None;
+        }
+        self.update_cache(position, tap.succeeded)?;
+        self.taps.push(TapRecord {
+            tap,
+            chance,
+            spent,
+            bought,
+        });
+        Ok(self.taps.last().unwrap())
+    }
+
+    /// Remaining cost of the current plan (juice usage & special order as they were before the taps)
+    pub fn remaining(
+        &mut self,
+        performance: &mut Performance,
+    ) -> Result<StateBundle, PayloadError> {
+        let mut state_bundle = self.state_bundle()?;
+        state_bundle.metric = state_bundle.metric_router(performance);
+        state_bundle.set_latest_special_probs();
+        Ok(state_bundle)
+    }
+
+    /// Re-optimizes what's left and keeps the new plan in the payload so that later taps shift it along
+    #[cfg(feature = "v35")]
+    pub fn recommend<R: Rng>(
+        &mut self,
+        rng: &mut R,
+        performance: &mut Performance,
+    ) -> Result<StateBundle, PayloadError> {
+        let mut state_bundle = if self.payload.choose_treatment {
+            let (state_bundle, _) = solve_treatments(rng, self.state_bundle()?, performance);
+            self.payload.optimizer_plan = Some(state_bundle.prep_output.optimizer_plan.clone());
+            state_bundle
+        } else {
+            solve(rng, self.state_bundle()?, performance)
+        };
+        for (input, upgrade) in self
+            .payload
+            .upgrade_info
+            .iter_mut()
+            .zip(state_bundle.upgrade_arr.iter())
+        {
+            input.state = Some(upgrade.state.payload.clone());
+        }
+        self.payload.special_state = Some(state_bundle.special_state.clone());
+        self.cache = Some(state_bundle.clone());
+        state_bundle.set_latest_special_probs();
+        Ok(state_bundle)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::helpers::test_cases_dir;
+    use crate::payload::parse_to_payloads;
+
+    #[test]
+    fn failed_taps_carry_over() {
+        let payloads = parse_to_payloads(&test_cases_dir().join("payloads"));
+        let (_, payload) = payloads.iter().find(|(name, _)| name == "2122").unwrap();
+        let mut session = HoningSession::new(payload.clone());
+        let mut performance = Performance::new();
+        let num_upgrades = session.payload.upgrade_info.len();
+
+        let tap = Tap {
+            piece_type: 5,
+            upgrade_index: 20,
+            kind: TapKind::Normal {
+                juice: true,
+                book: 0,
+            },
+            succeeded: false,
+        };
+        let record = session.record_tap(tap.clone()).unwrap().clone();
+        assert!(record.chance > 0.0 && record.chance < 1.0);
+        assert!(record.spent[0] > 0.0);
+        let weapon = session
+            .payload
+            .upgrade_info
+            .iter()
+            .find(|x| x.piece_type == 5 && x.upgrade_index == 20)
+            .unwrap();
+        assert_eq!(weapon.starting_num_taps, Some(1));
+        assert!(weapon.starting_artisan.unwrap() > 0.0);
+
+        // having already failed once, the same tap is now more likely
+        let next = session.record_tap(tap).unwrap();
+        assert!(next.chance > record.chance);
+        assert!(
+            session
+                .remaining(&mut performance)
+                .unwrap()
+                .metric
+                .is_finite()
+        );
+
+        session
+            .record_tap(Tap {
+                piece_type: 5,
+                upgrade_index: 20,
+                kind: TapKind::Normal {
+                    juice: false,
+                    book: 0,
+                },
+                succeeded: true,
+            })
+            .unwrap();
+        assert_eq!(session.payload.upgrade_info.len(), num_upgrades - 1);
+
+        let round_trip: HoningSession =
+            serde_json::from_str(&serde_json::to_string(&session).unwrap()).unwrap();
+        assert_eq!(round_trip.taps.len(), 3);
+        assert!(matches!(
+            session.record_tap(Tap {
+                piece_type: 5,
+                upgrade_index: 20,
+                kind: TapKind::Special,
+                succeeded: true,
+            }),
+            Err(SessionError::UnknownUpgrade { .. })
+        ));
+    }
+
+    #[test]
+    fn cache_matches_a_fresh_bundle() {
+        let payloads = parse_to_payloads(&test_cases_dir().join("payloads"));
+        let (_, payload) = payloads
+            .iter()
+            .find(|(name, _)| name == "1920_adv3040")
+            .unwrap();
+        let mut payload = payload.clone();
+        payload.special_budget = 100_000;
+        let num_materials = payload.material_info.len();
+        let mut session = HoningSession::new(payload);
+        let mut performance = Performance::new();
+
+        let normal = |piece_type: usize, succeeded: bool| Tap {
+            piece_type,
+            upgrade_index: 18,
+            kind: TapKind::Normal {
+                juice: true,
+                book: 0,
+            },
+            succeeded,
+        };
+        let taps = [
+            normal(0, false),
+            Tap {
+                piece_type: 1,
+                upgrade_index: 18,
+                kind: TapKind::Special,
+                succeeded: false,
+            },
+            Tap {
+                piece_type: 0,
+                upgrade_index: 2,
+                kind: TapKind::Advanced {
+                    progress: (120, 3, false, true),
+                    spent: vec![10.0; num_materials],
+                },
+                succeeded: false,
+            },
+            normal(2, true),
+        ];
+        for tap in taps {
+            session.record_tap(tap).unwrap();
+            let cached = session
+                .state_bundle()
+                .unwrap()
+                .optimizer_average_gold_metric(&mut performance);
+            let fresh = StateBundle::init_from_payload(session.payload.clone())
+                .unwrap()
+                .optimizer_average_gold_metric(&mut performance);
+            assert_eq!(cached, fresh);
+        }
+        assert_eq!(
+            session.state_bundle().unwrap().upgrade_arr.len(),
+            session.payload.upgrade_info.len()
+        );
+    }
+}
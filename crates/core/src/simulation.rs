@@ -0,0 +1,228 @@
+//! Monte carlo simulation of whole honing sessions, one sample at a time.
+//!
+//! Normal honing samples the number of taps straight from normal_dist (so juice follows Upgrade::state like everywhere else),
+//! special leaps are played out in special_state order and advanced honing is played out ball by ball.
+//! Samples are streamed out of an iterator so callers can build whatever statistics they want without keeping them all around.
+use crate::advanced_honing::one_sim::one_sim;
+use crate::market_depth;
+use crate::state_bundle::StateBundle;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SimOutcome {
+    pub spent: Vec<f64>,  // per material row, same layout as optimizer_material_info
+    pub taps: Vec<usize>, // per upgrade_arr index, taps paid for with materials (0 if it was special leaped)
+    pub special_taps: Vec<usize>, // per upgrade_arr index, special leaps tried
+    pub special_spent: i64,
+    pub gold: f64, // gold left, the realized version of the average metric
+}
+
+/// Gold left after using x of one material, the realized version of one_dimension_average_gold
+pub fn realized_gold(x: f64, thresh_price_pairs: &[(f64, f64)], depth: &[(f64, f64)]) -> f64 {
+    let (last_thresh, last_price) = *thresh_price_pairs.last().unwrap();
+    let mut out: f64 = last_price * (last_thresh - x)
+        - market_depth::extra_cost(x - last_thresh, last_price, depth);
+    for (index, &(thresh, price)) in thresh_price_pairs.iter().enumerate().skip(1) {
+        let prev_price = thresh_price_pairs[index - 1].1;
+        out += (prev_price - price) * (thresh - x).max(0.0);
+    }
+    out
+}
+
+fn sample_index<R: Rng>(dist: &[f64], rng: &mut R) -> usize {
+    let r: f64 = rng.random::<f64>();
+    let mut cum_prob: f64 = 0.0;
+    for (index, p) in dist.iter().enumerate() {
+        cum_prob += p;
+        if r < cum_prob {
+            return index;
+        }
+    }
+    dist.len() - 1 // floating point leftovers
+}
+
+pub struct Simulator<R: Rng> {
+    state_bundle: StateBundle,
+    rng: R,
+}
+
+impl Simulator<StdRng> {
+    pub fn new(state_bundle: &StateBundle, seed: u64) -> Self {
+        Self::with_rng(state_bundle, StdRng::seed_from_u64(seed))
+    }
+}
+
+impl<R: Rng> Simulator<R> {
+    pub fn with_rng(state_bundle: &StateBundle, rng: R) -> Self {
+        let mut state_bundle = state_bundle.clone();
+        state_bundle.update_prob_dist();
+        state_bundle.update_cost_dist();
+        Self { state_bundle, rng }
+    }
+
+    pub fn state_bundle(&self) -> &StateBundle {
+        &self.state_bundle
+    }
+
+    pub fn one_sample(&mut self) -> SimOutcome {
+        self.one_sample_with_special(self.state_bundle.prep_output.special_budget)
+    }
+
+    /// one_sample, but with this many special leaps instead of the bundle's special_budget
+    pub fn one_sample_with_special(&mut self, special_budget: i64) -> SimOutcome {
+        let state_bundle = &self.state_bundle;
+        let rng = &mut self.rng;
+        let juice_info = &state_bundle.prep_output.juice_info;
+        let num_upgrades = state_bundle.upgrade_arr.len();
+
+        let mut spent: Vec<f64> = vec![0.0; juice_info.total_num_avail];
+        let mut taps: Vec<usize> = vec![0; num_upgrades];
+        let mut special_taps: Vec<usize> = vec![0; num_upgrades];
+        let mut special_left: i64 = special_budget;
+        let mut highest_upgrade_index_seen: Vec<i64> = vec![-1; 6];
+
+        for &u_index in state_bundle.special_state.iter() {
+            let upgrade = &state_bundle.upgrade_arr[u_index];
+            if upgrade.is_normal_honing {
+                // special leaps only go in order within a piece, and stop for good once one runs out of budget
+                let special_valid: bool =
+                    highest_upgrade_index_seen[upgrade.piece_type] <= upgrade.upgrade_index as i64;
+                if special_valid {
+                    highest_upgrade_index_seen[upgrade.piece_type] = upgrade.upgrade_index as i64;
+                }
+                let mut tap_index: usize = sample_index(&upgrade.normal_dist, rng);
+                if special_valid && special_left >= upgrade.special_cost {
+                    let mut succeeded: bool = false;
+                    while special_left >= upgrade.special_cost {
+                        special_left -= upgrade.special_cost;
+                        special_taps[u_index] += 1;
+                        if rng.random::<f64>() < upgrade.base_chance {
+                            succeeded = true;
+                            break;
+                        }
+                    }
+                    if !succeeded {
+                        special_left = -1;
+                    } else {
+                        tap_index = 0;
+                    }
+                }
+                taps[u_index] = tap_index;
+                for (s, support) in spent.iter_mut().zip(upgrade.cost_dist.iter()) {
+                    *s += support.support.get(tap_index).unwrap_or(&0.0);
+                }
+            } else {
+                let (cost, juice, scroll) = one_sim(rng, &upgrade.adv_config);
+                taps[u_index] = cost as usize;
+                for (t_index, s) in spent.iter_mut().take(7).enumerate() {
+                    if upgrade.adv_config.start_xp == 0 {
+                        *s += upgrade.unlock_costs[t_index];
+                    }
+                    *s += upgrade.costs[t_index] * cost as f64;
+                }
+                let offset = 7 + if upgrade.is_weapon {
+                    0
+                } else {
+                    juice_info.num_juice_avail
+                };
+                for &id in juice_info.adv_uindex_to_id[upgrade.upgrade_index].iter() {
+                    let used = if id == 0 { juice } else { scroll } as f64;
+                    spent[offset + id] +=
+                        juice_info.access(id, upgrade.upgrade_index).adv_amt_used as f64 * used;
+                }
+            }
+        }
+
+        let gold: f64 = spent
+            .iter()
+            .zip(state_bundle.prep_output.optimizer_material_info.iter())
+            .zip(state_bundle.prep_output.market_depth.iter())
+            .map(|((x, pairs), depth)| realized_gold(*x, pairs, depth))
+            .sum();
+        SimOutcome {
+            spent,
+            taps,
+            special_taps,
+            special_spent: special_budget - special_left.max(0),
+            gold,
+        }
+    }
+}
+
+impl<R: Rng> Iterator for Simulator<R> {
+    type Item = SimOutcome;
+
+    fn next(&mut self) -> Option<SimOutcome> {
+        Some(self.one_sample())
+    }
+}
+
+/// Shorthand for Simulator::new(..).take(num_samples)
+pub fn simulate(
+    state_bundle: &StateBundle,
+    seed: u64,
+    num_samples: usize,
+) -> impl Iterator<Item = SimOutcome> {
+    Simulator::new(state_bundle, seed).take(num_samples)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::helpers::distribute_budgets;
+    use crate::helpers::test_cases_dir;
+    use crate::parser::MaterialTier;
+    use crate::payload::parse_to_payloads;
+    use crate::performance::Performance;
+
+    #[test]
+    fn matches_average_metric() {
+        let payloads = parse_to_payloads(&test_cases_dir().join("payloads"));
+        let (_, payload) = payloads.iter().find(|(name, _)| name == "2122").unwrap();
+        let mut state_bundle = StateBundle::init_from_payload(payload.clone()).unwrap();
+        let mut performance = Performance::new();
+        let metric = state_bundle.optimizer_average_gold_metric(&mut performance);
+
+        let num_samples: usize = 20000;
+        let mut sum: f64 = 0.0;
+        let mut sum_sq: f64 = 0.0;
+        for outcome in simulate(&state_bundle, 0, num_samples) {
+            assert_eq!(outcome.taps.len(), state_bundle.upgrade_arr.len());
+            sum += outcome.gold;
+            sum_sq += outcome.gold * outcome.gold;
+        }
+        let n = num_samples as f64;
+        let mean = sum / n;
+        let std_err = ((sum_sq / n - mean * mean).max(0.0) / n).sqrt();
+        assert!((mean - metric).abs() < 4.0 * std_err + 1e-6 * metric.abs());
+
+        // same seed, same samples
+        let a: Vec<f64> = simulate(&state_bundle, 7, 5).map(|x| x.gold).collect();
+        let b: Vec<f64> = simulate(&state_bundle, 7, 5).map(|x| x.gold).collect();
+        assert_eq!(a, b);
+    }
+
+    #[test]
+    fn leftovers_sell_for_less() {
+        let row = vec![
+            MaterialTier::bound(10.0, 0.0),
+            MaterialTier {
+                owned: 10.0,
+                buy: 5.0,
+                sell: 4.0,
+                sellable: true,
+            },
+            MaterialTier::tradable(0.0, 5.0),
+        ];
+        let pairs = &distribute_budgets(&vec![row], &[0, 1, 2])[0];
+        assert_eq!(pairs, &vec![(0.0, 0.0), (10.0, 4.0), (20.0, 5.0)]);
+        assert_eq!(realized_gold(5.0, pairs, &[]), 40.0); // bound leftovers are worth nothing
+        assert_eq!(realized_gold(15.0, pairs, &[]), 20.0);
+        assert_eq!(realized_gold(25.0, pairs, &[]), -25.0);
+        // nothing owned is just buying everything
+        let empty = &distribute_budgets(&vec![vec![MaterialTier::tradable(0.0, 5.0)]], &[0])[0];
+        assert_eq!(empty, &vec![(0.0, 5.0)]);
+    }
+}
@@ -0,0 +1,449 @@
+//! Doing the upgrades in stages (say all the advanced honing first, then normal honing) instead of as one batch.
+//!
+//! Normally every upgrade is planned at once against the whole budget. In stages, each stage is planned against whatever
+//! the earlier stages are expected to leave over, and the special leaps go to the earlier stages first.
+//! With replan, each stage is planned again once the earlier stages are done, on the budget that's actually left.
+//!
+//! There's no closed form for this so it's simulated. Replanning after every single sample would mean one solve per sample,
+//! so instead the samples are sorted by how many special leaps they have left, then by how much gold they've spent so far,
+//! and split into num_buckets groups. Each group gets replanned on its average leftover materials and on the smallest
+//! special budget in the group, so that no plan counts on special leaps a sample doesn't have.
+//! Every sample is still played out with its own special budget, so a later stage gets the leaps an earlier one didn't use.
+//! The gold at the end is always from the total spent over all stages against the original budget, so orders are comparable.
+use crate::instructions::upgrade_label;
+use crate::model::PayloadError;
+use crate::parser::OneUpgradeInput;
+use crate::payload::Payload;
+use crate::simulation::{SimOutcome, Simulator, realized_gold};
+use crate::state_bundle::StateBundle;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+#[cfg(feature = "v35")]
+use crate::optimizer::solve;
+#[cfg(feature = "v35")]
+use crate::performance::Performance;
+#[cfg(feature = "v35")]
+use crate::treatment::solve_treatments;
+#[cfg(feature = "v35")]
+use rand::Rng;
+
+fn default_num_samples() -> usize {
+    5000
+}
+fn default_num_buckets() -> usize {
+    4
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub enum StageOrder {
+    Together, // one stage, same as not staging at all
+    AdvFirst,
+    NormalFirst,
+    Custom(Vec<Vec<usize>>), // indices into upgrade_info, every upgrade has to be in exactly one stage
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum StageError {
+    Payload(PayloadError),
+    NoSamples,
+    NoOrders,
+    BadOrder(String),
+}
+
+impl fmt::Display for StageError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            StageError::Payload(e) => write!(f, "{}", e),
+            StageError::NoSamples => write!(f, "num_samples has to be at least 1"),
+            StageError::NoOrders => write!(f, "there are no orders to compare"),
+            StageError::BadOrder(reason) => write!(f, "{}", reason),
+        }
+    }
+}
+
+impl std::error::Error for StageError {}
+
+impl From<PayloadError> for StageError {
+    fn from(e: PayloadError) -> Self {
+        StageError::Payload(e)
+    }
+}
+
+impl StageOrder {
+    /// Indices into upgrade_info per stage, empty stages are dropped
+    pub fn stages(&self, upgrade_info: &[OneUpgradeInput]) -> Result<Vec<Vec<usize>>, StageError> {
+        let of_kind = |is_normal_honing: bool| -> Vec<usize> {
+            (0..upgrade_info.len())
+                .filter(|&i| upgrade_info[i].is_normal_honing == is_normal_honing)
+                .collect()
+        };
+        let stages: Vec<Vec<usize>> = match self {
+            StageOrder::Together => vec![(0..upgrade_info.len()).collect()],
+            StageOrder::AdvFirst => vec![of_kind(false), of_kind(true)],
+            StageOrder::NormalFirst => vec![of_kind(true), of_kind(false)],
+            StageOrder::Custom(stages) => {
+                let mut seen: Vec<usize> = stages.iter().flatten().copied().collect();
+                seen.sort();
+                if seen != (0..upgrade_info.len()).collect::<Vec<usize>>() {
+                    return Err(StageError::BadOrder(format!(
+                        "every one of the {} upgrades has to be in exactly one stage",
+                        upgrade_info.len()
+                    )));
+                }
+                stages.clone()
+            }
+        };
+        Ok(stages.into_iter().filter(|x| !x.is_empty()).collect())
+    }
+}
+
+#[derive(Deserialize, Clone, Serialize)]
+pub struct StagePayload {
+    pub payload: Payload,
+    pub orders: Vec<StageOrder>, // compared against each other
+    pub replan: bool,
+    #[serde(default = "default_num_samples")]
+    pub num_samples: usize,
+    #[serde(default = "default_num_buckets")]
+    pub num_buckets: usize, // how many budgets each stage gets replanned for, only used with replan
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct StagedPlan {
+    pub order: StageOrder,
+    pub stages: Vec<StateBundle>, // planned up front, replanned versions aren't kept
+    pub names: Vec<Vec<String>>,  // per stage
+    pub simulated: f64,           // average gold left
+    pub std_err: f64,
+    pub special_spent: Vec<f64>, // per stage, average special budget used
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct OrderComparison {
+    pub plans: Vec<StagedPlan>, // same order as StagePayload::orders
+    pub best: usize,
+}
+
+struct Progress {
+    spent: Vec<f64>,
+    special_left: i64,
+}
+
+/// payload for one stage, with the average of these paths' materials already used up,
+/// and the special budget every one of them still has
+fn stage_payload(payload: &Payload, stage: &[usize], paths: &[&Progress]) -> Payload {
+    let mut out: Payload = payload.clone();
+    if !paths.is_empty() {
+        let n = paths.len() as f64;
+        let mean_spent: Vec<f64> = (0..paths[0].spent.len())
+            .map(|row| paths.iter().map(|x| x.spent[row]).sum::<f64>() / n)
+            .collect();
+        out.use_up(&mean_spent);
+        out.special_budget = paths.iter().map(|x| x.special_left).min().unwrap();
+    }
+    out.upgrade_info = stage
+        .iter()
+        .map(|&i| payload.upgrade_info[i].clone())
+        .collect();
+    out.special_state = None;
+    out
+}
+
+/// Same budget, but juice usage, special order and treatment taken from an already planned bundle
+fn with_plan(mut payload: Payload, planned: &StateBundle) -> Payload {
+    for (input, upgrade) in payload
+        .upgrade_info
+        .iter_mut()
+        .zip(planned.upgrade_arr.iter())
+    {
+        input.state = Some(upgrade.state.payload.clone());
+    }
+    payload.special_state = Some(planned.special_state.clone());
+    payload.optimizer_plan = Some(planned.prep_output.optimizer_plan.clone());
+    payload
+}
+
+/// Simulates one order, plan is whatever optimizes a bundle (solve, or nothing at all to keep the payload's plan)
+pub fn evaluate_order(
+    stage_payload_in: &StagePayload,
+    order: &StageOrder,
+    plan: &mut impl FnMut(StateBundle) -> StateBundle,
+) -> Result<StagedPlan, StageError> {
+    let payload = &stage_payload_in.payload;
+    let num_samples = stage_payload_in.num_samples;
+    if num_samples == 0 {
+        return Err(StageError::NoSamples);
+    }
+    let full: StateBundle = StateBundle::init_from_payload(payload.clone())?;
+    let gold_of = |spent: &[f64]| -> f64 {
+        spent
+            .iter()
+            .zip(full.prep_output.optimizer_material_info.iter())
+            .zip(full.prep_output.market_depth.iter())
+            .map(|((x, pairs), depth)| realized_gold(*x, pairs, depth))
+            .sum()
+    };
+
+    let stages = order.stages(&payload.upgrade_info)?;
+    let mut paths: Vec<Progress> = Vec::new();
+    let mut planned_stages: Vec<StateBundle> = Vec::with_capacity(stages.len());
+    let mut special_spent: Vec<i64> = vec![0; stages.len()]; // summed over the samples
+    for (stage_index, stage) in stages.iter().enumerate() {
+        let all: Vec<&Progress> = paths.iter().collect();
+        let planned = plan(StateBundle::init_from_payload(stage_payload(
+            payload, stage, &all,
+        ))?);
+
+        if stage_index == 0 {
+            paths = Simulator::new(&planned, 0)
+                .take(num_samples)
+                .map(|x: SimOutcome| Progress {
+                    special_left: payload.special_budget - x.special_spent,
+                    spent: x.spent,
+                })
+                .collect();
+            special_spent[0] = paths
+                .iter()
+                .map(|x| payload.special_budget - x.special_left)
+                .sum();
+            planned_stages.push(planned);
+            continue;
+        }
+
+        // most special left first, then least spent so far
+        let golds: Vec<f64> = paths.iter().map(|x| gold_of(&x.spent)).collect();
+        let mut sorted: Vec<usize> = (0..paths.len()).collect();
+        sorted.sort_by(|&a, &b| {
+            paths[b]
+                .special_left
+                .cmp(&paths[a].special_left)
+                .then(golds[b].total_cmp(&golds[a]))
+        });
+        let num_buckets = if stage_payload_in.replan {
+            stage_payload_in.num_buckets.max(1)
+        } else {
+            1
+        };
+        for (bucket_index, bucket) in sorted.chunks(paths.len().div_ceil(num_buckets)).enumerate() {
+            let members: Vec<&Progress> = bucket.iter().map(|&i| &paths[i]).collect();
+            let this_payload = stage_payload(payload, stage, &members);
+            let bundle = if stage_payload_in.replan {
+                plan(StateBundle::init_from_payload(this_payload)?)
+            } else {
+                StateBundle::init_from_payload(with_plan(this_payload, &planned))?
+            };
+            let mut simulator =
+                Simulator::new(&bundle, (stage_index * num_buckets + bucket_index) as u64);
+            for &i in bucket.iter() {
+                let outcome: SimOutcome = simulator.one_sample_with_special(paths[i].special_left);
+                for (s, x) in paths[i].spent.iter_mut().zip(outcome.spent.iter()) {
+                    *s += x;
+                }
+                paths[i].special_left -= outcome.special_spent;
+                special_spent[stage_index] += outcome.special_spent;
+            }
+        }
+        planned_stages.push(planned);
+    }
+
+    let golds: Vec<f64> = paths.iter().map(|x| gold_of(&x.spent)).collect();
+    let n = golds.len() as f64;
+    let mean: f64 = golds.iter().sum::<f64>() / n;
+    let var: f64 = golds.iter().map(|x| (x - mean).powi(2)).sum::<f64>() / n;
+    Ok(StagedPlan {
+        order: order.clone(),
+        names: planned_stages
+            .iter()
+            .map(|x| x.upgrade_arr.iter().map(upgrade_label).collect())
+            .collect(),
+        stages: planned_stages,
+        simulated: mean,
+        std_err: (var / n).sqrt(),
+        special_spent: special_spent.iter().map(|&x| x as f64 / n).collect(),
+    })
+}
+
+pub fn compare_orders(
+    stage_payload_in: &StagePayload,
+    plan: &mut impl FnMut(StateBundle) -> StateBundle,
+) -> Result<OrderComparison, StageError> {
+    if stage_payload_in.orders.is_empty() {
+        return Err(StageError::NoOrders);
+    }
+    let plans: Vec<StagedPlan> = stage_payload_in
+        .orders
+        .iter()
+        .map(|order| evaluate_order(stage_payload_in, order, plan))
+        .collect::<Result<_, _>>()?;
+    let best = (0..plans.len())
+        .max_by(|&a, &b| plans[a].simulated.total_cmp(&plans[b].simulated))
+        .unwrap();
+    Ok(OrderComparison { plans, best })
+}
+
+/// compare_orders with every stage planned by solve (or solve_treatments if the payload lets the optimizer choose)
+#[cfg(feature = "v35")] // needs an optimizer version
+pub fn solve_orders<R: Rng>(
+    rng: &mut R,
+    stage_payload_in: &StagePayload,
+    performance: &mut Performance,
+) -> Result<OrderComparison, StageError> {
+    let choose_treatment = stage_payload_in.payload.choose_treatment;
+    compare_orders(stage_payload_in, &mut |state_bundle| {
+        if state_bundle.upgrade_arr.is_empty() {
+            state_bundle
+        } else if choose_treatment {
+            solve_treatments(rng, state_bundle, performance).0
+        } else {
+            solve(rng, state_bundle, performance)
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::helpers::test_cases_dir;
+    use crate::payload::parse_to_payloads;
+    use crate::performance::Performance;
+
+    #[test]
+    fn together_matches_metric() {
+        let (_, payload) = parse_to_payloads(&test_cases_dir().join("payloads"))
+            .into_iter()
+            .find(|(name, _)| name == "1920_adv3040")
+            .unwrap();
+        let metric = StateBundle::init_from_payload(payload.clone())
+            .unwrap()
+            .optimizer_average_gold_metric(&mut Performance::new());
+        let stage_payload_in = StagePayload {
+            payload,
+            orders: vec![StageOrder::Together, StageOrder::AdvFirst],
+            replan: true,
+            num_samples: 4000,
+            num_buckets: 3,
+        };
+        let comparison = compare_orders(&stage_payload_in, &mut |x| x).unwrap();
+
+        let together = &comparison.plans[0];
+        assert_eq!(together.stages.len(), 1);
+        assert!((together.simulated - metric).abs() < 4.0 * together.std_err + 1e-6 * metric.abs());
+
+        // nothing gets replanned differently without an optimizer, so staging only moves the random numbers around
+        let adv_first = &comparison.plans[1];
+        assert_eq!(adv_first.names[0].len(), 12);
+        assert!(adv_first.names[0].iter().all(|x| x.contains("adv")));
+        let tol = 4.0 * (together.std_err.powi(2) + adv_first.std_err.powi(2)).sqrt();
+        assert!((adv_first.simulated - together.simulated).abs() < tol + 1e-6 * metric.abs());
+    }
+
+    #[test]
+    fn replans_each_bucket() {
+        let (_, mut payload) = parse_to_payloads(&test_cases_dir().join("payloads"))
+            .into_iter()
+            .find(|(name, _)| name == "1920_adv3040")
+            .unwrap();
+        payload.special_budget = 3000;
+        let stage_payload_in = StagePayload {
+            payload,
+            orders: vec![StageOrder::AdvFirst],
+            replan: true,
+            num_samples: 300,
+            num_buckets: 3,
+        };
+
+        // juices every normal tap, and remembers which special budgets it got asked to plan for
+        let mut budgets: Vec<i64> = Vec::new();
+        let mut juice_all = |mut x: StateBundle| {
+            budgets.push(x.prep_output.special_budget);
+            for upgrade in x.upgrade_arr.iter_mut().filter(|u| u.is_normal_honing) {
+                let juiced: Vec<(bool, usize)> =
+                    upgrade.state.iter().map(|&(_, id)| (true, id)).collect();
+                upgrade.state.update_payload(juiced);
+            }
+            x
+        };
+        let comparison = compare_orders(&stage_payload_in, &mut juice_all).unwrap();
+        let plan = &comparison.plans[0];
+
+        // one plan for the first stage, then the average plan and one per bucket for the second
+        assert_eq!(budgets.len(), 1 + 1 + 3);
+        assert_eq!(budgets[0], 3000);
+        let buckets = &budgets[2..];
+        assert!(buckets.iter().all(|&x| (0..=3000).contains(&x)));
+        assert!(buckets.windows(2).all(|x| x[0] >= x[1]));
+        assert!(
+            plan.stages[1]
+                .upgrade_arr
+                .iter()
+                .filter(|u| u.is_normal_honing)
+                .all(|u| u.state.iter().all(|&(juice, _)| juice))
+        );
+
+        let unjuiced = compare_orders(&stage_payload_in, &mut |x| x).unwrap();
+        assert!(plan.simulated != unjuiced.plans[0].simulated);
+    }
+
+    #[test]
+    fn later_stage_gets_leftover_special() {
+        let (_, mut payload) = parse_to_payloads(&test_cases_dir().join("payloads"))
+            .into_iter()
+            .find(|(name, _)| name == "1920_adv3040")
+            .unwrap();
+        // enough special leaps for about 1 / base_chance taps on the first upgrade,
+        // so some samples burn all of it there and the rest have some left for the second stage
+        let first = &StateBundle::init_from_payload(payload.clone())
+            .unwrap()
+            .upgrade_arr[0];
+        payload.special_budget = (1.0 / first.base_chance).ceil() as i64 * first.special_cost;
+        let stage_payload_in = StagePayload {
+            payload,
+            orders: vec![StageOrder::Custom(vec![vec![0], (1..24).collect()])],
+            replan: false,
+            num_samples: 2000,
+            num_buckets: 3,
+        };
+        let comparison = compare_orders(&stage_payload_in, &mut |x| x).unwrap();
+        let special_spent = &comparison.plans[0].special_spent;
+
+        // the smallest special_left over all samples is 0, so planning the second stage on it alone would give no free taps
+        assert!(special_spent[0] < stage_payload_in.payload.special_budget as f64);
+        assert!(special_spent[1] > 0.0);
+        assert!(
+            special_spent.iter().sum::<f64>() <= stage_payload_in.payload.special_budget as f64
+        );
+    }
+
+    #[test]
+    fn bad_input_is_an_error() {
+        let (_, payload) = parse_to_payloads(&test_cases_dir().join("payloads"))
+            .into_iter()
+            .find(|(name, _)| name == "1920_adv3040")
+            .unwrap();
+        let mut stage_payload_in = StagePayload {
+            payload,
+            orders: vec![StageOrder::Together],
+            replan: true,
+            num_samples: 0,
+            num_buckets: 3,
+        };
+        assert_eq!(
+            compare_orders(&stage_payload_in, &mut |x| x).unwrap_err(),
+            StageError::NoSamples
+        );
+
+        stage_payload_in.num_samples = 10;
+        stage_payload_in.orders = vec![StageOrder::Custom(vec![vec![0, 1], vec![1]])];
+        assert!(matches!(
+            compare_orders(&stage_payload_in, &mut |x| x).unwrap_err(),
+            StageError::BadOrder(_)
+        ));
+
+        stage_payload_in.orders = vec![];
+        assert_eq!(
+            compare_orders(&stage_payload_in, &mut |x| x).unwrap_err(),
+            StageError::NoOrders
+        );
+    }
+}
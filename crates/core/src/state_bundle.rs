@@ -52,6 +52,8 @@ impl StateBundle {
         match self.metric_type {
             0 => unreachable!(), //self.success_prob_metric(performance),
             1 => self.optimizer_average_gold_metric(performance),
+            2 => self.expected_scenario_gold_metric(performance),
+            3 => self.worst_scenario_gold_metric(performance),
             _ => NAN,
         }
     }
@@ -83,6 +85,29 @@ impl StateBundle {
         self.metric = source.metric;
     }
 
+    /// A bundle with only the upgrades at `indices` (in that order), keeping their states and relative special order
+    ///
+    /// Cost distributions are carried over so this is cheap, caches are dropped since they're keyed by special_state
+    pub fn subset(&self, indices: &[usize]) -> StateBundle {
+        let mut out: StateBundle = StateBundle::new(
+            self.prep_output.clone(),
+            indices
+                .iter()
+                .map(|&u_index| self.upgrade_arr[u_index].clone())
+                .collect(),
+        );
+        out.special_state = self
+            .special_state
+            .iter()
+            .filter_map(|u_index| indices.iter().position(|x| x == u_index))
+            .collect();
+        out.metric_type = self.metric_type;
+        out.min_resolution = self.min_resolution;
+        out.num_threads = self.num_threads;
+        out.adv_cache = self.adv_cache.clone();
+        out
+    }
+
     pub fn to_essence(&self) -> StateEssence {
         StateEssence {
             state_arr: self
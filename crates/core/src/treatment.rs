@@ -0,0 +1,254 @@
+//! Treatment plans: how each column (material source) of material_info gets valued.
+//!
+//! Columns are sources in the order they get used up (char-bound, roster-bound, tradable, market by default,
+//! but anything like guild shop, event exchange or alt-transfer can be its own column with its own owned amounts and prices).
+//! Every column has its own buy price, sell price and whether it can be sold at all (see MaterialTier),
+//! the buy price of the last column is what anything beyond all the owned mats gets bought at, so that one should stay the market.
+//! plan[source] is the column whose leftover value (and place in the order) that source's mats are treated as, see distribute_budgets.
+//! So [0, 1, 1, 2] means leftover tradable mats are worth as much as bound ones, [0, 1, 2, 3] means they'd rather be sold.
+//!
+//! The plans in treatment_plans are meant to be real alternatives, and the optimizer can pick whichever leaves the most gold.
+//! A plan changes what the optimizer aims for, but candidates are compared on the gold actually left (realized_metric),
+//! otherwise the plan that prices leftovers highest would always win.
+use crate::constants::{TreatmentsType, UI_TREATMENTS};
+use crate::helpers::realized_budgets;
+use crate::parser::PreparationOutput;
+use crate::performance::Performance;
+use crate::state_bundle::StateBundle;
+use serde::{Deserialize, Serialize};
+
+pub const DEFAULT_MATERIAL_SOURCES: [&str; 4] =
+    ["char_bound", "roster_bound", "tradable", "market"];
+const DEFAULT_TREATMENT_NAMES: [&str; 2] = ["tradable used as bound", "tradable sold"];
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TreatmentPlan {
+    pub name: String,
+    pub plan: TreatmentsType, // [material source] = column it's treated as
+}
+
+impl TreatmentPlan {
+    /// Every source as itself
+    pub fn identity(num_sources: usize) -> TreatmentPlan {
+        TreatmentPlan {
+            name: "as given".to_owned(),
+            plan: (0..num_sources).collect(),
+        }
+    }
+
+    pub fn check(&self, num_sources: usize) -> Result<(), String> {
+        if self.plan.len() != num_sources {
+            return Err(format!(
+                "treatment plan \"{}\" has {} entries but there are {} material sources",
+                self.name,
+                self.plan.len(),
+                num_sources
+            ));
+        }
+        if let Some(x) = self.plan.iter().find(|x| **x >= num_sources) {
+            return Err(format!(
+                "treatment plan \"{}\" points at source {} but there are only {}",
+                self.name, x, num_sources
+            ));
+        }
+        Ok(())
+    }
+}
+
+/// The standard four columns get their names, anything else is numbered
+pub fn default_material_sources(num_sources: usize) -> Vec<String> {
+    if num_sources == DEFAULT_MATERIAL_SOURCES.len() {
+        DEFAULT_MATERIAL_SOURCES.map(|x| x.to_owned()).to_vec()
+    } else {
+        (0..num_sources).map(|i| format!("source {}", i)).collect()
+    }
+}
+
+/// UI_TREATMENTS for the standard four columns, otherwise just the identity
+pub fn default_treatment_plans(num_sources: usize) -> Vec<TreatmentPlan> {
+    if num_sources == DEFAULT_MATERIAL_SOURCES.len() {
+        UI_TREATMENTS
+            .iter()
+            .zip(DEFAULT_TREATMENT_NAMES)
+            .map(|(plan, name)| TreatmentPlan {
+                name: name.to_owned(),
+                plan: plan.to_vec(),
+            })
+            .collect()
+    } else {
+        vec![TreatmentPlan::identity(num_sources)]
+    }
+}
+
+impl PreparationOutput {
+    pub fn treatment_arr(&self) -> Vec<TreatmentsType> {
+        self.treatment_plans
+            .iter()
+            .map(|x| x.plan.clone())
+            .collect()
+    }
+
+    /// Index into treatment_plans of the plan the optimizer is using, None if it's not one of them
+    pub fn treatment_index(&self) -> Option<usize> {
+        self.treatment_plans
+            .iter()
+            .position(|x| x.plan == self.optimizer_plan)
+    }
+}
+
+impl StateBundle {
+    pub fn set_treatment(&mut self, treatment_index: usize) {
+        let plan = self.prep_output.treatment_plans[treatment_index]
+            .plan
+            .clone();
+        self.prep_output.set_optimizer_plan(plan);
+    }
+
+    /// Gold actually left for metric_type, with every source's leftovers at its own value (see realized_budgets).
+    /// Unlike metric_router this is on the same scale whatever the treatment plan is
+    pub fn realized_metric(&mut self, performance: &mut Performance) -> f64 {
+        self.update_prob_dist();
+        self.update_cost_dist();
+        self.compute_special_probs(false);
+        performance.states_evaluated += 1;
+
+        let raw = &self.prep_output.raw_material_info;
+        let plan = &self.prep_output.optimizer_plan;
+        let golds: Vec<(f64, f64)> = match &self.prep_output.price_scenarios {
+            Some(scenarios) if !scenarios.is_empty() && self.metric_type != 1 => scenarios
+                .iter()
+                .map(|x| {
+                    let thresh_prices = realized_budgets(&x.apply(raw), plan);
+                    (x.weight, self.average_gold(&thresh_prices, performance))
+                })
+                .collect(),
+            _ => vec![(
+                1.0,
+                self.average_gold(&realized_budgets(raw, plan), performance),
+            )],
+        };
+        if self.metric_type == 3 {
+            golds.iter().map(|x| x.1).fold(f64::INFINITY, f64::min)
+        } else {
+            let total_weight: f64 = golds.iter().map(|x| x.0).sum();
+            golds.iter().map(|(w, x)| w * x).sum::<f64>() / total_weight
+        }
+    }
+}
+
+/// The candidate that actually leaves the most gold, and its index. Each candidate is usually the states optimized under
+/// one treatment plan: a plan's own metric prices leftovers its own way, so those can't be compared with each other directly
+pub fn best_treatment(
+    candidates: Vec<StateBundle>,
+    performance: &mut Performance,
+) -> (StateBundle, usize) {
+    let mut best: Option<(StateBundle, usize, f64)> = None;
+    for (index, mut candidate) in candidates.into_iter().enumerate() {
+        let realized = candidate.realized_metric(performance);
+        if best.as_ref().is_none_or(|(_, _, b)| realized > *b) {
+            best = Some((candidate, index, realized));
+        }
+    }
+    let (state_bundle, index, _) = best.unwrap();
+    (state_bundle, index)
+}
+
+/// Optimizes under every treatment plan separately and keeps the one that leaves the most gold (see best_treatment),
+/// since the best states under one plan can be pretty bad under another
+#[cfg(feature = "v35")]
+pub fn solve_treatments<R: rand::Rng>(
+    rng: &mut R,
+    state_bundle: StateBundle,
+    performance: &mut Performance,
+) -> (StateBundle, usize) {
+    use crate::optimizer::solve;
+
+    let candidates: Vec<StateBundle> = (0..state_bundle.prep_output.treatment_plans.len())
+        .map(|treatment_index| {
+            let mut this = state_bundle.clone();
+            this.set_treatment(treatment_index);
+            solve(rng, this, performance)
+        })
+        .collect();
+    best_treatment(candidates, performance)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::helpers::{my_pct_diff, test_cases_dir};
+    use crate::model::PayloadError;
+    use crate::payload::parse_to_payloads;
+
+    #[test]
+    fn best_treatment_is_the_max() {
+        let (_, payload) = parse_to_payloads(&test_cases_dir().join("payloads"))
+            .into_iter()
+            .find(|(x, _)| x == "reaper")
+            .unwrap();
+        assert_eq!(payload.treatment_plans, default_treatment_plans(4));
+        let plain = StateBundle::init_from_payload(payload.clone()).unwrap();
+        let mut juiced = plain.clone();
+        for upgrade in juiced.upgrade_arr.iter_mut() {
+            if upgrade.is_normal_honing {
+                let state: Vec<(bool, usize)> = upgrade.state.iter().map(|x| (true, x.1)).collect();
+                upgrade.state.update_payload(state);
+            }
+        }
+        let mut performance = Performance::new();
+        let under = |state_bundle: &StateBundle, treatment_index: usize| {
+            let mut out = state_bundle.clone();
+            out.set_treatment(treatment_index);
+            out
+        };
+
+        // the plans' own metrics price leftover tradable mats differently, the realized one doesn't care
+        let mut plain_bound = under(&plain, 0);
+        let mut plain_sold = under(&plain, 1);
+        assert!(
+            plain_bound.metric_router(&mut performance)
+                < plain_sold.metric_router(&mut performance)
+        );
+        let realized = plain_sold.realized_metric(&mut performance);
+        assert!(my_pct_diff(realized, plain_bound.realized_metric(&mut performance)) < 1e-9);
+        // "tradable sold" is the identity, so its own metric is already the realized one
+        assert!(my_pct_diff(realized, plain_sold.metric_router(&mut performance)) < 1e-9);
+
+        // whichever states are better wins, whatever plan they came from
+        let juiced_realized = under(&juiced, 0).realized_metric(&mut performance);
+        assert!(my_pct_diff(realized, juiced_realized) > 1e-6);
+        let plain_wins = realized > juiced_realized;
+        let (_, index) =
+            best_treatment(vec![under(&plain, 0), under(&juiced, 1)], &mut performance);
+        assert_eq!(index, if plain_wins { 0 } else { 1 });
+        let (winner, index) =
+            best_treatment(vec![under(&juiced, 0), under(&plain, 1)], &mut performance);
+        assert_eq!(index, if plain_wins { 1 } else { 0 });
+        assert_eq!(winner.prep_output.treatment_index(), Some(index));
+
+        assert!(
+            TreatmentPlan {
+                name: "short".to_owned(),
+                plan: vec![0, 1]
+            }
+            .check(5)
+            .is_err()
+        );
+        let mut short_plan = payload.clone();
+        short_plan.treatment_plans[0].plan.pop();
+        assert!(matches!(
+            StateBundle::init_from_payload(short_plan),
+            Err(PayloadError::BadTreatmentPlan(_))
+        ));
+        let mut unnamed = payload;
+        unnamed.material_sources.pop();
+        assert_eq!(
+            StateBundle::init_from_payload(unnamed).err(),
+            Some(PayloadError::ColumnMismatch {
+                row: 0,
+                columns: 4,
+                num_sources: 3
+            })
+        );
+    }
+}
@@ -1,4 +1,5 @@
-mod monte_carlo;
-mod one_adv_sim;
+pub mod monte_carlo;
 pub mod run_tests;
-mod utils;
+pub mod sweep;
+pub mod utils;
+pub mod tune;
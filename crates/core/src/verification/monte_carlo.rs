@@ -1,11 +1,14 @@
 //! Monte carlo to experimentally verify our results, not used in the website (anymore)
+//!
+//! Deliberately doesn't go through Simulator: taps, juice and special leaps are sampled here from scratch,
+//! so a bug in cost_dist or in how juice costs get built shows up as a mismatch instead of being shared by both sides.
 
+use crate::advanced_honing::one_sim::one_sim;
 use crate::constants::FLOAT_TOL;
 use crate::core::average::DEBUG_AVERAGE;
 use crate::my_dbg;
 use crate::state_bundle::StateBundle;
 use crate::upgrade::Upgrade;
-use crate::verification::one_adv_sim::one_sim;
 use crate::verification::utils::apply_prices;
 use itertools::izip;
 use rand::Rng;
@@ -283,6 +286,7 @@ pub fn monte_carlo_wrapper<R: Rng>(
             *d += apply_prices(
                 float_row[index],
                 &state_bundle.prep_output.optimizer_material_info[index],
+                &state_bundle.prep_output.market_depth[index],
             )
         }
         for (index, d) in debug_avg_gold_by_mats_by_skip[skip_count_data[r_index]]
@@ -292,6 +296,7 @@ pub fn monte_carlo_wrapper<R: Rng>(
             *d += apply_prices(
                 float_row[index],
                 &state_bundle.prep_output.optimizer_material_info[index],
+                &state_bundle.prep_output.market_depth[index],
             )
         }
 
@@ -302,6 +307,7 @@ pub fn monte_carlo_wrapper<R: Rng>(
                 apply_prices(
                     *used,
                     &state_bundle.prep_output.optimizer_material_info[index],
+                    &state_bundle.prep_output.market_depth[index],
                 )
             })
             .sum();
@@ -315,7 +321,7 @@ pub fn monte_carlo_wrapper<R: Rng>(
         for (support_index, mat) in row.iter().enumerate() {
             for treatment_plan in 0..state_bundle.prep_output.raw_num_breakpoints {
                 if *mat as f64
-                    <= state_bundle.prep_output.raw_material_info[support_index][treatment_plan].1
+                    <= state_bundle.prep_output.raw_material_info[support_index][treatment_plan].buy
                 {
                     leftover_counts[support_index][treatment_plan] += 1;
                 }
@@ -2,7 +2,7 @@ use crate::constants::{
     MONTE_CARLO_CONFIDENCE, MONTE_CARLO_COUNT, MONTE_CARLO_PRECISION, SIMULATED_ANNEALING_DIFF_TOL,
 };
 use crate::core::average::DEBUG_AVERAGE;
-use crate::helpers::{my_pct_diff, write_jsonl};
+use crate::helpers::{my_pct_diff, test_cases_dir, write_jsonl};
 use crate::optimizer::ACTIVE_FEATURE;
 use crate::optimizer::solve;
 use crate::payload::parse_to_state_bundles;
@@ -65,17 +65,23 @@ pub fn run_tests(payload_path_string: String, is_verify: bool) {
     println!("Using {} threads", thread_num);
 
     let file_name: String = if is_verify {
-        format!(
-            "../../test_cases/verification_results/{}_{}.jsonl",
-            ACTIVE_FEATURE.replace("default, ", "").to_owned(),
-            payload_name
-        )
+        test_cases_dir()
+            .join(format!(
+                "verification_results/{}_{}.jsonl",
+                ACTIVE_FEATURE.replace("default, ", "").to_owned(),
+                payload_name
+            ))
+            .to_string_lossy()
+            .to_string()
     } else {
-        format!(
-            "./test_cases/optimizer_results/{}_{}.jsonl",
-            ACTIVE_FEATURE.replace("default, ", "").to_owned(),
-            payload_name // current_time_string().replace(":", "-"),
-        )
+        test_cases_dir()
+            .join(format!(
+                "optimizer_results/{}_{}.jsonl",
+                ACTIVE_FEATURE.replace("default, ", "").to_owned(),
+                payload_name // current_time_string().replace(":", "-"),
+            ))
+            .to_string_lossy()
+            .to_string()
     };
 
     let mut seen_tests: HashMap<(String, String, i64), f64> = HashMap::new();
@@ -106,13 +112,11 @@ pub fn run_tests(payload_path_string: String, is_verify: bool) {
     }
     // my_dbg!(&seen_tests);
 
-    let test_cases: Vec<(String, StateBundle)> = parse_to_state_bundles(Path::new(
-        &(if is_verify {
-            "../../test_cases/payloads".to_string()
-        } else {
-            payload_path_string.clone()
-        }),
-    ));
+    let test_cases: Vec<(String, StateBundle)> = parse_to_state_bundles(&if is_verify {
+        test_cases_dir().join("payloads")
+    } else {
+        payload_path.to_path_buf()
+    });
 
     if test_cases.len() == 0 {
         panic!(
@@ -309,10 +313,15 @@ pub fn run_tests(payload_path_string: String, is_verify: bool) {
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::constants::TEST_PAYLOAD_PATH;
 
     #[test]
     fn integration_test() {
-        run_tests(TEST_PAYLOAD_PATH.to_string(), true);
+        run_tests(
+            test_cases_dir()
+                .join("payloads")
+                .to_string_lossy()
+                .to_string(),
+            true,
+        );
     }
 }
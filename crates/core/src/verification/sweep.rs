@@ -0,0 +1,360 @@
+//! What-if sweeps: one base payload, a grid of overrides (prices, owned amounts, express event, special budget, tier),
+//! every combination evaluated (or optimized) in parallel, written out as one row per combination.
+//!
+//! Each axis of the grid is a list of alternatives and a combination picks one from every axis,
+//! so 3 juice prices x express on/off is 6 rows. Axes are applied in order so a later one wins if they touch the same thing.
+use crate::constants::MARKET_TAX;
+use crate::optimizer::solve;
+use crate::payload::Payload;
+use crate::performance::Performance;
+use crate::state_bundle::StateBundle;
+use crate::treatment::{DEFAULT_MATERIAL_SOURCES, solve_treatments};
+use rand::prelude::*;
+use rayon::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::fs;
+use std::path::Path;
+use std::time::Instant;
+
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub enum Override {
+    /// Market price, so the buy price of the market column and the tradable column (which sells for this minus MARKET_TAX).
+    /// Other columns (bound, shops and so on) keep their own prices
+    Price {
+        row: usize,
+        price: f64,
+    },
+    /// Same columns but relative, "juice costs 50 more"
+    PriceDelta {
+        row: usize,
+        delta: f64,
+    },
+    Owned {
+        row: usize,
+        column: usize,
+        amount: f64,
+    },
+    ExpressEvent(bool),
+    SpecialBudget(i64),
+    Tier(usize), // only works between tiers with the same material rows
+}
+
+impl fmt::Display for Override {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Override::Price { row, price } => write!(f, "price[{}]={}", row, price),
+            Override::PriceDelta { row, delta } => write!(f, "price[{}]{:+}", row, delta),
+            Override::Owned {
+                row,
+                column,
+                amount,
+            } => write!(f, "owned[{}][{}]={}", row, column, amount),
+            Override::ExpressEvent(on) => write!(f, "express={}", on),
+            Override::SpecialBudget(amount) => write!(f, "special={}", amount),
+            Override::Tier(tier) => write!(f, "tier={}", tier),
+        }
+    }
+}
+
+/// Columns whose price is the market price: the last one (what anything past the owned mats is bought at) and the tradable one
+fn market_columns(payload: &Payload) -> Vec<usize> {
+    let mut out: Vec<usize> = payload
+        .material_sources
+        .iter()
+        .position(|x| x == DEFAULT_MATERIAL_SOURCES[2])
+        .into_iter()
+        .collect();
+    let last = payload.material_sources.len().saturating_sub(1);
+    if !out.contains(&last) {
+        out.push(last);
+    }
+    out
+}
+
+impl Override {
+    pub fn apply(&self, payload: &mut Payload) -> Result<(), String> {
+        let num_rows = payload.material_info.len();
+        let check_row = |row: usize| {
+            if row < num_rows {
+                Ok(())
+            } else {
+                Err(format!(
+                    "{}: there are only {} material rows",
+                    self, num_rows
+                ))
+            }
+        };
+        match *self {
+            Override::Price { row, price } => {
+                check_row(row)?;
+                for column in market_columns(payload) {
+                    let Some(tier) = payload.material_info[row].get_mut(column) else {
+                        continue; // init_from_payload says what's wrong with the columns
+                    };
+                    tier.buy = price;
+                    if tier.sellable {
+                        tier.sell = price * (1.0 - MARKET_TAX);
+                    }
+                }
+            }
+            Override::PriceDelta { row, delta } => {
+                check_row(row)?;
+                for column in market_columns(payload) {
+                    let Some(tier) = payload.material_info[row].get_mut(column) else {
+                        continue; // init_from_payload says what's wrong with the columns
+                    };
+                    tier.buy = (tier.buy + delta).max(0.0);
+                    if tier.sellable {
+                        tier.sell = (tier.sell + delta * (1.0 - MARKET_TAX)).max(0.0);
+                    }
+                }
+            }
+            Override::Owned {
+                row,
+                column,
+                amount,
+            } => {
+                check_row(row)?;
+                let tier = payload.material_info[row]
+                    .get_mut(column)
+                    .ok_or_else(|| format!("{}: there's no column {}", self, column))?;
+                tier.owned = amount;
+            }
+            Override::ExpressEvent(on) => payload.express_event = on,
+            Override::SpecialBudget(amount) => payload.special_budget = amount,
+            Override::Tier(tier) => {
+                payload.tier = tier;
+                payload.data_name = None;
+                payload.resolve_tier().map_err(|e| e.to_string())?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct SweepAxis {
+    pub name: String, // column name in the output
+    pub values: Vec<Override>,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct SweepGrid {
+    pub axes: Vec<SweepAxis>,
+    pub optimize: bool, // otherwise the payload's own plan is evaluated as is
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct SweepRow {
+    pub values: Vec<String>, // the override picked from every axis
+    pub metric: f64,
+    pub plan_code: String,
+    pub wall_time: f64,
+    pub error: Option<String>,
+}
+
+impl SweepRow {
+    fn failed(values: Vec<String>, start: Instant, error: String) -> Self {
+        SweepRow {
+            values,
+            metric: f64::NAN,
+            plan_code: String::new(),
+            wall_time: start.elapsed().as_secs_f64(),
+            error: Some(error),
+        }
+    }
+}
+
+impl SweepGrid {
+    /// Every combination as one index per axis, the last axis changing fastest
+    pub fn combinations(&self) -> Vec<Vec<usize>> {
+        self.axes.iter().fold(vec![Vec::new()], |acc, axis| {
+            acc.iter()
+                .flat_map(|prefix| {
+                    (0..axis.values.len()).map(move |i| {
+                        let mut out = prefix.clone();
+                        out.push(i);
+                        out
+                    })
+                })
+                .collect()
+        })
+    }
+
+    fn run_one(&self, base: &Payload, picks: &[usize], seed: u64) -> SweepRow {
+        let start = Instant::now();
+        let values: Vec<String> = self
+            .axes
+            .iter()
+            .zip(picks)
+            .map(|(axis, &i)| axis.values[i].to_string())
+            .collect();
+        let mut payload: Payload = base.clone();
+        let applied: Result<(), String> = self
+            .axes
+            .iter()
+            .zip(picks)
+            .try_for_each(|(axis, &i)| axis.values[i].apply(&mut payload));
+        if let Err(e) = applied {
+            return SweepRow::failed(values, start, e);
+        }
+
+        let mut performance = Performance::new();
+        let choose_treatment = payload.choose_treatment;
+        let state_bundle = match StateBundle::init_from_payload(payload) {
+            Ok(x) => x,
+            Err(e) => return SweepRow::failed(values, start, e.to_string()),
+        };
+        let mut state_bundle = if !self.optimize || state_bundle.upgrade_arr.is_empty() {
+            state_bundle
+        } else {
+            let mut rng = StdRng::seed_from_u64(seed);
+            if choose_treatment {
+                solve_treatments(&mut rng, state_bundle, &mut performance).0
+            } else {
+                solve(&mut rng, state_bundle, &mut performance)
+            }
+        };
+        SweepRow {
+            values,
+            metric: state_bundle.metric_router(&mut performance),
+            plan_code: state_bundle.plan_code(None),
+            wall_time: start.elapsed().as_secs_f64(),
+            error: None,
+        }
+    }
+}
+
+/// One row per combination, in the order of combinations(), seeded by the row index so reruns give the same plans
+pub fn sweep(base: &Payload, grid: &SweepGrid) -> Vec<SweepRow> {
+    grid.combinations()
+        .par_iter()
+        .enumerate()
+        .map(|(index, picks)| grid.run_one(base, picks, index as u64))
+        .collect()
+}
+
+fn csv_field(field: &str) -> String {
+    if field.contains([',', '"', '\n']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_owned()
+    }
+}
+
+pub fn to_csv(grid: &SweepGrid, rows: &[SweepRow]) -> String {
+    let mut header: Vec<String> = grid.axes.iter().map(|x| csv_field(&x.name)).collect();
+    header.extend(["metric", "plan_code", "wall_time", "error"].map(str::to_owned));
+    let mut out = header.join(",");
+    out.push('\n');
+    for row in rows {
+        let mut fields: Vec<String> = row.values.iter().map(|x| csv_field(x)).collect();
+        fields.push(row.metric.to_string());
+        fields.push(csv_field(&row.plan_code));
+        fields.push(format!("{:.3}", row.wall_time));
+        fields.push(csv_field(row.error.as_deref().unwrap_or("")));
+        out.push_str(&fields.join(","));
+        out.push('\n');
+    }
+    out
+}
+
+/// Reads the base payload and the grid, writes out_prefix.csv and out_prefix.json
+pub fn run_sweep(payload_path: &Path, grid_path: &Path, out_prefix: &str) {
+    let base: Payload = Payload::from_json(
+        &fs::read_to_string(payload_path)
+            .unwrap_or_else(|e| panic!("Failed to read {:?}: {}", payload_path, e)),
+    )
+    .unwrap_or_else(|e| panic!("Failed to parse {:?}: {}", payload_path, e));
+    let grid: SweepGrid = serde_json::from_str(
+        &fs::read_to_string(grid_path)
+            .unwrap_or_else(|e| panic!("Failed to read {:?}: {}", grid_path, e)),
+    )
+    .unwrap_or_else(|e| panic!("Failed to parse {:?}: {}", grid_path, e));
+
+    let rows = sweep(&base, &grid);
+    fs::write(format!("{}.csv", out_prefix), to_csv(&grid, &rows)).expect("Failed to write csv");
+    fs::write(
+        format!("{}.json", out_prefix),
+        serde_json::to_string_pretty(&rows).unwrap(),
+    )
+    .expect("Failed to write json");
+    println!(
+        "{} combinations, {} failed, written to {}.csv / .json",
+        rows.len(),
+        rows.iter().filter(|x| x.error.is_some()).count(),
+        out_prefix
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::helpers::test_cases_dir;
+    use crate::payload::parse_to_payloads;
+
+    #[test]
+    fn evaluate_grid() {
+        let (_, payload) = parse_to_payloads(&test_cases_dir().join("payloads"))
+            .into_iter()
+            .find(|(name, _)| name == "2122")
+            .unwrap();
+        let grid = SweepGrid {
+            axes: vec![
+                SweepAxis {
+                    name: "red price".to_owned(),
+                    values: vec![
+                        Override::PriceDelta { row: 0, delta: 0.0 },
+                        Override::PriceDelta { row: 0, delta: 1.0 },
+                    ],
+                },
+                SweepAxis {
+                    name: "owned".to_owned(),
+                    values: vec![
+                        Override::ExpressEvent(false),
+                        Override::Owned {
+                            row: 99,
+                            column: 0,
+                            amount: 1.0,
+                        },
+                    ],
+                },
+            ],
+            optimize: false,
+        };
+        let rows = sweep(&payload, &grid);
+        assert_eq!(rows.len(), 4);
+        assert_eq!(rows[1].values, vec!["price[0]+0", "owned[99][0]=1"]);
+        assert!(rows[1].error.is_some() && rows[3].error.is_some());
+        // red costing more can't leave more gold
+        assert!(rows[2].metric < rows[0].metric);
+        assert!(!rows[0].plan_code.is_empty());
+
+        let csv = to_csv(&grid, &rows);
+        assert_eq!(csv.lines().count(), 5);
+        assert!(csv.starts_with("red price,owned,metric,plan_code,wall_time,error\n"));
+
+        // only the market and tradable columns follow the market price
+        let mut priced = payload.clone();
+        Override::Price { row: 0, price: 7.0 }
+            .apply(&mut priced)
+            .unwrap();
+        for (column, (before, after)) in payload.material_info[0]
+            .iter()
+            .zip(priced.material_info[0].iter())
+            .enumerate()
+        {
+            assert_eq!(after.buy == 7.0, column >= 2, "column {}", column);
+            if column < 2 {
+                assert_eq!(before, after);
+            }
+        }
+
+        // a payload that doesn't fit the data fails every row instead of taking the sweep down
+        let mut broken = payload;
+        broken.treatment_plans[0].plan.pop();
+        let rows = sweep(&broken, &grid);
+        assert!(rows.iter().all(|x| x.error.is_some() && x.metric.is_nan()));
+    }
+}
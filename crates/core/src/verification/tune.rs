@@ -0,0 +1,183 @@
+//! Random search + successive halving over AnnealParams, on a folder of payloads.
+//!
+//! Every config is scored by how much better (relative to |metric|) it does than the default params
+//! on the same payload with the same seed, so the payloads' very different scales don't matter and the noise mostly cancels out.
+//! Each rung keeps the best 1/ETA of the configs and gives them ETA times as many seeds.
+use crate::optimizer::{AnnealParams, solve_with_params};
+use crate::payload::parse_to_state_bundles;
+use crate::performance::Performance;
+use crate::state_bundle::StateBundle;
+use rand::prelude::*;
+use rayon::prelude::*;
+use std::collections::HashMap;
+use std::path::Path;
+
+const ETA: usize = 3;
+const Z_95: f64 = 1.96;
+
+fn random_params<R: Rng>(rng: &mut R) -> AnnealParams {
+    let warm_up_phase_end = rng.random_range(0.05..0.3);
+    AnnealParams {
+        magic_number: rng.random_range(0.2..0.7),
+        warm_up_phase_end,
+        cooling_phase_start: rng.random_range((warm_up_phase_end + 0.1)..0.9),
+        special_affinity_decay: rng.random_range(0.99..0.9999),
+        special_affinity_growth: rng.random_range(1.0..1.1),
+        non_impact_weight: rng.random_range(0.5..6.0),
+        scaler_batch_size: rng.random_range(20..200),
+        scaler_learning_rate: rng.random_range(0.02..0.3),
+    }
+}
+
+/// (mean, half width of the 95% interval) of the relative improvement over the default
+#[derive(Debug, Clone, Copy)]
+pub struct TuneScore {
+    pub mean: f64,
+    pub half_width: f64,
+    pub samples: usize,
+}
+
+fn score(
+    results: &HashMap<(usize, usize, u64), f64>,
+    config: usize,
+    num_cases: usize,
+    num_seeds: u64,
+) -> TuneScore {
+    let rel: Vec<f64> = (0..num_cases)
+        .flat_map(|case| (0..num_seeds).map(move |seed| (case, seed)))
+        .map(|(case, seed)| {
+            let base = results[&(0, case, seed)];
+            (results[&(config, case, seed)] - base) / base.abs().max(1.0)
+        })
+        .collect();
+    let n = rel.len() as f64;
+    let mean = rel.iter().sum::<f64>() / n;
+    let var = if rel.len() > 1 {
+        rel.iter().map(|x| (x - mean).powi(2)).sum::<f64>() / (n - 1.0)
+    } else {
+        0.0
+    };
+    TuneScore {
+        mean,
+        half_width: Z_95 * (var / n).sqrt(),
+        samples: rel.len(),
+    }
+}
+
+/// Returns the best params found and how it did against the default
+pub fn tune(payload_path: &Path, num_configs: usize, seed: u64) -> (AnnealParams, TuneScore) {
+    let test_cases: Vec<(String, StateBundle)> = parse_to_state_bundles(payload_path);
+    assert!(
+        !test_cases.is_empty(),
+        "No payload jsons found in {:?}",
+        payload_path
+    );
+    let mut rng = StdRng::seed_from_u64(seed);
+    let configs: Vec<AnnealParams> = std::iter::once(AnnealParams::default())
+        .chain((1..num_configs.max(2)).map(|_| random_params(&mut rng)))
+        .collect();
+
+    let (best, best_score) =
+        successive_halving(configs.len(), test_cases.len(), |config, case, s| {
+            let mut rng = StdRng::seed_from_u64(seed ^ (s + 1).wrapping_mul(0x9E3779B97F4A7C15));
+            solve_with_params(
+                &mut rng,
+                test_cases[case].1.clone(),
+                &mut Performance::new(),
+                configs[config],
+            )
+            .metric
+        });
+    println!("Best is config {}: {:?}", best, configs[best]);
+    (configs[best], best_score)
+}
+
+/// Config 0 is the default and the reference, the rest race. evaluate(config, test case, seed) -> metric, higher is better
+fn successive_halving<F: Fn(usize, usize, u64) -> f64 + Sync>(
+    num_configs: usize,
+    num_cases: usize,
+    evaluate: F,
+) -> (usize, TuneScore) {
+    let mut results: HashMap<(usize, usize, u64), f64> = HashMap::new(); // (config, test case, seed) -> metric
+    let mut alive: Vec<usize> = (1..num_configs).collect(); // the default (0) is always run, it's the reference
+    let mut num_seeds: u64 = 1;
+    loop {
+        let jobs: Vec<(usize, usize, u64)> = std::iter::once(0)
+            .chain(alive.iter().copied())
+            .flat_map(|config| {
+                (0..num_cases).flat_map(move |case| (0..num_seeds).map(move |s| (config, case, s)))
+            })
+            .filter(|key| !results.contains_key(key))
+            .collect();
+        let new_results: Vec<((usize, usize, u64), f64)> = jobs
+            .par_iter()
+            .map(|&(config, case, s)| ((config, case, s), evaluate(config, case, s)))
+            .collect();
+        results.extend(new_results);
+
+        let mut scored: Vec<(usize, TuneScore)> = alive
+            .iter()
+            .map(|&c| (c, score(&results, c, num_cases, num_seeds)))
+            .collect();
+        scored.sort_by(|a, b| b.1.mean.total_cmp(&a.1.mean));
+        println!(
+            "Rung with {} seed(s) per payload, {} config(s) left",
+            num_seeds,
+            alive.len()
+        );
+        for (c, s) in scored.iter().take(5) {
+            println!(
+                "  config {:>3}: {:+.5}% +-{:.5}%",
+                c,
+                s.mean * 100.0,
+                s.half_width * 100.0,
+            );
+        }
+
+        if alive.len() == 1 {
+            let (best, best_score) = scored[0];
+            if best_score.mean - best_score.half_width <= 0.0 {
+                println!("Config {} isn't clearly better than the default", best);
+            }
+            return (best, best_score);
+        }
+        alive = scored
+            .iter()
+            .take(alive.len().div_ceil(ETA))
+            .map(|x| x.0)
+            .collect();
+        num_seeds *= ETA as u64;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Mutex;
+
+    #[test]
+    fn halving_keeps_the_best_and_gives_it_more_seeds() {
+        let calls: Mutex<Vec<(usize, usize, u64)>> = Mutex::new(Vec::new());
+        // higher configs are better, except 3 which only gets lucky on seed 0
+        let (best, best_score) = successive_halving(10, 2, |config, case, s| {
+            calls.lock().unwrap().push((config, case, s));
+            let skill = match (config, s) {
+                (3, 0) => 20.0,
+                (3, _) => 0.0,
+                _ => config as f64,
+            };
+            100.0 * (case + 1) as f64 + skill
+        });
+        assert_eq!(best, 9);
+        assert_eq!(best_score.samples, 2 * 9);
+
+        let calls = calls.into_inner().unwrap();
+        let mut unique = calls.clone();
+        unique.sort();
+        unique.dedup();
+        assert_eq!(unique.len(), calls.len()); // nothing evaluated twice
+        // 9 configs x 1 seed, then the top 3 (3, 9, 8) x 3 seeds, then 9 x 9 seeds, each over 2 cases + the default
+        assert_eq!(calls.len(), (10 + 2 * 4 + 6 * 2) * 2);
+        assert!(!calls.iter().any(|&(c, _, s)| c == 3 && s >= 3));
+    }
+}
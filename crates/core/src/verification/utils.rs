@@ -1,7 +1,13 @@
+use crate::helpers::encode_one_positions;
+use crate::market_depth;
 use crate::state_bundle::StateBundle;
 
-pub fn apply_prices(used: f64, thresh_price_pairs: &[(f64, f64)]) -> f64 {
-    let mut out = 0.0;
+/// Gold left after using `used`, with the pairs from distribute_budgets: whatever's left of a segment is worth that
+/// segment's price (the sell price of its column), anything past the last threshold is bought at the last price
+/// (going up with market depth)
+pub fn apply_prices(used: f64, thresh_price_pairs: &[(f64, f64)], depth: &[(f64, f64)]) -> f64 {
+    let (last_thresh, last_price) = *thresh_price_pairs.last().unwrap();
+    let mut out = -market_depth::extra_cost(used - last_thresh, last_price, depth);
 
     for (index, &(thresh, price)) in thresh_price_pairs.iter().enumerate() {
         if index + 1 < thresh_price_pairs.len() {
@@ -20,23 +26,6 @@ pub fn apply_prices(used: f64, thresh_price_pairs: &[(f64, f64)]) -> f64 {
 
     out
 }
-pub fn encode_one_positions(v1: &[(bool, usize)]) -> String {
-    v1.iter()
-        .map(|(uppercase, num)| {
-            let letter: char = if *num == 0 {
-                'x'
-            } else {
-                (b'a' + (*num as u8 - 1)) as char
-            };
-
-            if *uppercase {
-                letter.to_ascii_uppercase()
-            } else {
-                letter
-            }
-        })
-        .collect()
-}
 
 impl StateBundle {
     pub fn encode_all(&self) -> String {
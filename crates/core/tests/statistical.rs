@@ -0,0 +1,288 @@
+//! Checks every payload in test_cases/payloads against monte carlo:
+//! the average gold metric, compute_leftover_probs and the histogram's cdf (one_dimension_prob).
+//!
+//! The samples come from verification's monte_carlo_data, which samples taps, juice and special leaps from scratch,
+//! not from Simulator (that one reads the same cost_dist as the code under test, so it would agree with a bug there).
+//! Needs the verification module: cargo test -p hf-core --features run_tests,v35 --test statistical
+//!
+//! Everything that gets compared ends up in a jsonl report (HF_VERIFY_REPORT, defaults to cargo's tmp dir)
+//! so a regression can be looked at without rerunning. HF_VERIFY_SAMPLES overrides the sample count.
+use hf_core::constants::{FLOAT_TOL, MONTE_CARLO_CONFIDENCE};
+use hf_core::helpers::test_cases_dir;
+use hf_core::payload::parse_to_payloads;
+use hf_core::performance::Performance;
+use hf_core::state_bundle::StateBundle;
+use hf_core::verification::monte_carlo::monte_carlo_data;
+use hf_core::verification::utils::apply_prices;
+use rand::SeedableRng;
+use rand::rngs::StdRng;
+use serde::Serialize;
+use statrs::distribution::{ContinuousCDF, Normal};
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::PathBuf;
+
+const DEFAULT_SAMPLES: usize = 20_000;
+// Exact answers (brute force / trivial) and the average metric only get the sampling error.
+// Probabilities that went through the saddlepoint approximation are off by up to 0.0225 in absolute terms:
+// measured with HF_VERIFY_SAMPLES=200000, worst is 1920_adv3040 row 0 mid-distribution, everything else is under 0.008.
+const SADDLEPOINT_PROB_TOL: f64 = 0.025;
+const CDF_POINTS: usize = 5; // per material, evenly spaced between luckiest and pity like the histogram
+const SEED: u64 = 6942067;
+
+struct VerifyConfig {
+    num_samples: usize,
+    report_path: PathBuf,
+}
+
+impl VerifyConfig {
+    fn from_env() -> Self {
+        Self {
+            num_samples: std::env::var("HF_VERIFY_SAMPLES")
+                .ok()
+                .and_then(|x| x.parse().ok())
+                .unwrap_or(DEFAULT_SAMPLES),
+            report_path: std::env::var("HF_VERIFY_REPORT")
+                .map(PathBuf::from)
+                .unwrap_or_else(|_| {
+                    PathBuf::from(env!("CARGO_TARGET_TMPDIR")).join("statistical_report.jsonl")
+                }),
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+struct Check {
+    test_case: String,
+    check: &'static str, // average, leftover, cdf
+    row: Option<usize>,
+    budget: Option<f64>,
+    analytic: f64,
+    monte_carlo: f64,
+    samples: usize,
+    std_err: f64,
+    saddlepoint: bool, // false if the analytic answer is exact (brute force or trivial)
+    slack: f64,
+    low: f64, // the analytic answer has to be in [low, high]
+    high: f64,
+    passed: bool,
+}
+
+/// Wilson score interval, unlike mean +- z * std_err it still works when only a handful of samples land in the tail
+fn wilson(p: f64, n: f64, z: f64) -> (f64, f64) {
+    let z2n = z * z / n;
+    let center = (p + z2n / 2.0) / (1.0 + z2n);
+    let half_width = z * (p * (1.0 - p) / n + z2n / (4.0 * n)).sqrt() / (1.0 + z2n);
+    (center - half_width, center + half_width)
+}
+
+impl Check {
+    /// std_err is the binomial one, the average has to set its own
+    fn new(
+        test_case: &str,
+        check: &'static str,
+        at: Option<(usize, f64)>, // (row, budget)
+        analytic: f64,
+        monte_carlo: f64,
+        samples: usize,
+        saddlepoint: bool,
+    ) -> Self {
+        Self {
+            test_case: test_case.to_string(),
+            check,
+            row: at.map(|x| x.0),
+            budget: at.map(|x| x.1),
+            analytic,
+            monte_carlo,
+            samples,
+            std_err: (monte_carlo * (1.0 - monte_carlo) / samples as f64).sqrt(),
+            saddlepoint,
+            slack: if saddlepoint && check != "average" {
+                SADDLEPOINT_PROB_TOL
+            } else {
+                0.0
+            },
+            low: f64::NAN,
+            high: f64::NAN,
+            passed: false,
+        }
+    }
+
+    fn judge(&mut self, z: f64) {
+        let (low, high) = if self.check == "average" {
+            (
+                self.monte_carlo - z * self.std_err,
+                self.monte_carlo + z * self.std_err,
+            )
+        } else {
+            wilson(self.monte_carlo, self.samples as f64, z)
+        };
+        let tol = self.slack + FLOAT_TOL * self.analytic.abs().max(1.0);
+        self.low = low - tol;
+        self.high = high + tol;
+        self.passed = self.low <= self.analytic && self.analytic <= self.high;
+    }
+}
+
+fn verify_one(name: &str, state_bundle: &mut StateBundle, num_samples: usize) -> Vec<Check> {
+    let mut performance = Performance::new();
+    let metric = state_bundle.optimizer_average_gold_metric(&mut performance);
+    let metric_saddlepoint = performance.sa_count > 0;
+    let leftover = state_bundle.compute_leftover_probs(); // [treatment][row]
+    state_bundle.set_latest_special_probs();
+    let luckiest = state_bundle.luckiest_mf();
+    let pity = state_bundle.pity();
+
+    let num_rows = state_bundle.prep_output.juice_info.total_num_avail;
+    let cdf_budgets: Vec<Vec<f64>> = (0..num_rows)
+        .map(|row| {
+            if pity[row] <= luckiest[row] {
+                return Vec::new();
+            }
+            (1..CDF_POINTS + 1)
+                .map(|i| {
+                    luckiest[row] as f64
+                        + i as f64 * (pity[row] - luckiest[row]) as f64 / (CDF_POINTS + 1) as f64
+                })
+                .collect()
+        })
+        .collect();
+    let leftover_budgets: Vec<Vec<f64>> = state_bundle
+        .prep_output
+        .raw_material_info
+        .iter()
+        .map(|row| row.iter().map(|x| x.owned).collect())
+        .collect();
+
+    let n = num_samples as f64;
+    let mut gold_sum: f64 = 0.0;
+    let mut gold_sq_sum: f64 = 0.0;
+    let mut cdf_counts: Vec<Vec<usize>> = cdf_budgets.iter().map(|x| vec![0; x.len()]).collect();
+    let mut leftover_counts: Vec<Vec<usize>> =
+        leftover_budgets.iter().map(|x| vec![0; x.len()]).collect();
+    let (cost_data, _) =
+        monte_carlo_data(num_samples, state_bundle, &mut StdRng::seed_from_u64(SEED));
+    for spent in cost_data.iter() {
+        let gold: f64 = spent
+            .iter()
+            .enumerate()
+            .map(|(row, x)| {
+                apply_prices(
+                    *x as f64,
+                    &state_bundle.prep_output.optimizer_material_info[row],
+                    &state_bundle.prep_output.market_depth[row],
+                )
+            })
+            .sum();
+        gold_sum += gold;
+        gold_sq_sum += gold * gold;
+        for (row, &x) in spent.iter().enumerate() {
+            let x = x as f64;
+            for (count, budget) in cdf_counts[row].iter_mut().zip(cdf_budgets[row].iter()) {
+                *count += (x <= *budget) as usize;
+            }
+            for (count, budget) in leftover_counts[row]
+                .iter_mut()
+                .zip(leftover_budgets[row].iter())
+            {
+                *count += (x <= *budget) as usize;
+            }
+        }
+    }
+
+    let mut out: Vec<Check> = Vec::new();
+    let mean = gold_sum / n;
+    out.push(Check {
+        std_err: ((gold_sq_sum / n - mean * mean).max(0.0) / n).sqrt(),
+        ..Check::new(
+            name,
+            "average",
+            None,
+            metric,
+            mean,
+            num_samples,
+            metric_saddlepoint,
+        )
+    });
+
+    let prob_check = |check: &'static str,
+                      row: usize,
+                      budget: f64,
+                      analytic: f64,
+                      count: usize,
+                      saddlepoint: bool| {
+        Check::new(
+            name,
+            check,
+            Some((row, budget)),
+            analytic,
+            count as f64 / n,
+            num_samples,
+            saddlepoint,
+        )
+    };
+    let used_saddlepoint = |row: usize, budget: f64| {
+        let mut performance = Performance::new();
+        let prob = state_bundle.one_dimension_prob(row as i64, budget, &mut performance);
+        (prob, performance.sa_count > 0)
+    };
+    for row in 0..num_rows {
+        for (treatment, (&budget, &count)) in leftover_budgets[row]
+            .iter()
+            .zip(leftover_counts[row].iter())
+            .enumerate()
+        {
+            let (_, saddlepoint) = used_saddlepoint(row, budget);
+            out.push(prob_check(
+                "leftover",
+                row,
+                budget,
+                leftover[treatment][row],
+                count,
+                saddlepoint,
+            ));
+        }
+        for (&budget, &count) in cdf_budgets[row].iter().zip(cdf_counts[row].iter()) {
+            let (analytic, saddlepoint) = used_saddlepoint(row, budget);
+            out.push(prob_check("cdf", row, budget, analytic, count, saddlepoint));
+        }
+    }
+    out
+}
+
+#[test]
+fn analytic_matches_monte_carlo() {
+    let config = VerifyConfig::from_env();
+    let payloads = parse_to_payloads(&test_cases_dir().join("payloads"));
+    assert!(!payloads.is_empty(), "No payloads found");
+
+    let mut checks: Vec<Check> = Vec::new();
+    for (name, payload) in payloads {
+        let mut state_bundle = StateBundle::init_from_payload(payload).unwrap();
+        checks.extend(verify_one(&name, &mut state_bundle, config.num_samples));
+    }
+    // MONTE_CARLO_CONFIDENCE is for the whole suite, so every check gets its share of the false alarm rate (Bonferroni)
+    let alpha = (1.0 - MONTE_CARLO_CONFIDENCE) / checks.len() as f64;
+    let z = Normal::new(0.0, 1.0)
+        .unwrap()
+        .inverse_cdf(1.0 - alpha / 2.0);
+    for check in checks.iter_mut() {
+        check.judge(z);
+    }
+
+    let mut writer = BufWriter::new(File::create(&config.report_path).unwrap());
+    for check in checks.iter() {
+        writeln!(writer, "{}", serde_json::to_string(check).unwrap()).unwrap();
+    }
+    writer.flush().unwrap();
+
+    let failed: Vec<&Check> = checks.iter().filter(|x| !x.passed).collect();
+    assert!(
+        failed.is_empty(),
+        "{} of {} checks failed (report at {:?}):\n{:#?}",
+        failed.len(),
+        checks.len(),
+        config.report_path,
+        failed
+    );
+}
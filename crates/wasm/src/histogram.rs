@@ -1,3 +1,4 @@
+use hf_core::attribution::UpgradeAttribution;
 use hf_core::constants::juice_info::JuiceInfo;
 use hf_core::constants::*;
 use hf_core::js_interface::remove_adv_cache;
@@ -14,11 +15,12 @@ pub struct HistogramOutputs {
     metrics_arr: Vec<f64>,
 
     avg_breakdown: Vec<f64>,
+    upgrade_breakdown: Option<Vec<UpgradeAttribution>>, // per upgrade, the treatment plan vecs line up with metrics_arr. None unless the payload asks for it
     juice_info: JuiceInfo,
     state_bundle: StateBundle,
 }
 
-pub fn histogram(state_bundle: &mut StateBundle) -> HistogramOutputs {
+pub fn histogram(state_bundle: &mut StateBundle, upgrade_attribution: bool) -> HistogramOutputs {
     state_bundle.update_prob_dist();
     state_bundle.update_cost_dist();
     state_bundle.compute_special_probs(false);
@@ -53,7 +55,7 @@ pub fn histogram(state_bundle: &mut StateBundle) -> HistogramOutputs {
         let mut cumulative: f64 = 0.0;
         for treatment_plan in 0..state_bundle.prep_output.raw_num_breakpoints {
             cumulative +=
-                state_bundle.prep_output.raw_material_info[support_index][treatment_plan].0;
+                state_bundle.prep_output.raw_material_info[support_index][treatment_plan].owned;
             chances_arr[treatment_plan].push(state_bundle.one_dimension_prob(
                 support_index as i64,
                 cumulative,
@@ -62,13 +64,17 @@ pub fn histogram(state_bundle: &mut StateBundle) -> HistogramOutputs {
         }
     }
 
+    let treatment_arr = state_bundle.prep_output.treatment_arr();
     let (metrics_arr, avg_breakdown, gold_breakdown_arr) =
-        state_bundle.ui_average_gold_metric(Some(&UI_TREATMENTS.to_vec()), &mut dummy_performance);
+        state_bundle.ui_average_gold_metric(Some(&treatment_arr), &mut dummy_performance);
+    let upgrade_breakdown = upgrade_attribution
+        .then(|| state_bundle.upgrade_attribution(Some(&treatment_arr), &mut dummy_performance));
     // state_bundle.average_gold_metric(true, &mut Performance::new());
     HistogramOutputs {
         cum_percentiles,
         chances_arr,
         avg_breakdown,
+        upgrade_breakdown,
         gold_breakdown_arr,
         metrics_arr,
         juice_info: state_bundle.prep_output.juice_info.clone(),
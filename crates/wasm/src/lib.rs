@@ -7,11 +7,24 @@
 mod histogram;
 use crate::histogram::HistogramOutputs;
 use crate::histogram::histogram;
+use hf_core::constants::registry::{list_data, register_data_bytes};
+use hf_core::diff::diff;
+use hf_core::goal::{GoalPayload, solve_goal};
+use hf_core::instructions::Instructions;
+use hf_core::migration::migrate_payload;
 use hf_core::optimizer::solve;
 use hf_core::payload::Payload;
 use hf_core::performance::Performance;
+use hf_core::plan_code::encode_plan;
+use hf_core::roster::{RosterPayload, RosterResult, solve_roster};
+use hf_core::session::{HoningSession, Tap};
+use hf_core::simulation::{SimOutcome, simulate};
+use hf_core::stages::{OrderComparison, StagePayload, solve_orders};
 use hf_core::state_bundle::StateBundle;
+use hf_core::treatment::solve_treatments;
 use rand::rngs::ThreadRng;
+use serde::de::DeserializeOwned;
+use serde_json::Value;
 use serde_wasm_bindgen::{from_value, to_value};
 use wasm_bindgen::JsValue;
 use wasm_bindgen::prelude::*;
@@ -19,6 +32,26 @@ use wasm_bindgen::prelude::*;
 #[allow(unused_imports)]
 use web_sys::console;
 
+/// Errors go back to js as plain strings, the worker passes them on as is
+fn js_error(e: impl std::fmt::Display) -> JsValue {
+    JsValue::from_str(&e.to_string())
+}
+
+/// Payloads can come from saved state in the browser, so they go through migration like files do
+fn payload_from_js(input_payload: JsValue) -> Result<Payload, JsValue> {
+    let value: Value = from_value(input_payload).map_err(js_error)?;
+    Payload::from_json_value(value).map_err(js_error)
+}
+
+/// Same for inputs that carry a payload in their "payload" field (goals, stages, sessions)
+fn with_payload_from_js<T: DeserializeOwned>(input: JsValue) -> Result<T, JsValue> {
+    let mut value: Value = from_value(input).map_err(js_error)?;
+    if let Some(payload) = value.get_mut("payload") {
+        *payload = migrate_payload(payload.take()).map_err(js_error)?;
+    }
+    serde_json::from_value(value).map_err(js_error)
+}
+
 // #[wasm_bindgen]
 // #[must_use]
 // pub fn parser_wrapper(input_payload: JsValue) -> JsValue {
@@ -49,33 +82,227 @@ use web_sys::console;
 // }
 
 #[wasm_bindgen]
-#[must_use]
-pub fn optimize_average_wrapper(input_payload: JsValue) -> JsValue {
+pub fn optimize_average_wrapper(input_payload: JsValue) -> Result<JsValue, JsValue> {
     console_error_panic_hook::set_once();
     // let json_string = js_sys::JSON::stringify(&input_state_bundle).unwrap();
     // let json_str: String = json_string.into();
     // let result: Result<StateBundle, _> = serde_json::from_str(&json_str);
     // let state_bundle: StateBundle = result.unwrap();
-    let payload: Payload = from_value(input_payload).unwrap();
-    let state_bundle: StateBundle = StateBundle::init_from_payload(payload);
+    let payload: Payload = payload_from_js(input_payload)?;
+    let choose_treatment = payload.choose_treatment;
+    let state_bundle: StateBundle = StateBundle::init_from_payload(payload).map_err(js_error)?;
 
     let mut rng: ThreadRng = rand::rng();
     let mut dummy_performance = Performance::new();
-    let mut best_state: StateBundle = solve(&mut rng, state_bundle, &mut dummy_performance);
+    let mut best_state: StateBundle = if choose_treatment {
+        solve_treatments(&mut rng, state_bundle, &mut dummy_performance).0
+    } else {
+        solve(&mut rng, state_bundle, &mut dummy_performance)
+    };
 
     best_state.optimizer_average_gold_metric(&mut dummy_performance);
     best_state.set_latest_special_probs();
 
-    to_value(&best_state).unwrap()
+    Ok(to_value(&best_state).unwrap())
+}
+
+#[wasm_bindgen]
+pub fn histogram_wrapper(input_payload: JsValue) -> Result<JsValue, JsValue> {
+    console_error_panic_hook::set_once();
+
+    let payload: Payload = payload_from_js(input_payload)?;
+    let upgrade_attribution = payload.upgrade_attribution;
+    let mut state_bundle: StateBundle =
+        StateBundle::init_from_payload(payload).map_err(js_error)?;
+    let out: HistogramOutputs = histogram(&mut state_bundle, upgrade_attribution);
+    Ok(to_value(&out).unwrap())
+}
+
+#[wasm_bindgen]
+pub fn optimize_roster_wrapper(input_roster_payload: JsValue) -> Result<JsValue, JsValue> {
+    console_error_panic_hook::set_once();
+
+    let roster_payload: RosterPayload = from_value(input_roster_payload).map_err(js_error)?;
+    let mut rng: ThreadRng = rand::rng();
+    let mut dummy_performance = Performance::new();
+    let mut result: RosterResult =
+        solve_roster(&mut rng, &roster_payload, &mut dummy_performance).map_err(js_error)?;
+
+    for state_bundle in result.state_bundles.iter_mut() {
+        state_bundle.set_latest_special_probs();
+        state_bundle.adv_cache.clear();
+    }
+    Ok(to_value(&result).unwrap())
+}
+
+/// Picks and optimizes the upgrades for a goal, errors if the goal makes no sense or can't be reached
+#[wasm_bindgen]
+pub fn optimize_goal_wrapper(input_goal_payload: JsValue) -> Result<JsValue, JsValue> {
+    console_error_panic_hook::set_once();
+
+    let goal_payload: GoalPayload = with_payload_from_js(input_goal_payload)?;
+    let mut rng: ThreadRng = rand::rng();
+    let mut dummy_performance = Performance::new();
+    match solve_goal(&mut rng, &goal_payload, &mut dummy_performance) {
+        Ok(mut plan) => {
+            plan.state_bundle.set_latest_special_probs();
+            plan.state_bundle.adv_cache.clear();
+            Ok(to_value(&plan).unwrap())
+        }
+        Err(e) => Err(js_error(e)),
+    }
+}
+
+/// Compares doing the upgrades in different orders (e.g. advanced honing first), optionally replanning between stages
+#[wasm_bindgen]
+pub fn optimize_order_wrapper(input_stage_payload: JsValue) -> Result<JsValue, JsValue> {
+    console_error_panic_hook::set_once();
+
+    let stage_payload: StagePayload = with_payload_from_js(input_stage_payload)?;
+    let mut rng: ThreadRng = rand::rng();
+    let mut dummy_performance = Performance::new();
+    let mut result: OrderComparison =
+        solve_orders(&mut rng, &stage_payload, &mut dummy_performance).map_err(js_error)?;
+
+    for state_bundle in result.plans.iter_mut().flat_map(|x| x.stages.iter_mut()) {
+        state_bundle.set_latest_special_probs();
+        state_bundle.adv_cache.clear();
+    }
+    Ok(to_value(&result).unwrap())
+}
+
+/// What changed between two payloads (with their plans) and how much gold each change accounts for
+#[wasm_bindgen]
+pub fn diff_wrapper(
+    input_payload_a: JsValue,
+    input_payload_b: JsValue,
+) -> Result<JsValue, JsValue> {
+    console_error_panic_hook::set_once();
+
+    let a: StateBundle =
+        StateBundle::init_from_payload(payload_from_js(input_payload_a)?).map_err(js_error)?;
+    let b: StateBundle =
+        StateBundle::init_from_payload(payload_from_js(input_payload_b)?).map_err(js_error)?;
+    let mut dummy_performance = Performance::new();
+    Ok(to_value(&diff(&a, &b, &mut dummy_performance)).unwrap())
+}
+
+/// Registers a data file fetched by the frontend, returns its tier or why it was rejected
+#[wasm_bindgen]
+pub fn register_data_wrapper(name: String, contents: String) -> Result<JsValue, JsValue> {
+    console_error_panic_hook::set_once();
+
+    match register_data_bytes(&name, contents.as_bytes()) {
+        Ok(tier) => Ok(to_value(&tier).unwrap()),
+        Err(e) => Err(js_error(e)),
+    }
 }
 
 #[wasm_bindgen]
 #[must_use]
-pub fn histogram_wrapper(input_payload: JsValue) -> JsValue {
+pub fn list_data_wrapper() -> JsValue {
+    console_error_panic_hook::set_once();
+    to_value(&list_data()).unwrap()
+}
+
+/// Starts a session from a payload, the returned session is what the frontend should persist
+#[wasm_bindgen]
+pub fn session_start_wrapper(input_payload: JsValue) -> Result<JsValue, JsValue> {
+    console_error_panic_hook::set_once();
+
+    let payload: Payload = payload_from_js(input_payload)?;
+    Ok(to_value(&HoningSession::new(payload)).unwrap())
+}
+
+/// Returns the updated session, or why the tap couldn't be recorded
+#[wasm_bindgen]
+pub fn session_tap_wrapper(input_session: JsValue, input_tap: JsValue) -> Result<JsValue, JsValue> {
+    console_error_panic_hook::set_once();
+
+    let mut session: HoningSession = with_payload_from_js(input_session)?;
+    let tap: Tap = from_value(input_tap).map_err(js_error)?;
+    match session.record_tap(tap) {
+        Ok(_) => Ok(to_value(&session).unwrap()),
+        Err(e) => Err(js_error(e)),
+    }
+}
+
+/// Re-optimizes what's left of the session, returns [session, state_bundle]
+#[wasm_bindgen]
+pub fn session_recommend_wrapper(input_session: JsValue) -> Result<JsValue, JsValue> {
+    console_error_panic_hook::set_once();
+
+    let mut session: HoningSession = with_payload_from_js(input_session)?;
+    let mut rng: ThreadRng = rand::rng();
+    let mut dummy_performance = Performance::new();
+    let mut state_bundle: StateBundle = session
+        .recommend(&mut rng, &mut dummy_performance)
+        .map_err(js_error)?;
+    state_bundle.metric = state_bundle.metric_router(&mut dummy_performance);
+    state_bundle.adv_cache.clear();
+    Ok(to_value(&(session, state_bundle)).unwrap())
+}
+
+/// num_samples simulated honing sessions of the payload as it is (no optimizing), e.g. for "simulate 10 sessions"
+#[wasm_bindgen]
+pub fn simulate_wrapper(
+    input_payload: JsValue,
+    seed: u32,
+    num_samples: usize,
+) -> Result<JsValue, JsValue> {
+    console_error_panic_hook::set_once();
+
+    let payload: Payload = payload_from_js(input_payload)?;
+    let state_bundle: StateBundle = StateBundle::init_from_payload(payload).map_err(js_error)?;
+    let out: Vec<SimOutcome> = simulate(&state_bundle, seed as u64, num_samples).collect();
+    Ok(to_value(&out).unwrap())
+}
+
+/// Shareable code for the plan in the payload (upgrade_info states + special_state), with the inputs too if include_payload
+#[wasm_bindgen]
+pub fn plan_code_wrapper(input_payload: JsValue, include_payload: bool) -> Result<String, JsValue> {
+    console_error_panic_hook::set_once();
+
+    let payload: Payload = payload_from_js(input_payload)?;
+    let state_bundle: StateBundle =
+        StateBundle::init_from_payload(payload.clone()).map_err(js_error)?;
+    Ok(encode_plan(
+        &state_bundle.to_essence(),
+        include_payload.then_some(&payload),
+    ))
+}
+
+/// Loads a shared plan, input_payload (can be null) is only used if the code didn't include one
+#[wasm_bindgen]
+pub fn load_plan_code_wrapper(code: String, input_payload: JsValue) -> Result<JsValue, JsValue> {
+    console_error_panic_hook::set_once();
+
+    let payload: Option<Payload> = if input_payload.is_null() || input_payload.is_undefined() {
+        None
+    } else {
+        Some(payload_from_js(input_payload)?)
+    };
+    match StateBundle::from_plan_code(&code, payload) {
+        Ok(mut state_bundle) => {
+            let mut dummy_performance = Performance::new();
+            state_bundle.metric = state_bundle.metric_router(&mut dummy_performance);
+            state_bundle.set_latest_special_probs();
+            state_bundle.adv_cache.clear();
+            Ok(to_value(&state_bundle).unwrap())
+        }
+        Err(e) => Err(js_error(e)),
+    }
+}
+
+/// Step by step instructions for the plan in the payload, returns [structured, one line of text per step]
+#[wasm_bindgen]
+pub fn instructions_wrapper(input_payload: JsValue) -> Result<JsValue, JsValue> {
     console_error_panic_hook::set_once();
 
-    let payload: Payload = from_value(input_payload).unwrap();
-    let mut state_bundle: StateBundle = StateBundle::init_from_payload(payload);
-    let out: HistogramOutputs = histogram(&mut state_bundle);
-    to_value(&out).unwrap()
+    let payload: Payload = payload_from_js(input_payload)?;
+    let mut state_bundle: StateBundle =
+        StateBundle::init_from_payload(payload).map_err(js_error)?;
+    let instructions: Instructions = state_bundle.instructions();
+    let lines: Vec<String> = instructions.to_lines();
+    Ok(to_value(&(instructions, lines)).unwrap())
 }
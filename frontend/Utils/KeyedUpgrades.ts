@@ -58,8 +58,13 @@ export type StatusGrid = UpgradeStatus[][];
 
 //                    'bound','tradable', leftover(bound), tradable sell price, market price
 
-export type BudgetPricePair = [number, number];
-export type OneMaterialInput = BudgetPricePair[]; // an array of this is passed into rust
+export interface MaterialTier {
+  owned: number;
+  buy: number;
+  sell: number; // what a leftover is worth, after tax
+  sellable: boolean; // bound leftovers are worth nothing
+}
+export type OneMaterialInput = MaterialTier[]; // an array of this is passed into rust
 
 //                        piece type, upgrade index, is_normal_honing, normal_progress, state, unlocked, succeeded, adv_progress
 export type OldOneUpgrade = [
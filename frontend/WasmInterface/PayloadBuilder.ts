@@ -21,8 +21,12 @@ import { storeToRefs } from "pinia";
 // I don't think it's possible to directly export this struct from rust to javascript because of all the vectors,
 // so it's copied & pasted here
 export interface Payload {
-  material_info: number[][][];
+  schema_version: number; // PAYLOAD_SCHEMA_VERSION in crates/core/src/migration.rs
+  material_info: OneMaterialInput[];
+  material_sources: string[]; // names of the columns of material_info, market last
   optimizer_plan?: number[];
+  treatment_plans: { name: string; plan: number[] }[];
+  choose_treatment: boolean;
   upgrade_info: OneUpgradeInput[];
   special_budget: number;
   special_state?: number[];
@@ -31,6 +35,7 @@ export interface Payload {
   min_resolution: number;
   num_threads: number;
   metric_type: number;
+  upgrade_attribution?: boolean; // histogram also fills upgrade_breakdown, defaults to true
   adv_cache: any;
 }
 
@@ -277,13 +282,25 @@ export function build_material_info(): OneMaterialInput[] {
   );
   // console.log()
   return ALL_LABELS[tier].map((_, index) => [
-    [0, 0],
-    [bound_budgets[index], leftover_price[index]],
-    [roster_mats_owned[index], tradable_mats_price[index]],
-    [
-      !enabled[index] || index == 5 ? 0 : tradable_mats_owned[index],
-      actual_mats_prices[index],
-    ], // disabled mats shouldn't be sold either, disregard tradable gold
+    { owned: 0, buy: 0, sell: 0, sellable: false },
+    {
+      owned: bound_budgets[index],
+      buy: leftover_price[index],
+      sell: 0,
+      sellable: false,
+    },
+    {
+      owned: roster_mats_owned[index],
+      buy: tradable_mats_price[index],
+      sell: leftover_price[index], // what the user says leftover bound mats are worth to them
+      sellable: leftover_price[index] > 0,
+    },
+    {
+      owned: !enabled[index] || index == 5 ? 0 : tradable_mats_owned[index], // disabled mats shouldn't be sold either, disregard tradable gold
+      buy: actual_mats_prices[index],
+      sell: tradable_mats_price[index],
+      sellable: true,
+    },
   ]);
 }
 
@@ -292,15 +309,22 @@ export function build_payload(override?: OptimizerOverride): Payload {
   const tier = active_profile.value.tier;
   // console.log(active_profile.value.optimizer_worker_bundle.result?.adv_cache);
   return {
+    schema_version: 3,
     material_info: build_material_info(),
+    material_sources: ["char_bound", "roster_bound", "tradable", "market"],
+    treatment_plans: [
+      { name: "tradable used as bound", plan: [0, 1, 1, 2] },
+      { name: "tradable sold", plan: [0, 1, 2, 3] },
+    ],
+    choose_treatment: false,
     optimizer_plan:
       // wasm_op == WasmOp.OptimizeAverage
       active_profile.value.optimizer_treatment_plan ===
       TreatmentPlan.TreatRosterAsBound
-        ? [0, 0, 2, 3]
+        ? [0, 0, 1, 3]
         : active_profile.value.optimizer_treatment_plan ===
             TreatmentPlan.TreatTradableAsBound
-          ? [0, 0, 0, 3]
+          ? [0, 0, 0, 1]
           : [0, 1, 2, 3], //this  shouldn't happen
     // : null,
     upgrade_info: keyed_to_array(
@@ -29,9 +29,18 @@ export interface HistogramOutputs {
   metrics_arr: number[];
 
   avg_breakdown: number[];
+  upgrade_breakdown: UpgradeAttribution[] | null; // null when payload.upgrade_attribution is false
 
   juice_info: any;
 }
+export interface UpgradeAttribution {
+  upgrade: number; // index into upgrade_arr
+  label: string;
+  expected_use: number[]; // [material type]
+  marginal_gold: number[]; // [treatment plan] gold saved by not doing this one, lines up with metrics_arr
+  gold_cost: number[]; // [treatment plan] marginal_gold scaled to add up to the total cost
+  share: number[]; // [treatment plan] gold_cost / total cost
+}
 export interface StateBundle {
   upgrade_arr: Upgrade[];
   special_state: number[];
@@ -69,20 +78,27 @@ self.addEventListener("message", async (ev) => {
 
   let result;
 
-  // if (wasm_op == WasmOp.EvaluateAverage) {
-  //     result = await EvaluateAverageWasm(payload)
-  // } else
-  if (wasm_op == WasmOp.OptimizeAverage) {
-    console.log(WasmOp[wasm_op], "Began", payload);
-    result = await OptimizeAverageWasm(payload);
-  } else if (wasm_op == WasmOp.Histogram) {
-    console.log(WasmOp[wasm_op], "Began", payload);
-    result = await HistogramWasm(payload);
-  } else //     if (wasm_op == WasmOp.Parser) {
-  //     result = await ParserWasm(payload)
-  // } else
-  {
-    return; // react dev tool shenanigans
+  try {
+    // if (wasm_op == WasmOp.EvaluateAverage) {
+    //     result = await EvaluateAverageWasm(payload)
+    // } else
+    if (wasm_op == WasmOp.OptimizeAverage) {
+      console.log(WasmOp[wasm_op], "Began", payload);
+      result = await OptimizeAverageWasm(payload);
+    } else if (wasm_op == WasmOp.Histogram) {
+      console.log(WasmOp[wasm_op], "Began", payload);
+      result = await HistogramWasm(payload);
+    } else //     if (wasm_op == WasmOp.Parser) {
+    //     result = await ParserWasm(payload)
+    // } else
+    {
+      return; // react dev tool shenanigans
+    }
+  } catch (error) {
+    // the wrappers throw a plain string when the payload doesn't fit the data
+    console.log(WasmOp[wasm_op], "failed", error);
+    self.postMessage({ type: "error", error: String(error) });
+    return;
   }
   console.log(
     WasmOp[wasm_op],
@@ -84,6 +84,16 @@ export function create_worker_bundle() {
         if (callback) {
           callback(result.value);
         }
+      } else if (e.data.type === "error") {
+        error.value = e.data.error;
+        status.value = "error";
+        if (cancel) {
+          worker.terminate();
+          worker = null;
+        }
+        if (callback) {
+          callback(null);
+        }
       } else {
         // 1 sec interval from rust's side
         if (e.data.state_bundle) {